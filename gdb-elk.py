@@ -1,3 +1,4 @@
+import json
 import subprocess
 
 
@@ -13,11 +14,20 @@ class AutoSym(gdb.Command):
             print("No inferior.")
             return
 
-        cmd = ["elk", "autosym", str(pid)]
-        lines = subprocess.check_output(cmd).decode("utf-8").split("\n")
+        cmd = ["elk", "autosym", "--format", "json", str(pid)]
+        objects = json.loads(subprocess.check_output(cmd))
 
-        for line in lines:
-            gdb.execute(line)
+        for obj in objects:
+            sections = {s["name"]: s["addr"] for s in obj["sections"]}
+            if ".text" not in sections:
+                continue
+            # stripped objects get their symbols from their debug file
+            path = obj["debug_file"] or obj["path"]
+            command = "add-symbol-file {} 0x{:x}".format(
+                json.dumps(path), sections.pop(".text"))
+            for name, addr in sections.items():
+                command += " -s {} 0x{:x}".format(name, addr)
+            gdb.execute(command)
 
 
 AutoSym()
@@ -42,13 +52,40 @@ class Dig(gdb.Command):
             print("No inferior.")
             return
 
-        cmd = ["elk", "dig", "--pid", str(pid), "--addr", str(addr)]
+        cmd = ["elk", "dig", "--pid", str(pid), "--addr", str(addr),
+               "--format", "json"]
+        report = json.loads(subprocess.check_output(cmd))
+        if report is None:
+            print("0x{:x} is not mapped".format(addr))
+            return
 
-        # note: `check_call` would print stdout directly, but this somehow
-        # breaks GDB TUI, so we print every line ourselves
-        lines = subprocess.check_output(cmd).decode("utf-8").split("\n")
+        mapping = report["mapping"]
+        obj = report["object"]
+
+        # for further commands, like `x/16gx $dig_start`
+        gdb.set_convenience_variable("dig_start", mapping["start"])
+        gdb.set_convenience_variable("dig_end", mapping["end"])
+        if obj is not None and obj["vaddr"] is not None:
+            gdb.set_convenience_variable("dig_vaddr", obj["vaddr"])
+
+        # one line for where the address is, the most precise we have...
+        where = mapping["source"] or mapping["kind"]
+        if obj is not None:
+            where = obj["path"]
+            if obj["section"] is not None:
+                where = "{}+0x{:x} in {}".format(
+                    obj["section"]["name"], obj["section"]["offset"], where)
+            if obj["symbols"]:
+                sym = obj["symbols"][0]
+                where = "{}+0x{:x}, {}".format(sym["name"], sym["offset"], where)
+        print("0x{:x}: {} [{}]".format(addr, where, mapping["perms"]))
+
+        # ...then one per (inlined) function, innermost first
+        for frame in [] if obj is None else obj["source"]:
+            loc = frame["location"]
+            print("    {} at {}".format(
+                frame["function"] or "??",
+                "??" if loc is None else "{}:{}".format(loc["file"], loc["line"])))
 
-        for line in lines:
-            print(line)
 
 Dig()
\ No newline at end of file
@@ -0,0 +1,33 @@
+// in `samples/inline.c`
+//
+// Fixtures for the DWARF tests, built twice from this directory:
+//
+//   gcc -g -gdwarf-4 -O1 -nostdlib -static -no-pie -fdebug-prefix-map=$PWD=. -o inline-dwarf4 inline.c
+//   gcc -g -gdwarf-5 -O1 -nostdlib -static -no-pie -fdebug-prefix-map=$PWD=. -o inline-dwarf5 inline.c
+//
+// `compute` starts with `square`, inlined into `sum_of_squares`, itself
+// inlined into `compute`.
+
+static inline __attribute__((always_inline)) int square(int x) {
+    return x * x;
+}
+
+static inline __attribute__((always_inline)) int sum_of_squares(int a, int b) {
+    return square(a) + square(b);
+}
+
+__attribute__((noinline)) int compute(int a, int b) {
+    return sum_of_squares(a, b) + 1;
+}
+
+void _start() {
+    int code = compute(2, 3);
+    __asm__ volatile (
+            " \
+            mov     %[code], %%edi \n\t\
+            mov     $60, %%rax \n\t\
+            syscall"
+            :
+            : [code] "r" (code)
+            );
+}
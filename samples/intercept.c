@@ -0,0 +1,40 @@
+// in `samples/intercept.c`
+//
+// Fixture for the `Process::intercept` test in `process.rs`, built from
+// this directory:
+//
+//   gcc -shared -fPIC -nostdlib -DGREET -o libgreet.so intercept.c
+//   gcc -shared -fPIC -nostdlib -Wl,--no-as-needed -Wl,-rpath,'$ORIGIN' \
+//       -o intercept.so intercept.c -L. -lgreet
+//
+// `intercept.so` calls `greet` from `libgreet.so` (a JUMP_SLOT), and refers
+// to `wave` by address, both in code (GLOB_DAT) and in a pointer in `.data`
+// (an absolute relocation). If one function did both, the linker would
+// call it through its GLOB_DAT slot, with no JUMP_SLOT.
+
+#ifdef GREET
+
+int greet(void) {
+    return 1;
+}
+
+int wave(void) {
+    return 2;
+}
+
+#else
+
+int greet(void);
+int wave(void);
+
+int call_greet(void) {
+    return greet();
+}
+
+int (*get_wave(void))(void) {
+    return wave;
+}
+
+int (*wave_ptr)(void) = wave;
+
+#endif
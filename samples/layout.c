@@ -0,0 +1,25 @@
+// in `samples/layout.c`
+//
+// Fixture for the `relocatable::layout` tests, built from this directory:
+//
+//   gcc -c -O1 -fPIC -fcommon -fno-asynchronous-unwind-tables -o layout.o layout.c
+//
+// A bit of every kind of section, a common symbol, and references to
+// things defined elsewhere: through the GOT, and through the PLT.
+
+extern int external_var;
+extern int external_fn(int x);
+
+// .data
+int counter = 42;
+// with -fcommon, this one's a common symbol, not in .bss
+int common_var[16];
+// .bss
+static int zeroed[32];
+// .rodata
+const char message[] = "hello";
+
+int layout_entry(int x) {
+    zeroed[x & 31] = external_var;
+    return external_fn(x) + counter + common_var[x & 15] + zeroed[0] + message[x & 3];
+}
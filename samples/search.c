@@ -0,0 +1,29 @@
+// in `samples/search.c`
+//
+// Fixtures for the library search order tests in `process.rs`. They're all
+// built from this file, from this directory:
+//
+//   mkdir -p search/rpath search/libpath search/runpath search/default
+//   for dir in rpath libpath runpath default; do
+//       gcc -shared -fPIC -nostdlib -o search/$dir/libleaf.so search.c
+//   done
+//   gcc -shared -fPIC -nostdlib -o search/rpath/libchain.so search.c
+//   gcc -shared -fPIC -nostdlib -Wl,--no-as-needed \
+//       -o search/rpath/libmid.so search.c -Lsearch/rpath -lchain
+//   gcc -shared -fPIC -nostdlib -Wl,--no-as-needed -Wl,--disable-new-dtags \
+//       -Wl,-rpath,'$ORIGIN/rpath' -o search/with-rpath.so search.c \
+//       -Lsearch/rpath -lleaf -lmid
+//   gcc -shared -fPIC -nostdlib -Wl,--no-as-needed -Wl,--enable-new-dtags \
+//       -Wl,-rpath,'$ORIGIN/runpath' -o search/with-runpath.so search.c \
+//       -Lsearch/runpath -lleaf
+//   gcc -shared -fPIC -nostdlib -Wl,--no-as-needed \
+//       -o search/plain.so search.c -Lsearch/default -lleaf
+//
+// `libleaf.so` is in every directory, so which copy gets picked says which
+// search path entry won. `libchain.so` is only needed by `libmid.so`, which
+// has no search path of its own: it's found through the RPATH of
+// `with-rpath.so`, which loaded `libmid.so`.
+
+int search_fixture(void) {
+    return 0;
+}
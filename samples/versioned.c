@@ -0,0 +1,25 @@
+// in `samples/versioned.c`
+//
+// Fixture for the `versions` tests, built from this directory:
+//
+//   gcc -shared -fPIC -nostdlib -Wl,--version-script=versioned.map -o libversioned.so.1 versioned.c -lc
+//
+// It defines `foo@VERS_1` (hidden), `foo@@VERS_2` and an unversioned
+// `bar`, and needs `puts@GLIBC_2.2.5` from libc.
+
+int puts(const char *s);
+
+int foo_v1(void) {
+    return 1;
+}
+
+int foo_v2(void) {
+    return puts("foo");
+}
+
+int bar(void) {
+    return 3;
+}
+
+__asm__(".symver foo_v1, foo@VERS_1");
+__asm__(".symver foo_v2, foo@@VERS_2");
@@ -0,0 +1,252 @@
+use std::fmt;
+
+use crate::process::AuxType;
+
+/// What a relocation does, regardless of how a given architecture
+/// numbers it. Each `Arch` maps its own relocation types onto these.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelKind {
+    /// Nothing to do
+    None,
+    /// Symbol value plus addend, word-sized
+    Absolute,
+    /// Load base plus addend
+    Relative,
+    /// Whatever the resolver at load base plus addend returns
+    IRelative,
+    /// Copy the symbol's contents from the object that defines it
+    Copy,
+    /// Symbol value, for a GOT entry
+    GlobDat,
+    /// Symbol value, for a PLT entry's GOT slot
+    JumpSlot,
+    /// Offset of the symbol from the thread pointer
+    TpOff,
+    /// Module ID of the object defining the symbol
+    DtpMod,
+    /// Symbol value plus addend minus the relocation's address, 32-bit
+    Pc32,
+    /// Like `Pc32`, but through a PLT stub if the symbol is external
+    Plt32,
+    /// Address of the symbol's GOT slot, relative to the relocation, 32-bit
+    GotPcRel,
+    /// Symbol value plus addend, zero-extended from 32 bits
+    Abs32,
+    /// Symbol value plus addend, sign-extended from 32 bits
+    Abs32S,
+}
+
+/// Everything the loader needs to know about the machine it's loading for.
+pub trait Arch: Sync + fmt::Debug {
+    /// A human-readable name, for error messages
+    fn name(&self) -> &'static str;
+
+    /// Size of a pointer, in bytes
+    fn word_size(&self) -> usize;
+
+    /// Translates a raw relocation type, returns `None` if we don't
+    /// support it.
+    fn rel_kind(&self, r#type: u32) -> Option<RelKind>;
+
+    /// How much room to leave for the thread control block
+    fn tcb_size(&self) -> usize;
+
+    /// Builds a "somewhat fake" thread control block header, to be
+    /// written at `tcb_addr`.
+    fn tcb_head(&self, tcb_addr: delf::Addr) -> Vec<u8>;
+
+    /// Auxiliary vectors we can forward from our own process
+    fn aux_types(&self) -> &'static [AuxType];
+
+    /// Code for a stub that jumps to whatever address is stored at
+    /// `slot_addr`, to be placed at `stub_addr`. At most
+    /// `relocatable::PLT_STUB_SIZE` bytes. Returns `None` if the slot is
+    /// out of the stub's reach.
+    fn plt_stub(&self, stub_addr: delf::Addr, slot_addr: delf::Addr) -> Option<Vec<u8>>;
+
+    /// Points the thread pointer register to `tcb_addr`
+    ///
+    /// # Safety
+    /// Anything that relies on our own thread-local storage is off-limits
+    /// after this.
+    unsafe fn set_thread_pointer(&self, tcb_addr: delf::Addr);
+
+    /// Sets up the stack with `stack` (one element per word, in order)
+    /// and jumps to `entry_point`.
+    ///
+    /// # Safety
+    /// This transfers control to whatever is at `entry_point`.
+    unsafe fn enter(&self, entry_point: delf::Addr, stack: &[u64]) -> !;
+
+    /// Reads a word at `addr`
+    ///
+    /// # Safety
+    /// `addr` must be mapped and readable.
+    unsafe fn read_word(&self, addr: delf::Addr) -> u64 {
+        *addr.as_ptr::<u64>()
+    }
+
+    /// Writes a word at `addr`
+    ///
+    /// # Safety
+    /// `addr` must be mapped and writable.
+    unsafe fn write_word(&self, addr: delf::Addr, value: u64) {
+        addr.set(value)
+    }
+}
+
+/// The architecture we run on, and the only one we load objects for
+pub static HOST: &dyn Arch = &X86_64;
+
+/// Picks the architecture an ELF object was built for, if we can load it.
+pub fn for_machine(machine: &delf::Machine) -> Option<&'static dyn Arch> {
+    match machine {
+        delf::Machine::X86_64 => Some(HOST),
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+pub struct X86_64;
+
+impl Arch for X86_64 {
+    fn name(&self) -> &'static str {
+        "x86-64"
+    }
+
+    fn word_size(&self) -> usize {
+        8
+    }
+
+    fn rel_kind(&self, r#type: u32) -> Option<RelKind> {
+        Some(match r#type {
+            0 => RelKind::None,
+            1 => RelKind::Absolute,
+            2 => RelKind::Pc32,
+            4 => RelKind::Plt32,
+            5 => RelKind::Copy,
+            6 => RelKind::GlobDat,
+            7 => RelKind::JumpSlot,
+            8 => RelKind::Relative,
+            // GOTPCREL
+            9 => RelKind::GotPcRel,
+            10 => RelKind::Abs32,
+            11 => RelKind::Abs32S,
+            16 => RelKind::DtpMod,
+            18 => RelKind::TpOff,
+            37 => RelKind::IRelative,
+            // GOTPCRELX and REX_GOTPCRELX: the linker is allowed to relax
+            // those, but going through the GOT is always correct.
+            41 | 42 => RelKind::GotPcRel,
+            _ => return None,
+        })
+    }
+
+    fn tcb_size(&self) -> usize {
+        704
+    }
+
+    fn tcb_head(&self, tcb_addr: delf::Addr) -> Vec<u8> {
+        let mut head = Vec::new();
+        head.extend(&tcb_addr.0.to_le_bytes()); // tcb
+        head.extend(&0_u64.to_le_bytes()); // dtv
+        head.extend(&tcb_addr.0.to_le_bytes()); // thread pointer
+        head.extend(&0_u32.to_le_bytes()); // multiple_threads
+        head.extend(&0_u32.to_le_bytes()); // gscope_flag
+        head.extend(&0_u64.to_le_bytes()); // sysinfo
+        head.extend(&0xDEADBEEF_u64.to_le_bytes()); // stack guard
+        head.extend(&0xFEEDFACE_u64.to_le_bytes()); // pointer guard
+        head
+    }
+
+    fn aux_types(&self) -> &'static [AuxType] {
+        &[
+            AuxType::ExecFd,
+            AuxType::PHdr,
+            AuxType::PhEnt,
+            AuxType::PhNum,
+            AuxType::PageSz,
+            AuxType::Base,
+            AuxType::Flags,
+            AuxType::Entry,
+            AuxType::NotElf,
+            AuxType::Uid,
+            AuxType::EUid,
+            AuxType::Gid,
+            AuxType::EGid,
+            AuxType::Platform,
+            AuxType::HwCap,
+            AuxType::ClkTck,
+            AuxType::Secure,
+            AuxType::BasePlatform,
+            AuxType::Random,
+            AuxType::HwCap2,
+            AuxType::ExecFn,
+            AuxType::SysInfo,
+            AuxType::SysInfoEHdr,
+        ]
+    }
+
+    fn plt_stub(&self, stub_addr: delf::Addr, slot_addr: delf::Addr) -> Option<Vec<u8>> {
+        // jmp QWORD PTR [rip+disp32]
+        const JMP_LEN: u64 = 6;
+        let disp = slot_addr.0.wrapping_sub(stub_addr.0 + JMP_LEN) as i64;
+        let disp = i32::try_from(disp).ok()?;
+
+        let mut code = vec![0xff, 0x25];
+        code.extend(&disp.to_le_bytes());
+        // pad with int3
+        code.resize(crate::relocatable::PLT_STUB_SIZE as usize, 0xcc);
+        Some(code)
+    }
+
+    #[inline(never)]
+    unsafe fn set_thread_pointer(&self, tcb_addr: delf::Addr) {
+        let syscall_number: u64 = 158;
+        let arch_set_fs: u64 = 0x1002;
+
+        use std::arch::asm;
+        asm!(
+            "syscall",
+            inout("rax") syscall_number => _,
+            in("rdi") arch_set_fs,
+            in("rsi") tcb_addr.0,
+            lateout("rcx") _, lateout("r11") _,
+        )
+    }
+
+    unsafe fn enter(&self, entry_point: delf::Addr, stack: &[u64]) -> ! {
+        jmp(entry_point.as_ptr(), stack.as_ptr(), stack.len())
+    }
+}
+
+#[allow(named_asm_labels)]
+#[inline(never)]
+unsafe fn jmp(entry_point: *const u8, stack_contents: *const u64, qword_count: usize) -> ! {
+    use std::arch::asm;
+    asm!(
+        // allocate (qword_count * 8) bytes
+        "mov {tmp}, {qword_count}",
+        "sal {tmp}, 3",
+        "sub rsp, {tmp}",
+
+        "l1:",
+        // start at i = (n-1)
+        "sub {qword_count}, 1",
+        // copy qwords to the stack
+        "mov {tmp}, QWORD PTR [{stack_contents}+{qword_count}*8]",
+        "mov QWORD PTR [rsp+{qword_count}*8], {tmp}",
+        // loop if i isn't zero, break otherwise
+        "test {qword_count}, {qword_count}",
+        "jnz l1",
+
+        "jmp {entry_point}",
+
+        entry_point = in(reg) entry_point,
+        stack_contents = in(reg) stack_contents,
+        qword_count = in(reg) qword_count,
+        tmp = out(reg) _,
+    );
+
+    asm!("ud2", options(noreturn));
+}
@@ -0,0 +1,264 @@
+//! The glibc rtld-audit interface (see `man 7 rtld-audit`). Audit
+//! libraries are loaded in *our* address space, with the host's
+//! `dlopen`, just like ld.so loads them in a separate namespace: they
+//! never see the program's symbols, only what we tell them.
+
+use std::{
+    cell::Cell,
+    ffi::{CStr, CString},
+    os::raw::{c_char, c_int, c_void},
+};
+
+use crate::linkmap::LinkMap;
+
+/// The audit interface version we implement
+const LAV_CURRENT: u32 = 2;
+
+/// `la_objopen` return flags: audit bindings to / from this object
+pub const LA_FLG_BINDTO: u32 = 0x01;
+pub const LA_FLG_BINDFROM: u32 = 0x02;
+
+/// `la_objsearch` flags: the name as it appears in DT_NEEDED, then where
+/// each candidate path came from
+pub const LA_SER_ORIG: u32 = 0x01;
+pub const LA_SER_LIBPATH: u32 = 0x02;
+pub const LA_SER_RUNPATH: u32 = 0x04;
+pub const LA_SER_DEFAULT: u32 = 0x40;
+
+/// `la_activity` flags
+pub const LA_ACT_CONSISTENT: u32 = 0;
+pub const LA_ACT_ADD: u32 = 1;
+
+/// We only ever have one namespace
+const LM_ID_BASE: i64 = 0;
+
+const RTLD_NOW: c_int = 2;
+
+#[link(name = "dl")]
+extern "C" {
+    fn dlopen(filename: *const c_char, flags: c_int) -> *mut c_void;
+    fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    fn dlerror() -> *const c_char;
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum AuditError {
+    #[error("could not load audit library {0}: {1}")]
+    Open(String, String),
+    #[error("audit library {0} doesn't export la_version")]
+    NoVersion(String),
+}
+
+/// `Elf64_Sym`, as passed to `la_symbind64`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Elf64Sym {
+    pub st_name: u32,
+    pub st_info: u8,
+    pub st_other: u8,
+    pub st_shndx: u16,
+    pub st_value: u64,
+    pub st_size: u64,
+}
+
+type VersionFn = unsafe extern "C" fn(u32) -> u32;
+type ObjSearchFn = unsafe extern "C" fn(*const c_char, *mut usize, u32) -> *mut c_char;
+type ObjOpenFn = unsafe extern "C" fn(*mut LinkMap, i64, *mut usize) -> u32;
+type SymBindFn = unsafe extern "C" fn(
+    *mut Elf64Sym,
+    u32,
+    *mut usize,
+    *mut usize,
+    *mut u32,
+    *const c_char,
+) -> usize;
+type CookieFn = unsafe extern "C" fn(*mut usize);
+type ActivityFn = unsafe extern "C" fn(*mut usize, u32);
+
+#[derive(Debug)]
+struct Auditor {
+    objsearch: Option<ObjSearchFn>,
+    objopen: Option<ObjOpenFn>,
+    symbind64: Option<SymBindFn>,
+    preinit: Option<CookieFn>,
+    activity: Option<ActivityFn>,
+}
+
+/// Per-object audit state: one cookie and one set of `la_objopen` flags
+/// per auditor. Auditors get pointers to the cookies, so they live in a
+/// box, and are cells because we hand them out from shared references.
+#[derive(Debug, Default)]
+pub struct ObjectAudit {
+    cookies: Box<[Cell<usize>]>,
+    flags: Vec<u32>,
+}
+
+impl ObjectAudit {
+    /// `None` for objects loaded before the auditor was
+    fn cookie(&self, auditor: usize) -> Option<*mut usize> {
+        self.cookies.get(auditor).map(Cell::as_ptr)
+    }
+
+    fn flag(&self, auditor: usize, flag: u32) -> bool {
+        self.flags
+            .get(auditor)
+            .is_some_and(|flags| flags & flag != 0)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Audit {
+    auditors: Vec<Auditor>,
+}
+
+impl Audit {
+    /// Loads every audit library in `paths`. Libraries whose `la_version`
+    /// returns a version we don't support are skipped, like glibc does.
+    pub fn load(paths: &[String]) -> Result<Self, AuditError> {
+        let mut auditors = Vec::new();
+        for path in paths {
+            let c_path = CString::new(path.as_str())
+                .map_err(|e| AuditError::Open(path.clone(), e.to_string()))?;
+            let handle = unsafe { dlopen(c_path.as_ptr(), RTLD_NOW) };
+            if handle.is_null() {
+                let message = unsafe { CStr::from_ptr(dlerror()) };
+                return Err(AuditError::Open(
+                    path.clone(),
+                    message.to_string_lossy().into_owned(),
+                ));
+            }
+
+            let sym = |name: &str| -> *mut c_void {
+                let name = CString::new(name).unwrap_or_default();
+                unsafe { dlsym(handle, name.as_ptr()) }
+            };
+            macro_rules! func {
+                ($name:expr, $type:ty) => {{
+                    let ptr = sym($name);
+                    if ptr.is_null() {
+                        None
+                    } else {
+                        Some(unsafe { std::mem::transmute::<*mut c_void, $type>(ptr) })
+                    }
+                }};
+            }
+
+            let version = func!("la_version", VersionFn)
+                .ok_or_else(|| AuditError::NoVersion(path.clone()))?;
+            match unsafe { version(LAV_CURRENT) } {
+                1..=LAV_CURRENT => {}
+                v => {
+                    eprintln!("Skipping audit library {} (version {})", path, v);
+                    continue;
+                }
+            }
+
+            auditors.push(Auditor {
+                objsearch: func!("la_objsearch", ObjSearchFn),
+                objopen: func!("la_objopen", ObjOpenFn),
+                symbind64: func!("la_symbind64", SymBindFn),
+                preinit: func!("la_preinit", CookieFn),
+                activity: func!("la_activity", ActivityFn),
+            });
+        }
+        Ok(Self { auditors })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.auditors.is_empty()
+    }
+
+    /// Lets auditors rewrite (or refuse, with `None`) a name we're about
+    /// to search for, or a path we're about to try. `requester` is the
+    /// object that needs it.
+    pub fn objsearch(&self, requester: &ObjectAudit, name: &str, flag: u32) -> Option<String> {
+        let mut name = name.to_string();
+        for (i, auditor) in self.auditors.iter().enumerate() {
+            if let (Some(objsearch), Some(cookie)) = (auditor.objsearch, requester.cookie(i)) {
+                let c_name = CString::new(name.as_str()).ok()?;
+                let res = unsafe { objsearch(c_name.as_ptr(), cookie, flag) };
+                if res.is_null() {
+                    return None;
+                }
+                name = unsafe { CStr::from_ptr(res) }
+                    .to_string_lossy()
+                    .into_owned();
+            }
+        }
+        Some(name)
+    }
+
+    /// Tells auditors about a freshly loaded object, returns its audit state
+    pub fn objopen(&self, map: &mut LinkMap) -> ObjectAudit {
+        let map: *mut LinkMap = map;
+        // like glibc, cookies start out as the link map's address
+        let audit = ObjectAudit {
+            cookies: (0..self.auditors.len())
+                .map(|_| Cell::new(map as usize))
+                .collect(),
+            flags: vec![LA_FLG_BINDTO | LA_FLG_BINDFROM; self.auditors.len()],
+        };
+        let flags = self
+            .auditors
+            .iter()
+            .zip(audit.cookies.iter())
+            .map(|(auditor, cookie)| match auditor.objopen {
+                Some(objopen) => unsafe { objopen(map, LM_ID_BASE, cookie.as_ptr()) },
+                None => LA_FLG_BINDTO | LA_FLG_BINDFROM,
+            })
+            .collect();
+        ObjectAudit { flags, ..audit }
+    }
+
+    /// Tells auditors a symbol from `definer` got bound in `referrer`.
+    /// They may return a different address to bind to, which we honor.
+    pub fn symbind(
+        &self,
+        referrer: &ObjectAudit,
+        definer: &ObjectAudit,
+        mut sym: Elf64Sym,
+        index: u32,
+        name: &[u8],
+    ) -> u64 {
+        let c_name = CString::new(name).unwrap_or_default();
+        for (i, auditor) in self.auditors.iter().enumerate() {
+            if !(referrer.flag(i, LA_FLG_BINDFROM) && definer.flag(i, LA_FLG_BINDTO)) {
+                continue;
+            }
+            let (symbind, refcook, defcook) =
+                match (auditor.symbind64, referrer.cookie(i), definer.cookie(i)) {
+                    (Some(f), Some(refcook), Some(defcook)) => (f, refcook, defcook),
+                    _ => continue,
+                };
+            let mut flags = 0;
+            let value = unsafe {
+                symbind(
+                    &mut sym,
+                    index,
+                    refcook,
+                    defcook,
+                    &mut flags,
+                    c_name.as_ptr(),
+                )
+            };
+            sym.st_value = value as u64;
+        }
+        sym.st_value
+    }
+
+    pub fn activity(&self, main: &ObjectAudit, flag: u32) {
+        for (i, auditor) in self.auditors.iter().enumerate() {
+            if let (Some(activity), Some(cookie)) = (auditor.activity, main.cookie(i)) {
+                unsafe { activity(cookie, flag) };
+            }
+        }
+    }
+
+    pub fn preinit(&self, main: &ObjectAudit) {
+        for (i, auditor) in self.auditors.iter().enumerate() {
+            if let (Some(preinit), Some(cookie)) = (auditor.preinit, main.cookie(i)) {
+                unsafe { preinit(cookie) };
+            }
+        }
+    }
+}
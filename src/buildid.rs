@@ -0,0 +1,36 @@
+//! The GNU build-id is a note that uniquely identifies a build of an
+//! object - usually a SHA-1 of its contents, computed by the linker.
+
+/// `NT_GNU_BUILD_ID`
+const NT_GNU_BUILD_ID: u32 = 3;
+
+/// Reads the build-id from the `.note.gnu.build-id` section, if any
+pub fn build_id<I: AsRef<[u8]>>(file: &delf::File<I>) -> Option<Vec<u8>> {
+    let sh = file
+        .section_headers
+        .iter()
+        .find(|sh| file.shstrtab_entry(sh.name) == b".note.gnu.build-id")?;
+    let start: usize = sh.offset.into();
+    let note = file
+        .input
+        .as_ref()
+        .get(start..start.checked_add(usize::from(sh.size))?)?;
+
+    // Elf_Nhdr: namesz, descsz, type, then the name and the
+    // description, each padded to 4 bytes.
+    let u32_at = |i: usize| -> Option<u32> {
+        let b = note.get(i..i + 4)?;
+        Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    };
+    let (namesz, descsz, typ) = (u32_at(0)? as usize, u32_at(4)? as usize, u32_at(8)?);
+    if typ != NT_GNU_BUILD_ID || note.get(12..12 + namesz)? != b"GNU\0" {
+        return None;
+    }
+    let desc = 12 + namesz.next_multiple_of(4);
+    note.get(desc..desc + descsz).map(|id| id.to_vec())
+}
+
+/// Formats a build-id the way `file` and gdb do
+pub fn to_hex(id: &[u8]) -> String {
+    id.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,206 @@
+//! Separate debug info: distributions strip their binaries, and ship the
+//! symbols and DWARF in a different file, found either by build-id or by
+//! the name in the `.gnu_debuglink` section. We search the same places
+//! gdb does, and only trust a candidate if its build-id or CRC32 matches.
+
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use crate::{buildid, dig, filemap::FileMap};
+
+/// Where distributions install debug files
+const DEFAULT_DEBUG_DIR: &str = "/usr/lib/debug";
+
+/// Where to look for debug files
+#[derive(Debug)]
+pub struct Search {
+    dirs: Vec<PathBuf>,
+}
+
+/// A debug file we found, and checked
+pub struct DebugFile {
+    pub path: PathBuf,
+    pub file: delf::File<FileMap>,
+}
+
+/// What a debug file must match to belong to an object
+struct Expected {
+    build_id: Option<Vec<u8>>,
+    crc: Option<u32>,
+}
+
+impl Search {
+    /// Searches `dirs` first, then the default debug directory
+    pub fn new<P: AsRef<Path>>(dirs: &[P]) -> Self {
+        let mut dirs: Vec<PathBuf> = dirs.iter().map(|dir| dir.as_ref().into()).collect();
+        dirs.push(DEFAULT_DEBUG_DIR.into());
+        Self { dirs }
+    }
+
+    /// Finds the debug file for the object at `path`, if it has one. An
+    /// object that has no build-id and no debuglink can't be matched, so
+    /// we don't try.
+    pub fn find<I: AsRef<[u8]>>(&self, path: &Path, file: &delf::File<I>) -> Option<DebugFile> {
+        let debuglink = debuglink(file);
+        let expected = Expected {
+            build_id: buildid::build_id(file),
+            crc: debuglink.as_ref().map(|(_, crc)| *crc),
+        };
+        if expected.build_id.is_none() && expected.crc.is_none() {
+            return None;
+        }
+
+        let mut candidates = Vec::new();
+        if let Some(id) = expected.build_id.as_deref().filter(|id| id.len() > 1) {
+            let hex = buildid::to_hex(id);
+            for dir in &self.dirs {
+                candidates.push(
+                    dir.join(".build-id")
+                        .join(&hex[..2])
+                        .join(format!("{}.debug", &hex[2..])),
+                );
+            }
+        }
+        let dir = path.parent().unwrap_or_else(|| Path::new("/"));
+        let relative_dir = dir.strip_prefix("/").unwrap_or(dir);
+        if let Some((name, _)) = &debuglink {
+            candidates.push(dir.join(name));
+            candidates.push(dir.join(".debug").join(name));
+            for debug_dir in &self.dirs {
+                candidates.push(debug_dir.join(relative_dir).join(name));
+            }
+        }
+        // some distributions mirror the whole path, with no extension
+        let relative_path = path.strip_prefix("/").unwrap_or(path);
+        for debug_dir in &self.dirs {
+            candidates.push(debug_dir.join(relative_path));
+        }
+
+        candidates
+            .into_iter()
+            // the debuglink usually names a file right next to the object -
+            // sometimes the object itself, if it wasn't stripped
+            .filter(|candidate| candidate != path)
+            .find_map(|candidate| {
+                let contents = FileMap::new(&File::open(&candidate).ok()?).ok()?;
+                let file = expected.check(contents)?;
+                Some(DebugFile {
+                    path: candidate,
+                    file,
+                })
+            })
+    }
+}
+
+impl Expected {
+    /// Parses `contents` if it's the debug file we expect. Most
+    /// candidates don't exist, and the ones that do but fail to parse
+    /// aren't worth more than a line on stderr.
+    fn check<I: AsRef<[u8]>>(&self, contents: I) -> Option<delf::File<I>> {
+        match &self.build_id {
+            // an object with a build-id always passes it on to its
+            // debug file, so that settles it either way
+            Some(id) => {
+                let file = dig::parse_or_eprint_error(contents)?;
+                (buildid::build_id(&file).as_ref() == Some(id)).then_some(file)
+            }
+            None if self.crc == Some(crc32(contents.as_ref())) => {
+                dig::parse_or_eprint_error(contents)
+            }
+            None => None,
+        }
+    }
+}
+
+/// Reads the `.gnu_debuglink` section: a file name, padded to 4 bytes,
+/// then the CRC32 of the debug file.
+fn debuglink<I: AsRef<[u8]>>(file: &delf::File<I>) -> Option<(PathBuf, u32)> {
+    let sh = file
+        .section_headers
+        .iter()
+        .find(|sh| file.shstrtab_entry(sh.name) == b".gnu_debuglink")?;
+    let start: usize = sh.offset.into();
+    let section = file
+        .input
+        .as_ref()
+        .get(start..start.checked_add(usize::from(sh.size))?)?;
+
+    let len = section.iter().position(|&b| b == 0)?;
+    let name = std::str::from_utf8(&section[..len]).ok()?;
+    let crc_offset = (len + 1).next_multiple_of(4);
+    let crc = section.get(crc_offset..crc_offset + 4)?;
+    Some((
+        name.into(),
+        u32::from_le_bytes([crc[0], crc[1], crc[2], crc[3]]),
+    ))
+}
+
+/// The CRC-32 (IEEE 802.3) that `objcopy --add-gnu-debuglink` computes
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_check_value() {
+        // the standard check value for CRC-32/ISO-HDLC
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn debuglink_crc_must_match() {
+        let contents = std::fs::read("samples/nolibc").unwrap();
+        let crc = crc32(&contents);
+
+        let expected = Expected {
+            build_id: None,
+            crc: Some(crc),
+        };
+        assert!(expected.check(contents.as_slice()).is_some());
+
+        let expected = Expected {
+            build_id: None,
+            crc: Some(!crc),
+        };
+        assert!(expected.check(contents.as_slice()).is_none());
+    }
+
+    #[test]
+    fn build_id_must_match() {
+        let contents = std::fs::read("samples/nolibc").unwrap();
+        let file = delf::File::parse_or_print_error(contents.as_slice()).unwrap();
+        let id = buildid::build_id(&file).unwrap();
+
+        let expected = Expected {
+            build_id: Some(id.clone()),
+            crc: None,
+        };
+        assert!(expected.check(contents.as_slice()).is_some());
+
+        // the build-id settles it, even if the CRC would match
+        let mut other = id;
+        other[0] ^= 0xff;
+        let expected = Expected {
+            build_id: Some(other),
+            crc: Some(crc32(&contents)),
+        };
+        assert!(expected.check(contents.as_slice()).is_none());
+    }
+}
@@ -0,0 +1,239 @@
+//! Undefined symbols are the most common way for loading to fail, so we
+//! try hard to say why: who needed the symbol, where we looked, and what
+//! we found instead.
+
+use std::{fmt, path::PathBuf};
+
+/// How many near-misses we show, at most
+const MAX_NEAR_MISSES: usize = 5;
+
+#[derive(Debug, Clone)]
+pub struct UndefinedSymbol {
+    /// The object that needs the symbol
+    pub object: PathBuf,
+    pub name: String,
+    /// The version the reference needs, if any
+    pub version: Option<String>,
+    /// Name of the relocation type, or its number if we don't know it
+    pub relocation: String,
+    pub offset: delf::Addr,
+    /// Objects we looked in, in scope order
+    pub searched: Vec<PathBuf>,
+    /// Similar names we did find, with the object that defines them
+    pub near_misses: Vec<(String, PathBuf)>,
+}
+
+impl UndefinedSymbol {
+    /// Picks the candidates that look like typos of (or other versions of)
+    /// the symbol we wanted. `candidates` should be the defined symbols in
+    /// scope, as (name, version, object).
+    pub fn find_near_misses<'a>(
+        &mut self,
+        candidates: impl Iterator<Item = (String, Option<&'a str>, &'a PathBuf)>,
+    ) {
+        let threshold = (self.name.len() / 4).clamp(1, 3);
+        let mut misses: Vec<_> = candidates
+            .filter_map(|(name, version, object)| {
+                let distance = strsim::levenshtein(&self.name, &name);
+                if distance > threshold {
+                    return None;
+                }
+                let display = match version {
+                    Some(version) => format!("{}@{}", name, version),
+                    None => name,
+                };
+                Some((distance, display, object.clone()))
+            })
+            .collect();
+        // a distance of 0 means a version mismatch, that's the best hint,
+        // so it sorts first.
+        misses.sort();
+        misses.dedup();
+        self.near_misses = misses
+            .into_iter()
+            .take(MAX_NEAR_MISSES)
+            .map(|(_, name, object)| (name, object))
+            .collect();
+    }
+}
+
+impl fmt::Display for UndefinedSymbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "undefined symbol {}", self.name)?;
+        if let Some(version) = &self.version {
+            write!(f, "@{}", version)?;
+        }
+        if let Some(demangled) = demangle(&self.name) {
+            write!(f, " ({})", demangled)?;
+        }
+        writeln!(
+            f,
+            ", needed by {:?} ({} relocation at offset {})",
+            self.object, self.relocation, self.offset
+        )?;
+
+        writeln!(f, "  searched, in order:")?;
+        for path in &self.searched {
+            writeln!(f, "    {:?}", path)?;
+        }
+        if !self.near_misses.is_empty() {
+            writeln!(f, "  did you mean:")?;
+            for (name, object) in &self.near_misses {
+                writeln!(f, "    {} in {:?}", name, object)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Demangles C++ (Itanium ABI) and Rust symbol names. Returns `None` for
+/// names that aren't mangled, like C symbols.
+pub fn demangle(name: &str) -> Option<String> {
+    if let Ok(demangled) = rustc_demangle::try_demangle(name) {
+        // the alternate format leaves out the hash
+        return Some(format!("{:#}", demangled));
+    }
+    cpp_demangle::Symbol::new(name)
+        .ok()
+        .map(|symbol| symbol.to_string())
+}
+
+/// Adds `symbol` to `symbols`, unless it's already there: an object
+/// that needs a symbol in several places only gets reported once.
+pub fn push_unique(symbols: &mut Vec<UndefinedSymbol>, symbol: UndefinedSymbol) {
+    let seen = symbols
+        .iter()
+        .any(|s| s.object == symbol.object && s.name == symbol.name && s.version == symbol.version);
+    if !seen {
+        symbols.push(symbol);
+    }
+}
+
+/// Formats a bunch of `UndefinedSymbol` for `RelocationError`
+pub fn display_all(symbols: &[UndefinedSymbol]) -> String {
+    let mut out = format!("{} undefined symbol(s)\n", symbols.len());
+    for symbol in symbols {
+        out.push_str(&symbol.to_string());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn undefined(object: &str, name: &str) -> UndefinedSymbol {
+        UndefinedSymbol {
+            object: object.into(),
+            name: name.into(),
+            version: None,
+            relocation: "R_X86_64_JUMP_SLOT".into(),
+            offset: delf::Addr(0x4018),
+            searched: vec![object.into(), "/usr/lib/libc.so.6".into()],
+            near_misses: vec![],
+        }
+    }
+
+    #[test]
+    fn near_misses() {
+        let libc = PathBuf::from("/usr/lib/libc.so.6");
+        let libfoo = PathBuf::from("/opt/foo/libfoo.so");
+        let candidates = vec![
+            ("mempcpy".to_string(), None, &libc),
+            ("memcpy".to_string(), Some("GLIBC_2.2.5"), &libc),
+            ("memcpy".to_string(), Some("GLIBC_2.14"), &libc),
+            ("mempcpy".to_string(), None, &libc),
+            // two edits away, too far for a name this short
+            ("memcmp".to_string(), None, &libc),
+            ("memcpz".to_string(), None, &libfoo),
+            ("strcpy".to_string(), None, &libc),
+        ];
+        let mut sym = undefined("/tmp/hello", "memcpy");
+        sym.version = Some("GLIBC_2.99".into());
+        sym.find_near_misses(candidates.into_iter());
+
+        // other versions of the symbol first, then typos, each once
+        let expected = [
+            ("memcpy@GLIBC_2.14", &libc),
+            ("memcpy@GLIBC_2.2.5", &libc),
+            ("memcpz", &libfoo),
+            ("mempcpy", &libc),
+        ];
+        let found: Vec<_> = sym
+            .near_misses
+            .iter()
+            .map(|(name, object)| (name.as_str(), object))
+            .collect();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn near_misses_are_capped() {
+        let libc = PathBuf::from("/usr/lib/libc.so.6");
+        let mut sym = undefined("/tmp/hello", "puts");
+        sym.find_near_misses(
+            ["putc", "putw", "outs", "pits", "pots", "puto", "putz"]
+                .iter()
+                .map(|name| (name.to_string(), None, &libc)),
+        );
+        assert_eq!(sym.near_misses.len(), MAX_NEAR_MISSES);
+    }
+
+    #[test]
+    fn demangles() {
+        // legacy Rust mangling is valid Itanium too, but the C++ demangler
+        // would keep the hash as a path component
+        assert_eq!(
+            demangle("_ZN4core3fmt5write17h0123456789abcdefE").as_deref(),
+            Some("core::fmt::write")
+        );
+        assert_eq!(
+            demangle("_RNvCs1234_7mycrate3foo").as_deref(),
+            Some("mycrate::foo")
+        );
+        assert_eq!(demangle("_ZN3foo3barEi").as_deref(), Some("foo::bar(int)"));
+        assert_eq!(demangle("puts"), None);
+    }
+
+    #[test]
+    fn collects_each_symbol_once() {
+        let mut symbols = Vec::new();
+        for (object, name) in [
+            ("/tmp/hello", "puts"),
+            ("/tmp/hello", "prinft"),
+            // needed again, from another relocation
+            ("/tmp/hello", "puts"),
+            ("/tmp/libfoo.so", "puts"),
+        ] {
+            push_unique(&mut symbols, undefined(object, name));
+        }
+        let mut versioned = undefined("/tmp/hello", "puts");
+        versioned.version = Some("GLIBC_2.2.5".into());
+        push_unique(&mut symbols, versioned);
+
+        let found: Vec<_> = symbols
+            .iter()
+            .map(|s| {
+                (
+                    s.object.to_str().unwrap(),
+                    s.name.as_str(),
+                    s.version.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("/tmp/hello", "puts", None),
+                ("/tmp/hello", "prinft", None),
+                ("/tmp/libfoo.so", "puts", None),
+                ("/tmp/hello", "puts", Some("GLIBC_2.2.5")),
+            ]
+        );
+
+        let report = display_all(&symbols);
+        assert!(report.starts_with("4 undefined symbol(s)\n"));
+        assert_eq!(report.matches("undefined symbol puts").count(), 3);
+        assert!(report.contains("undefined symbol prinft, needed by \"/tmp/hello\""));
+    }
+}
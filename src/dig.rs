@@ -0,0 +1,635 @@
+//! `elk dig`: everything we can find out about an address in another
+//! process, and optionally, what's there. The lookup builds a `Report`,
+//! which gets printed either for humans, or as JSON for tools (like
+//! `gdb-elk.py`) - so the two can never disagree.
+
+use std::{error::Error, fmt, path::Path, str::FromStr};
+
+use serde::Serialize;
+
+use crate::{
+    buildid, debuginfo, dwarf,
+    procfs::{self, ProcessMemory},
+};
+
+/// Longest C string we'll read
+const MAX_CSTR_LEN: usize = 4096;
+
+/// Mappings start on a page boundary, segments don't have to
+const PAGE_SIZE: u64 = 0x1000;
+
+/// A byte count, printed with a human-friendly unit
+pub struct Size(pub delf::Addr);
+
+impl fmt::Debug for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const KIB: u64 = 1024;
+        const MIB: u64 = 1024 * KIB;
+
+        let x = (self.0).0;
+        #[allow(overlapping_range_endpoints)]
+        #[allow(clippy::clippy::match_overlapping_arm)]
+        match x {
+            0..=KIB => write!(f, "{} B", x),
+            KIB..=MIB => write!(f, "{} KiB", x / KIB),
+            _ => write!(f, "{} MiB", x / MIB),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub addr: u64,
+    pub mapping: Mapping,
+    /// Only for file-backed mappings of ELF objects
+    pub object: Option<Object>,
+    /// Only if we were asked to read memory
+    pub contents: Option<Contents>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Mapping {
+    pub start: u64,
+    pub end: u64,
+    /// Size of the mapping, in bytes
+    pub size: u64,
+    /// As in `/proc/:pid/maps`, like "r-xp"
+    pub perms: String,
+    /// "file", "memfd", "anonymous", "anon_named", "anon_shmem", "stack",
+    /// "vvar", "vsyscall" or "special"
+    pub kind: &'static str,
+    /// The file path, the name of a named or special mapping (without
+    /// brackets), or the thread ID for a thread's stack
+    pub source: Option<String>,
+    /// Whether the file (or memfd) has been deleted
+    pub deleted: bool,
+    /// The source, for humans
+    #[serde(skip)]
+    pub display: String,
+    /// Offset of the mapping in its file
+    pub offset: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Object {
+    pub path: String,
+    /// The address as seen in the object file, like `objdump` shows it.
+    /// Missing if the address isn't part of any segment.
+    pub vaddr: Option<u64>,
+    pub section: Option<Section>,
+    pub symbols: Vec<Symbol>,
+    /// Why we couldn't look at the symbol table, if we couldn't
+    pub symbols_error: Option<String>,
+    /// Where the section, symbols and source locations came from, if not
+    /// from the object itself
+    pub debug_file: Option<String>,
+    /// Source locations from DWARF, innermost (inlined) frame first
+    pub source: Vec<dwarf::Frame>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Section {
+    pub name: String,
+    pub offset: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Symbol {
+    pub name: String,
+    pub offset: u64,
+}
+
+/// What's at the address, as far as we could read it
+#[derive(Debug, Default, Serialize)]
+pub struct Contents {
+    /// Raw bytes, for `--dump`. Shorter than asked for if that would go
+    /// past the end of the mapping, or if the end isn't readable.
+    #[serde(serialize_with = "serialize_hex")]
+    pub dump: Option<Vec<u8>>,
+    pub value: Option<Value>,
+    /// Where `value` points, when following pointers
+    pub pointee: Option<Box<Report>>,
+    pub errors: Vec<String>,
+}
+
+fn serialize_hex<S: serde::Serializer>(
+    bytes: &Option<Vec<u8>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match bytes {
+        Some(bytes) => serializer.serialize_str(&buildid::to_hex(bytes)),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+pub enum Value {
+    U64(u64),
+    Cstr(String),
+    Ptr(u64),
+}
+
+/// What to read the address as, for `--as`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadAs {
+    U64,
+    Cstr,
+    Ptr,
+}
+
+impl FromStr for ReadAs {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "u64" => Ok(Self::U64),
+            "cstr" => Ok(Self::Cstr),
+            "ptr" => Ok(Self::Ptr),
+            _ => Err(format!("unknown type {:?}, expected u64, cstr or ptr", s)),
+        }
+    }
+}
+
+/// What to read from the process's memory, if anything
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReadOptions {
+    /// How many bytes to hexdump
+    pub dump: Option<usize>,
+    pub read_as: Option<ReadAs>,
+    /// How many pointers to follow. Implies reading as `ptr`.
+    pub follow: u32,
+}
+
+impl ReadOptions {
+    pub fn reads_memory(&self) -> bool {
+        self.dump.is_some() || self.read_as.is_some() || self.follow > 0
+    }
+
+    /// Rejects options that contradict each other
+    pub fn check(&self) -> Result<(), &'static str> {
+        let not_ptr = matches!(self.read_as, Some(ReadAs::U64 | ReadAs::Cstr));
+        if not_ptr && self.follow > 0 {
+            return Err("--follow reads pointers, it can't be combined with --as u64 or cstr");
+        }
+        Ok(())
+    }
+}
+
+/// Finds what `addr` is, in a process with those `mappings`, and reads
+/// what's there according to `opts`. Returns `None` if it isn't mapped at
+/// all. `mem` is only needed if `opts` reads anything.
+pub fn dig(
+    mappings: &[procfs::Mapping<'_>],
+    addr: delf::Addr,
+    mem: Option<&ProcessMemory>,
+    opts: &ReadOptions,
+    debug: &debuginfo::Search,
+) -> Result<Option<Report>, Box<dyn Error>> {
+    let report = match locate(mappings, addr, debug)? {
+        Some(report) => report,
+        None => return Ok(None),
+    };
+    let mem = match mem {
+        Some(mem) if opts.reads_memory() => mem,
+        _ => return Ok(Some(report)),
+    };
+
+    let mut contents = Contents::default();
+    if let Some(len) = opts.dump {
+        // the next mapping may well be readable, but it's something else
+        let len = len.min((report.mapping.end - addr.0) as usize);
+        match mem.read(addr.0, len) {
+            Ok(bytes) => contents.dump = Some(bytes),
+            Err(e) => contents
+                .errors
+                .push(format!("could not read memory: {}", e)),
+        }
+    }
+
+    let read_as = if opts.follow > 0 {
+        Some(ReadAs::Ptr)
+    } else {
+        opts.read_as
+    };
+    let value = match read_as {
+        None => Ok(None),
+        Some(ReadAs::U64) => mem.read_u64(addr.0).map(|v| Some(Value::U64(v))),
+        Some(ReadAs::Ptr) => mem.read_u64(addr.0).map(|v| Some(Value::Ptr(v))),
+        Some(ReadAs::Cstr) => mem
+            .read_cstr(addr.0, MAX_CSTR_LEN)
+            .map(|s| Some(Value::Cstr(String::from_utf8_lossy(&s).into_owned()))),
+    };
+    match value {
+        Ok(value) => contents.value = value,
+        Err(e) => contents
+            .errors
+            .push(format!("could not read memory: {}", e)),
+    }
+
+    if let (Some(Value::Ptr(ptr)), true) = (&contents.value, opts.follow > 0) {
+        let opts = ReadOptions {
+            follow: opts.follow - 1,
+            ..*opts
+        };
+        match dig(mappings, delf::Addr(*ptr), Some(mem), &opts, debug)? {
+            Some(pointee) => contents.pointee = Some(Box::new(pointee)),
+            None => contents
+                .errors
+                .push(format!("pointer target {:#x} is not mapped", ptr)),
+        }
+    }
+
+    Ok(Some(Report {
+        contents: Some(contents),
+        ..report
+    }))
+}
+
+/// Finds where `addr` is, in a process with those `mappings`
+fn locate(
+    mappings: &[procfs::Mapping<'_>],
+    addr: delf::Addr,
+    debug: &debuginfo::Search,
+) -> Result<Option<Report>, Box<dyn Error>> {
+    let mapping = match mappings.iter().find(|m| m.addr_range.contains(&addr)) {
+        Some(mapping) => mapping,
+        None => return Ok(None),
+    };
+    let (kind, source) = match &mapping.source {
+        procfs::Source::Anonymous => ("anonymous", None),
+        procfs::Source::AnonNamed(name) => ("anon_named", Some(name.to_string())),
+        procfs::Source::AnonShmem(name) => ("anon_shmem", Some(name.to_string())),
+        procfs::Source::Stack(tid) => ("stack", tid.map(|tid| tid.to_string())),
+        procfs::Source::Vvar => ("vvar", None),
+        procfs::Source::Vsyscall => ("vsyscall", None),
+        procfs::Source::Special(name) => ("special", Some(name.to_string())),
+        procfs::Source::Memfd(name) => ("memfd", Some(name.to_string())),
+        procfs::Source::File(path) => ("file", Some(path.to_string())),
+    };
+    let report = Report {
+        addr: addr.0,
+        mapping: Mapping {
+            start: mapping.addr_range.start.0,
+            end: mapping.addr_range.end.0,
+            size: (mapping.addr_range.end - mapping.addr_range.start).0,
+            perms: format!("{:?}", mapping.perms),
+            kind,
+            source,
+            deleted: mapping.deleted,
+            display: mapping.source.to_string(),
+            offset: mapping.offset.0,
+        },
+        object: None,
+        contents: None,
+    };
+
+    // we've used this pattern a bunch of times already, but in case you don't
+    // see the point: this avoids deep indentation. If we didn't use that, we'd
+    // soon find ourselves several levels deep into `if let` statements, whereas
+    // really, if we get a `None` or an `Err`, we just want to bail out early
+    // and gracefully.
+    let path = match &mapping.source {
+        procfs::Source::File(path) => path,
+        // if it's not a file mapping, there's nothing more to say
+        _ => return Ok(Some(report)),
+    };
+
+    let contents = std::fs::read(path.as_ref())?;
+    let file = match parse_or_eprint_error(&contents) {
+        Some(x) => x,
+        // if we couldn't parse the file, a message was printed,
+        // and we can bail out
+        _ => return Ok(Some(report)),
+    };
+    let mut object = Object {
+        path: path.to_string(),
+        vaddr: None,
+        section: None,
+        symbols: Vec::new(),
+        symbols_error: None,
+        debug_file: None,
+        source: Vec::new(),
+    };
+
+    let offset = addr + mapping.offset - mapping.addr_range.start;
+
+    // Segments (loader view, `delf::ProgramHeader` type) determine what parts
+    // of the ELF file get mapped where, so we try to determine which
+    // segment this mapping corresponds to.
+    let segment = match file
+        .program_headers
+        .iter()
+        .find(|ph| ph.file_range().contains(&offset))
+    {
+        Some(s) => s,
+        None => {
+            return Ok(Some(Report {
+                object: Some(object),
+                ..report
+            }))
+        }
+    };
+
+    // This is the main thing I wanted `elk dig` to do - display
+    // the virtual address *for this ELF object*, so that it matches
+    // up with the output from `objdump` and `readelf`
+    let vaddr = offset + segment.vaddr - segment.offset;
+    object.vaddr = Some(vaddr.0);
+
+    // Stripped binaries don't have much more to tell, but their debug
+    // file, if it's installed, does.
+    match debug.find(Path::new(&**path), &file) {
+        Some(debug_file) => {
+            object.debug_file = Some(debug_file.path.display().to_string());
+            describe(&debug_file.file, vaddr, &mut object);
+        }
+        None => describe(&file, vaddr, &mut object),
+    }
+
+    Ok(Some(Report {
+        object: Some(object),
+        ..report
+    }))
+}
+
+/// Fills in what `file` (the object, or its debug file) knows about
+/// `vaddr`: section, symbols, source lines.
+fn describe<I: AsRef<[u8]>>(file: &delf::File<I>, vaddr: delf::Addr, object: &mut Object) {
+    // But we can go a bit further: we can find to which section
+    // this corresponds, and show *where* in this section the
+    // dug address was.
+    let section = match file
+        .section_headers
+        .iter()
+        .find(|sh| sh.mem_range().contains(&vaddr))
+    {
+        Some(s) => s,
+        None => return,
+    };
+    object.section = Some(Section {
+        name: String::from_utf8_lossy(file.shstrtab_entry(section.name)).into_owned(),
+        offset: (vaddr - section.addr).0,
+    });
+
+    // And, even further, we can try to map it to a symbol. This is all
+    // stuff GDB does in its `info addr 0xABCD` command, but isn't it
+    // satisfying to re-implement it ourselves?
+    match file.read_symtab_entries() {
+        Ok(syms) => {
+            for sym in &syms {
+                let sym_range = sym.value..(sym.value + delf::Addr(sym.size));
+                // the first check is for zero-sized symbols, since `sym_range`
+                // ends up being a 0-sized range.
+                if sym.value == vaddr || sym_range.contains(&vaddr) {
+                    object.symbols.push(Symbol {
+                        name: String::from_utf8_lossy(file.strtab_entry(sym.name)).into_owned(),
+                        offset: (vaddr - sym.value).0,
+                    });
+                }
+            }
+        }
+        Err(e) => object.symbols_error = Some(format!("{:?}", e)),
+    }
+
+    // Finally, if the object has debug info, that tells us which line of
+    // which source file this is, and what got inlined where.
+    object.source = dwarf::frames(&dwarf::Sections::new(file), vaddr.0);
+}
+
+/// How far the object `mapping` comes from was moved from the addresses it
+/// was linked at, going by the `PT_LOAD` segment `mapping` is part of.
+/// Segments can start mid-page, in which case the mapping starts a bit
+/// before them.
+pub fn load_bias<I: AsRef<[u8]>>(
+    file: &delf::File<I>,
+    mapping: &procfs::Mapping<'_>,
+) -> Option<delf::Addr> {
+    let offset = mapping.offset.0;
+    let candidates: Vec<_> = file
+        .program_headers
+        .iter()
+        .filter(|ph| ph.r#type == delf::SegmentType::Load)
+        .filter(|ph| {
+            let start = ph.offset.0 - ph.offset.0 % PAGE_SIZE;
+            (start..ph.offset.0 + ph.filesz.0).contains(&offset)
+        })
+        .collect();
+    // two segments can share a page of the file, the permissions tell
+    // them apart (except for RELRO, which is read-only by now)
+    let segment = candidates
+        .iter()
+        .find(|ph| {
+            ph.flags.contains(delf::SegmentFlag::Execute) == mapping.perms.x
+                && ph.flags.contains(delf::SegmentFlag::Write) == mapping.perms.w
+        })
+        .or_else(|| candidates.first())?;
+    let vaddr = segment
+        .vaddr
+        .0
+        .wrapping_add(offset)
+        .wrapping_sub(segment.offset.0);
+    Some(delf::Addr(mapping.addr_range.start.0.wrapping_sub(vaddr)))
+}
+
+/// Like `delf::File::parse_or_print_error`, except whatever it prints goes
+/// to stderr: stdout may be JSON for another program to read.
+pub fn parse_or_eprint_error<I: AsRef<[u8]>>(input: I) -> Option<delf::File<I>> {
+    use std::{io::Write, os::raw::c_int};
+
+    extern "C" {
+        // from libc
+        fn dup(fd: c_int) -> c_int;
+        fn dup2(fd: c_int, fd2: c_int) -> c_int;
+        fn close(fd: c_int) -> c_int;
+    }
+
+    // anything we buffered before belongs on stdout
+    std::io::stdout().flush().ok();
+    let saved = unsafe { dup(1) };
+    if saved < 0 || unsafe { dup2(2, 1) } < 0 {
+        return delf::File::parse_or_print_error(input);
+    }
+    let file = delf::File::parse_or_print_error(input);
+    std::io::stdout().flush().ok();
+    unsafe {
+        dup2(saved, 1);
+        close(saved);
+    }
+    file
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mapping = &self.mapping;
+        write!(f, "Mapped {} from {}", mapping.perms, mapping.display)?;
+        if mapping.deleted {
+            write!(f, " (deleted)")?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "(Map range: {:?}..{:?}, {:?} total)",
+            delf::Addr(mapping.start),
+            delf::Addr(mapping.end),
+            Size(delf::Addr(mapping.size))
+        )?;
+
+        if let Some(object) = &self.object {
+            write!(f, "{}", object)?;
+        }
+        if let Some(contents) = &self.contents {
+            if let Some(dump) = &contents.dump {
+                hexdump(f, self.addr, dump)?;
+            }
+            write!(f, "{}", contents)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(vaddr) = self.vaddr {
+            writeln!(f, "Object virtual address: {:?}", delf::Addr(vaddr))?;
+        }
+        if let Some(debug_file) = &self.debug_file {
+            writeln!(f, "Debug info from {}", debug_file)?;
+        }
+        if let Some(section) = &self.section {
+            writeln!(
+                f,
+                "At section {:?} + {} (0x{:x})",
+                section.name, section.offset, section.offset
+            )?;
+        }
+        for sym in &self.symbols {
+            writeln!(
+                f,
+                "At symbol {:?} + {} (0x{:x})",
+                sym.name, sym.offset, sym.offset
+            )?;
+        }
+        if let Some(e) = &self.symbols_error {
+            writeln!(f, "Could not read syms: {}", e)?;
+        }
+        for (i, frame) in self.source.iter().enumerate() {
+            let function = frame.function.as_deref().unwrap_or("??");
+            if i == 0 {
+                write!(f, "In {}", function)?;
+            } else {
+                write!(f, "  inlined into {}", function)?;
+            }
+            match &frame.location {
+                Some(loc) => writeln!(f, " at {}:{}:{}", loc.file, loc.line, loc.column)?,
+                None => writeln!(f, " at ??")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Contents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for e in &self.errors {
+            writeln!(f, "Error: {}", e)?;
+        }
+        match &self.value {
+            Some(Value::U64(v)) => writeln!(f, "Value as u64: {} (0x{:x})", v, v)?,
+            Some(Value::Cstr(s)) => writeln!(f, "Value as C string: {:?}", s)?,
+            Some(Value::Ptr(p)) => writeln!(f, "Pointer to {:?}", delf::Addr(*p))?,
+            None => {}
+        }
+        if let Some(pointee) = &self.pointee {
+            // nested reports are indented, so each level stands out
+            for line in pointee.to_string().lines() {
+                writeln!(f, "    {}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes `bytes` (found at `addr`) like `xxd` would
+fn hexdump(f: &mut fmt::Formatter<'_>, addr: u64, bytes: &[u8]) -> fmt::Result {
+    for (i, line) in bytes.chunks(16).enumerate() {
+        write!(f, "{:016x}: ", addr + i as u64 * 16)?;
+        for j in 0..16 {
+            match line.get(j) {
+                Some(b) => write!(f, "{:02x}", b)?,
+                None => write!(f, "  ")?,
+            }
+            if j % 2 == 1 {
+                write!(f, " ")?;
+            }
+        }
+        let ascii: String = line
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        writeln!(f, " {}", ascii)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// How `samples/puts` gets mapped with a base of 0x555555554000: its
+    /// code segment starts on a page, its data segment (at offset 0x2db8,
+    /// vaddr 0x3db8) doesn't, and its RELRO part has been made read-only.
+    const MAPS: &str = "\
+555555554000-555555555000 r--p 00000000 fd:01 1234                       /samples/puts
+555555555000-555555556000 r-xp 00001000 fd:01 1234                       /samples/puts
+555555556000-555555557000 r--p 00002000 fd:01 1234                       /samples/puts
+555555557000-555555558000 r--p 00002000 fd:01 1234                       /samples/puts
+555555558000-555555559000 rw-p 00003000 fd:01 1234                       /samples/puts
+";
+
+    #[test]
+    fn load_bias_of_each_segment() {
+        let input = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/samples/puts")).unwrap();
+        let file = delf::File::parse_or_print_error(&input[..]).unwrap();
+        let (_, mappings) = procfs::mappings(MAPS).unwrap();
+
+        let base = delf::Addr(0x5555_5555_4000);
+        let biases: Vec<_> = mappings
+            .iter()
+            .map(|mapping| load_bias(&file, mapping))
+            .collect();
+        // the RELRO page is the only one we get wrong: it looks just like
+        // the read-only segment at the same offset
+        assert_eq!(
+            biases,
+            [
+                Some(base),
+                Some(base),
+                Some(base),
+                Some(base + delf::Addr(0x1000)),
+                Some(base),
+            ]
+        );
+
+        // what autosym tells gdb, from the code mapping: .data and .bss are
+        // in the data segment, at their address plus the base
+        let bias = load_bias(&file, &mappings[1]).unwrap();
+        let section = |name: &[u8]| {
+            let sh = file
+                .section_headers
+                .iter()
+                .find(|sh| file.shstrtab_entry(sh.name) == name)
+                .unwrap();
+            bias + sh.addr
+        };
+        assert_eq!(section(b".text"), delf::Addr(0x5555_5555_5060));
+        assert_eq!(section(b".data"), delf::Addr(0x5555_5555_8000));
+        assert_eq!(section(b".bss"), delf::Addr(0x5555_5555_8010));
+    }
+}
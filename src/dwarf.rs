@@ -0,0 +1,1261 @@
+//! Just enough DWARF to turn an address into source locations, like
+//! `addr2line -i`: the line number program from `.debug_line` (versions 2
+//! to 5), and the tree of subprograms and inlined calls from `.debug_info`
+//! (versions 4 and 5).
+//!
+//! Everything is read lazily, and for a single address at a time: we walk
+//! the whole thing on every lookup, which is plenty fast for `elk dig`.
+//! Malformed data makes lookups come up empty, it never panics.
+//! Compressed sections (`SHF_COMPRESSED`) aren't supported.
+
+use std::{collections::HashMap, ops::Range};
+
+use serde::Serialize;
+
+use crate::diagnostics;
+
+// tags
+const DW_TAG_SUBPROGRAM: u64 = 0x2e;
+const DW_TAG_INLINED_SUBROUTINE: u64 = 0x1d;
+
+// attributes
+const DW_AT_NAME: u64 = 0x03;
+const DW_AT_STMT_LIST: u64 = 0x10;
+const DW_AT_LOW_PC: u64 = 0x11;
+const DW_AT_HIGH_PC: u64 = 0x12;
+const DW_AT_COMP_DIR: u64 = 0x1b;
+const DW_AT_ABSTRACT_ORIGIN: u64 = 0x31;
+const DW_AT_SPECIFICATION: u64 = 0x47;
+const DW_AT_RANGES: u64 = 0x55;
+const DW_AT_CALL_COLUMN: u64 = 0x57;
+const DW_AT_CALL_FILE: u64 = 0x58;
+const DW_AT_CALL_LINE: u64 = 0x59;
+const DW_AT_LINKAGE_NAME: u64 = 0x6e;
+const DW_AT_STR_OFFSETS_BASE: u64 = 0x72;
+const DW_AT_ADDR_BASE: u64 = 0x73;
+const DW_AT_RNGLISTS_BASE: u64 = 0x74;
+const DW_AT_MIPS_LINKAGE_NAME: u64 = 0x2007;
+
+// forms
+const DW_FORM_ADDR: u64 = 0x01;
+const DW_FORM_BLOCK2: u64 = 0x03;
+const DW_FORM_BLOCK4: u64 = 0x04;
+const DW_FORM_DATA2: u64 = 0x05;
+const DW_FORM_DATA4: u64 = 0x06;
+const DW_FORM_DATA8: u64 = 0x07;
+const DW_FORM_STRING: u64 = 0x08;
+const DW_FORM_BLOCK: u64 = 0x09;
+const DW_FORM_BLOCK1: u64 = 0x0a;
+const DW_FORM_DATA1: u64 = 0x0b;
+const DW_FORM_FLAG: u64 = 0x0c;
+const DW_FORM_SDATA: u64 = 0x0d;
+const DW_FORM_STRP: u64 = 0x0e;
+const DW_FORM_UDATA: u64 = 0x0f;
+const DW_FORM_REF_ADDR: u64 = 0x10;
+const DW_FORM_REF1: u64 = 0x11;
+const DW_FORM_REF2: u64 = 0x12;
+const DW_FORM_REF4: u64 = 0x13;
+const DW_FORM_REF8: u64 = 0x14;
+const DW_FORM_REF_UDATA: u64 = 0x15;
+const DW_FORM_INDIRECT: u64 = 0x16;
+const DW_FORM_SEC_OFFSET: u64 = 0x17;
+const DW_FORM_EXPRLOC: u64 = 0x18;
+const DW_FORM_FLAG_PRESENT: u64 = 0x19;
+const DW_FORM_STRX: u64 = 0x1a;
+const DW_FORM_ADDRX: u64 = 0x1b;
+const DW_FORM_REF_SUP4: u64 = 0x1c;
+const DW_FORM_STRP_SUP: u64 = 0x1d;
+const DW_FORM_DATA16: u64 = 0x1e;
+const DW_FORM_LINE_STRP: u64 = 0x1f;
+const DW_FORM_REF_SIG8: u64 = 0x20;
+const DW_FORM_IMPLICIT_CONST: u64 = 0x21;
+const DW_FORM_LOCLISTX: u64 = 0x22;
+const DW_FORM_RNGLISTX: u64 = 0x23;
+const DW_FORM_REF_SUP8: u64 = 0x24;
+const DW_FORM_STRX1: u64 = 0x25;
+const DW_FORM_STRX2: u64 = 0x26;
+const DW_FORM_STRX3: u64 = 0x27;
+const DW_FORM_STRX4: u64 = 0x28;
+const DW_FORM_ADDRX1: u64 = 0x29;
+const DW_FORM_ADDRX2: u64 = 0x2a;
+const DW_FORM_ADDRX3: u64 = 0x2b;
+const DW_FORM_ADDRX4: u64 = 0x2c;
+
+// unit types (DWARF 5)
+const DW_UT_COMPILE: u8 = 0x01;
+const DW_UT_PARTIAL: u8 = 0x03;
+
+// line number program content types (DWARF 5)
+const DW_LNCT_PATH: u64 = 0x1;
+const DW_LNCT_DIRECTORY_INDEX: u64 = 0x2;
+
+// standard opcodes
+const DW_LNS_COPY: u8 = 1;
+const DW_LNS_ADVANCE_PC: u8 = 2;
+const DW_LNS_ADVANCE_LINE: u8 = 3;
+const DW_LNS_SET_FILE: u8 = 4;
+const DW_LNS_SET_COLUMN: u8 = 5;
+const DW_LNS_NEGATE_STMT: u8 = 6;
+const DW_LNS_SET_BASIC_BLOCK: u8 = 7;
+const DW_LNS_CONST_ADD_PC: u8 = 8;
+const DW_LNS_FIXED_ADVANCE_PC: u8 = 9;
+const DW_LNS_SET_PROLOGUE_END: u8 = 10;
+const DW_LNS_SET_EPILOGUE_BEGIN: u8 = 11;
+
+// extended opcodes
+const DW_LNE_END_SEQUENCE: u8 = 1;
+const DW_LNE_SET_ADDRESS: u8 = 2;
+
+// range list entries (DWARF 5)
+const DW_RLE_END_OF_LIST: u8 = 0;
+const DW_RLE_BASE_ADDRESSX: u8 = 1;
+const DW_RLE_STARTX_ENDX: u8 = 2;
+const DW_RLE_STARTX_LENGTH: u8 = 3;
+const DW_RLE_OFFSET_PAIR: u8 = 4;
+const DW_RLE_BASE_ADDRESS: u8 = 5;
+const DW_RLE_START_END: u8 = 6;
+const DW_RLE_START_LENGTH: u8 = 7;
+
+/// How deep we follow `DW_AT_abstract_origin` and `DW_AT_specification`
+const MAX_ORIGIN_DEPTH: usize = 8;
+
+/// The debug sections of an object. Missing ones are empty.
+pub struct Sections<'a> {
+    info: &'a [u8],
+    abbrev: &'a [u8],
+    line: &'a [u8],
+    line_str: &'a [u8],
+    str: &'a [u8],
+    str_offsets: &'a [u8],
+    addr: &'a [u8],
+    ranges: &'a [u8],
+    rnglists: &'a [u8],
+}
+
+impl<'a> Sections<'a> {
+    pub fn new<I: AsRef<[u8]>>(file: &'a delf::File<I>) -> Self {
+        let section = |name: &[u8]| -> &'a [u8] {
+            file.section_headers
+                .iter()
+                .find(|sh| file.shstrtab_entry(sh.name) == name)
+                .and_then(|sh| {
+                    let start: usize = sh.offset.into();
+                    file.input
+                        .as_ref()
+                        .get(start..start.checked_add(usize::from(sh.size))?)
+                })
+                .unwrap_or_default()
+        };
+        Self {
+            info: section(b".debug_info"),
+            abbrev: section(b".debug_abbrev"),
+            line: section(b".debug_line"),
+            line_str: section(b".debug_line_str"),
+            str: section(b".debug_str"),
+            str_offsets: section(b".debug_str_offsets"),
+            addr: section(b".debug_addr"),
+            ranges: section(b".debug_ranges"),
+            rnglists: section(b".debug_rnglists"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Location {
+    pub file: String,
+    pub line: u64,
+    /// 0 when the compiler didn't say
+    pub column: u64,
+}
+
+/// A function, and where we are in it
+#[derive(Debug, Clone, Serialize)]
+pub struct Frame {
+    pub function: Option<String>,
+    pub location: Option<Location>,
+}
+
+/// Where `addr` (an address in the object file, not in memory) comes
+/// from. With inlining, that's several frames: the innermost inlined
+/// function first, then each function it was inlined into, with the
+/// location of the call. Empty if we know nothing about `addr`.
+pub fn frames(sections: &Sections<'_>, addr: u64) -> Vec<Frame> {
+    let unit = units(sections).find(|unit| unit.contains(sections, addr));
+    let unit = match unit {
+        Some(unit) => unit,
+        // no (usable) .debug_info: all we can do is the line table
+        None => {
+            return line_location(sections, None, addr)
+                .map(|location| Frame {
+                    function: None,
+                    location: Some(location),
+                })
+                .into_iter()
+                .collect()
+        }
+    };
+
+    let program = unit
+        .stmt_list
+        .and_then(|offset| LineProgram::parse(sections, offset as usize, unit.comp_dir));
+    let mut location = line_location(sections, program.as_ref(), addr);
+
+    let chain = unit.inline_chain(sections, addr).unwrap_or_default();
+    if chain.is_empty() {
+        return location
+            .map(|location| Frame {
+                function: None,
+                location: Some(location),
+            })
+            .into_iter()
+            .collect();
+    }
+
+    let mut frames = Vec::new();
+    for die in chain.iter().rev() {
+        frames.push(Frame {
+            function: function_name(sections, &unit, die, 0),
+            location: location.take(),
+        });
+        // the call site is in the function we were inlined into
+        if die.tag == DW_TAG_INLINED_SUBROUTINE {
+            let call_line = die.attr(DW_AT_CALL_LINE).and_then(Value::udata);
+            let file = die
+                .attr(DW_AT_CALL_FILE)
+                .and_then(Value::udata)
+                .and_then(|index| program.as_ref()?.file_name(index));
+            location = match (file, call_line) {
+                (Some(file), Some(line)) => Some(Location {
+                    file,
+                    line,
+                    column: die
+                        .attr(DW_AT_CALL_COLUMN)
+                        .and_then(Value::udata)
+                        .unwrap_or(0),
+                }),
+                _ => None,
+            };
+        }
+    }
+    frames
+}
+
+/// Looks `addr` up in `program`, or in every line program if we don't
+/// know which one it's in.
+fn line_location(
+    sections: &Sections<'_>,
+    program: Option<&LineProgram<'_>>,
+    addr: u64,
+) -> Option<Location> {
+    if let Some(program) = program {
+        return program.find(addr);
+    }
+    let mut offset = 0;
+    while offset < sections.line.len() {
+        let program = LineProgram::parse(sections, offset, None)?;
+        if let Some(location) = program.find(addr) {
+            return Some(location);
+        }
+        offset = program.end;
+    }
+    None
+}
+
+/// A cursor over a section. Every read returns `None` past the end.
+#[derive(Clone)]
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Self { data, pos }
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(bytes)
+    }
+
+    fn skip(&mut self, n: u64) -> Option<()> {
+        self.bytes(usize::try_from(n).ok()?).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.uint(2).map(|v| v as u16)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.uint(4).map(|v| v as u32)
+    }
+
+    /// A little-endian unsigned integer of `size` bytes
+    fn uint(&mut self, size: usize) -> Option<u64> {
+        if size > 8 {
+            return None;
+        }
+        let bytes = self.bytes(size)?;
+        let mut buf = [0u8; 8];
+        buf[..size].copy_from_slice(bytes);
+        Some(u64::from_le_bytes(buf))
+    }
+
+    fn uleb(&mut self) -> Option<u64> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= u64::from(byte & 0x7f) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+    }
+
+    fn sleb(&mut self) -> Option<i64> {
+        let mut value = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= i64::from(byte & 0x7f) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                return Some(value);
+            }
+        }
+    }
+
+    fn cstr(&mut self) -> Option<&'a [u8]> {
+        let rest = self.data.get(self.pos..)?;
+        let len = rest.iter().position(|&b| b == 0)?;
+        self.pos += len + 1;
+        Some(&rest[..len])
+    }
+
+    /// A unit length, and whether it's 64-bit DWARF (whose section
+    /// offsets are 8 bytes instead of 4).
+    fn unit_length(&mut self) -> Option<(u64, usize)> {
+        match self.u32()? {
+            0xffff_ffff => Some((self.uint(8)?, 8)),
+            len => Some((len.into(), 4)),
+        }
+    }
+}
+
+/// A NUL-terminated string at `offset` in a string section
+fn str_at(section: &[u8], offset: u64) -> Option<&[u8]> {
+    Reader::new(section, usize::try_from(offset).ok()?).cstr()
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FileEntry<'a> {
+    name: &'a [u8],
+    dir: u64,
+}
+
+/// A line number program, from `.debug_line`
+struct LineProgram<'a> {
+    /// All of `.debug_line`
+    data: &'a [u8],
+    version: u16,
+    min_inst_len: u8,
+    line_base: i8,
+    line_range: u8,
+    opcode_base: u8,
+    standard_opcode_lengths: &'a [u8],
+    dirs: Vec<&'a [u8]>,
+    files: Vec<FileEntry<'a>>,
+    /// The compilation directory, for relative paths in DWARF 4 and
+    /// earlier (DWARF 5 has it as directory 0)
+    comp_dir: Option<&'a [u8]>,
+    /// Where the opcodes start
+    program: usize,
+    /// Where the next program starts
+    end: usize,
+}
+
+impl<'a> LineProgram<'a> {
+    fn parse(sections: &Sections<'a>, offset: usize, comp_dir: Option<&'a [u8]>) -> Option<Self> {
+        let mut r = Reader::new(sections.line, offset);
+        let (unit_length, offset_size) = r.unit_length()?;
+        let end = r.pos.checked_add(usize::try_from(unit_length).ok()?)?;
+        let mut r = Reader::new(sections.line.get(..end)?, r.pos);
+
+        let version = r.u16()?;
+        if !(2..=5).contains(&version) {
+            return None;
+        }
+        if version >= 5 {
+            // address_size and segment_selector_size
+            r.skip(2)?;
+        }
+        let header_length = r.uint(offset_size)?;
+        let program = r.pos.checked_add(usize::try_from(header_length).ok()?)?;
+        let min_inst_len = r.u8()?;
+        if version >= 4 {
+            // maximum_operations_per_instruction, only for VLIW
+            r.u8()?;
+        }
+        // default_is_stmt
+        r.u8()?;
+        let line_base = r.u8()? as i8;
+        let line_range = r.u8()?;
+        let opcode_base = r.u8()?;
+        let standard_opcode_lengths = r.bytes(usize::from(opcode_base.checked_sub(1)?))?;
+        if line_range == 0 {
+            return None;
+        }
+
+        let mut dirs = Vec::new();
+        let mut files = Vec::new();
+        if version >= 5 {
+            // directories are entries too, they just don't have one
+            let entries = |r: &mut Reader<'a>| -> Option<Vec<FileEntry<'a>>> {
+                let format_count = r.u8()?;
+                let formats = (0..format_count)
+                    .map(|_| Some((r.uleb()?, r.uleb()?)))
+                    .collect::<Option<Vec<_>>>()?;
+                let count = r.uleb()?;
+                (0..count)
+                    .map(|_| {
+                        let mut entry = FileEntry { name: &[], dir: 0 };
+                        for &(content, form) in &formats {
+                            let value = read_line_form(sections, r, form, offset_size)?;
+                            match (content, value) {
+                                (DW_LNCT_PATH, LineValue::Str(s)) => entry.name = s,
+                                (DW_LNCT_DIRECTORY_INDEX, LineValue::Num(n)) => entry.dir = n,
+                                _ => {}
+                            }
+                        }
+                        Some(entry)
+                    })
+                    .collect()
+            };
+            dirs = entries(&mut r)?
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect();
+            files = entries(&mut r)?;
+        } else {
+            loop {
+                match r.cstr()? {
+                    b"" => break,
+                    dir => dirs.push(dir),
+                }
+            }
+            loop {
+                match r.cstr()? {
+                    b"" => break,
+                    name => {
+                        let dir = r.uleb()?;
+                        // modification time and length
+                        r.uleb()?;
+                        r.uleb()?;
+                        files.push(FileEntry { name, dir });
+                    }
+                }
+            }
+        }
+
+        Some(Self {
+            data: sections.line,
+            version,
+            min_inst_len,
+            line_base,
+            line_range,
+            opcode_base,
+            standard_opcode_lengths,
+            dirs,
+            files,
+            comp_dir,
+            program,
+            end,
+        })
+    }
+
+    /// The path of file number `index`. Files are numbered from 1 before
+    /// DWARF 5, and from 0 since.
+    fn file_name(&self, index: u64) -> Option<String> {
+        let index = if self.version >= 5 {
+            index
+        } else {
+            index.checked_sub(1)?
+        };
+        let file = self.files.get(usize::try_from(index).ok()?)?;
+        // directory 0 is the compilation directory, which DWARF 4 doesn't
+        // list.
+        let dir = if self.version >= 5 {
+            self.dirs.get(file.dir as usize).copied()
+        } else if file.dir == 0 {
+            self.comp_dir
+        } else {
+            self.dirs.get(file.dir as usize - 1).copied()
+        };
+
+        let name = String::from_utf8_lossy(file.name);
+        let path = match dir {
+            Some(dir) if !name.starts_with('/') && !dir.is_empty() => {
+                format!("{}/{}", String::from_utf8_lossy(dir), name)
+            }
+            _ => name.into_owned(),
+        };
+        Some(path)
+    }
+
+    /// Runs the program until it finds the row that covers `addr`
+    fn find(&self, addr: u64) -> Option<Location> {
+        #[derive(Clone, Copy)]
+        struct Row {
+            address: u64,
+            file: u64,
+            line: u64,
+            column: u64,
+        }
+
+        let mut r = Reader::new(self.data.get(..self.end)?, self.program);
+        let initial = Row {
+            address: 0,
+            file: 1,
+            line: 1,
+            column: 0,
+        };
+        let mut row = initial;
+        // the last row we emitted in the current sequence
+        let mut prev: Option<Row> = None;
+
+        let min_inst_len = u64::from(self.min_inst_len);
+        let line_range = u64::from(self.line_range);
+
+        // if `addr` is between the previous row and this one, the
+        // previous row is where it's from.
+        macro_rules! check {
+            () => {
+                if let Some(prev) = prev {
+                    if (prev.address..row.address).contains(&addr) {
+                        return Some(Location {
+                            file: self.file_name(prev.file)?,
+                            line: prev.line,
+                            column: prev.column,
+                        });
+                    }
+                }
+            };
+        }
+        macro_rules! emit {
+            () => {
+                check!();
+                prev = Some(row);
+            };
+        }
+
+        while !r.at_end() {
+            let opcode = r.u8()?;
+            if opcode >= self.opcode_base {
+                // special opcode: advance the address and line, and emit
+                let adjusted = u64::from(opcode - self.opcode_base);
+                row.address = row
+                    .address
+                    .wrapping_add(adjusted / line_range * min_inst_len);
+                row.line = row.line.wrapping_add(
+                    (i64::from(self.line_base) + (adjusted % line_range) as i64) as u64,
+                );
+                emit!();
+                continue;
+            }
+            match opcode {
+                0 => {
+                    let len = r.uleb()?;
+                    if len == 0 {
+                        continue;
+                    }
+                    let sub = r.u8()?;
+                    match sub {
+                        DW_LNE_END_SEQUENCE => {
+                            // the end of a sequence is one past its last
+                            // instruction, nothing comes from there
+                            check!();
+                            row = initial;
+                            prev = None;
+                        }
+                        DW_LNE_SET_ADDRESS => {
+                            row.address = r.uint(usize::try_from(len - 1).ok()?)?;
+                        }
+                        _ => r.skip(len - 1)?,
+                    }
+                }
+                DW_LNS_COPY => {
+                    emit!();
+                }
+                DW_LNS_ADVANCE_PC => {
+                    row.address = row.address.wrapping_add(r.uleb()? * min_inst_len);
+                }
+                DW_LNS_ADVANCE_LINE => {
+                    row.line = row.line.wrapping_add(r.sleb()? as u64);
+                }
+                DW_LNS_SET_FILE => row.file = r.uleb()?,
+                DW_LNS_SET_COLUMN => row.column = r.uleb()?,
+                // we don't care about statement boundaries or basic blocks:
+                // every row counts.
+                DW_LNS_NEGATE_STMT
+                | DW_LNS_SET_BASIC_BLOCK
+                | DW_LNS_SET_PROLOGUE_END
+                | DW_LNS_SET_EPILOGUE_BEGIN => {}
+                DW_LNS_CONST_ADD_PC => {
+                    let adjusted = u64::from(255 - self.opcode_base);
+                    row.address = row
+                        .address
+                        .wrapping_add(adjusted / line_range * min_inst_len);
+                }
+                DW_LNS_FIXED_ADVANCE_PC => {
+                    row.address = row.address.wrapping_add(r.u16()?.into());
+                }
+                // opcodes we don't know: skip their (ULEB128) arguments
+                _ => {
+                    let args = self.standard_opcode_lengths[usize::from(opcode - 1)];
+                    for _ in 0..args {
+                        r.uleb()?;
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// A value in a DWARF 5 line program header
+enum LineValue<'a> {
+    Str(&'a [u8]),
+    Num(u64),
+    Other,
+}
+
+fn read_line_form<'a>(
+    sections: &Sections<'a>,
+    r: &mut Reader<'a>,
+    form: u64,
+    offset_size: usize,
+) -> Option<LineValue<'a>> {
+    Some(match form {
+        DW_FORM_STRING => LineValue::Str(r.cstr()?),
+        DW_FORM_LINE_STRP => LineValue::Str(str_at(sections.line_str, r.uint(offset_size)?)?),
+        DW_FORM_STRP => LineValue::Str(str_at(sections.str, r.uint(offset_size)?)?),
+        DW_FORM_UDATA => LineValue::Num(r.uleb()?),
+        DW_FORM_DATA1 => LineValue::Num(r.uint(1)?),
+        DW_FORM_DATA2 => LineValue::Num(r.uint(2)?),
+        DW_FORM_DATA4 => LineValue::Num(r.uint(4)?),
+        DW_FORM_DATA8 => LineValue::Num(r.uint(8)?),
+        DW_FORM_DATA16 => {
+            r.skip(16)?;
+            LineValue::Other
+        }
+        DW_FORM_BLOCK => {
+            let len = r.uleb()?;
+            r.skip(len)?;
+            LineValue::Other
+        }
+        _ => return None,
+    })
+}
+
+/// An attribute value, as much as we care to decode it
+#[derive(Debug, Clone, Copy)]
+enum Value<'a> {
+    Addr(u64),
+    /// An index into `.debug_addr`
+    Addrx(u64),
+    Udata(u64),
+    Sdata(i64),
+    Str(&'a [u8]),
+    /// An index into `.debug_str_offsets`
+    Strx(u64),
+    /// An offset into `.debug_info`
+    Ref(u64),
+    SecOffset(u64),
+    /// An index into the unit's range list offsets
+    Rnglistx(u64),
+    Other,
+}
+
+impl Value<'_> {
+    fn udata(self) -> Option<u64> {
+        match self {
+            Self::Udata(v) => Some(v),
+            Self::Sdata(v) => u64::try_from(v).ok(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct AttrSpec {
+    name: u64,
+    form: u64,
+    implicit_const: i64,
+}
+
+#[derive(Debug)]
+struct Abbrev {
+    tag: u64,
+    has_children: bool,
+    attrs: Vec<AttrSpec>,
+}
+
+/// A debugging information entry
+#[derive(Debug, Clone)]
+struct Die<'a> {
+    tag: u64,
+    has_children: bool,
+    attrs: Vec<(u64, Value<'a>)>,
+}
+
+impl<'a> Die<'a> {
+    fn attr(&self, name: u64) -> Option<Value<'a>> {
+        self.attrs
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|&(_, value)| value)
+    }
+}
+
+/// A compilation unit from `.debug_info`, and what its root DIE tells us
+/// about the rest.
+struct Unit<'a> {
+    version: u16,
+    address_size: usize,
+    offset_size: usize,
+    abbrevs: HashMap<u64, Abbrev>,
+    /// Where the unit header starts
+    offset: usize,
+    /// Where the root DIE starts
+    dies: usize,
+    /// Where the next unit starts
+    end: usize,
+    root: Die<'a>,
+    /// Base address for range lists
+    base_address: u64,
+    str_offsets_base: u64,
+    addr_base: u64,
+    rnglists_base: u64,
+    stmt_list: Option<u64>,
+    comp_dir: Option<&'a [u8]>,
+}
+
+/// Every compilation (or partial) unit, in order. Type units are skipped.
+fn units<'a, 's>(sections: &'s Sections<'a>) -> impl Iterator<Item = Unit<'a>> + 's {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        while offset < sections.info.len() {
+            let (unit, end) = Unit::parse(sections, offset)?;
+            offset = end;
+            if unit.is_some() {
+                return unit;
+            }
+        }
+        None
+    })
+}
+
+impl<'a> Unit<'a> {
+    /// Returns the unit at `offset` (if it's one we can read), and where
+    /// the next one starts.
+    fn parse(sections: &Sections<'a>, offset: usize) -> Option<(Option<Self>, usize)> {
+        let mut r = Reader::new(sections.info, offset);
+        let (unit_length, offset_size) = r.unit_length()?;
+        let end = r.pos.checked_add(usize::try_from(unit_length).ok()?)?;
+        let mut r = Reader::new(sections.info.get(..end)?, r.pos);
+
+        let version = r.u16()?;
+        let (address_size, abbrev_offset) = match version {
+            2..=4 => {
+                let abbrev_offset = r.uint(offset_size)?;
+                (r.u8()?, abbrev_offset)
+            }
+            5 => {
+                let unit_type = r.u8()?;
+                let address_size = r.u8()?;
+                let abbrev_offset = r.uint(offset_size)?;
+                if unit_type != DW_UT_COMPILE && unit_type != DW_UT_PARTIAL {
+                    return Some((None, end));
+                }
+                (address_size, abbrev_offset)
+            }
+            _ => return Some((None, end)),
+        };
+        if !(1..=8).contains(&address_size) {
+            return Some((None, end));
+        }
+
+        let mut unit = Self {
+            version,
+            address_size: address_size.into(),
+            offset_size,
+            abbrevs: parse_abbrevs(sections.abbrev, abbrev_offset)?,
+            offset,
+            dies: r.pos,
+            end,
+            root: Die {
+                tag: 0,
+                has_children: false,
+                attrs: Vec::new(),
+            },
+            base_address: 0,
+            str_offsets_base: 0,
+            addr_base: 0,
+            rnglists_base: 0,
+            stmt_list: None,
+            comp_dir: None,
+        };
+        let root = match unit.read_die(sections, &mut r)? {
+            Some(root) => root,
+            None => return Some((None, end)),
+        };
+        let sec_offset = |name| match root.attr(name)? {
+            Value::SecOffset(v) | Value::Udata(v) => Some(v),
+            _ => None,
+        };
+        unit.str_offsets_base = sec_offset(DW_AT_STR_OFFSETS_BASE).unwrap_or(0);
+        unit.addr_base = sec_offset(DW_AT_ADDR_BASE).unwrap_or(0);
+        unit.rnglists_base = sec_offset(DW_AT_RNGLISTS_BASE).unwrap_or(0);
+        unit.stmt_list = sec_offset(DW_AT_STMT_LIST);
+        // now that we have the bases, we can resolve the rest
+        unit.base_address = root
+            .attr(DW_AT_LOW_PC)
+            .and_then(|v| unit.address(sections, v))
+            .unwrap_or(0);
+        unit.comp_dir = root
+            .attr(DW_AT_COMP_DIR)
+            .and_then(|v| unit.string(sections, v));
+        unit.root = root;
+        Some((Some(unit), end))
+    }
+
+    /// Reads a DIE, or `None` for a null entry (the end of a list of
+    /// siblings).
+    fn read_die(&self, sections: &Sections<'a>, r: &mut Reader<'a>) -> Option<Option<Die<'a>>> {
+        let code = r.uleb()?;
+        if code == 0 {
+            return Some(None);
+        }
+        let abbrev = self.abbrevs.get(&code)?;
+        let attrs = abbrev
+            .attrs
+            .iter()
+            .map(|spec| {
+                let value = self.read_value(sections, r, spec.form, spec.implicit_const)?;
+                Some((spec.name, value))
+            })
+            .collect::<Option<_>>()?;
+        Some(Some(Die {
+            tag: abbrev.tag,
+            has_children: abbrev.has_children,
+            attrs,
+        }))
+    }
+
+    fn read_value(
+        &self,
+        sections: &Sections<'a>,
+        r: &mut Reader<'a>,
+        form: u64,
+        implicit_const: i64,
+    ) -> Option<Value<'a>> {
+        let cu_ref = |v: u64| Value::Ref(self.offset as u64 + v);
+        let skip = |r: &mut Reader<'a>, n: u64| r.skip(n).map(|_| Value::Other);
+        Some(match form {
+            DW_FORM_ADDR => Value::Addr(r.uint(self.address_size)?),
+            DW_FORM_ADDRX => Value::Addrx(r.uleb()?),
+            DW_FORM_ADDRX1 => Value::Addrx(r.uint(1)?),
+            DW_FORM_ADDRX2 => Value::Addrx(r.uint(2)?),
+            DW_FORM_ADDRX3 => Value::Addrx(r.uint(3)?),
+            DW_FORM_ADDRX4 => Value::Addrx(r.uint(4)?),
+            DW_FORM_DATA1 => Value::Udata(r.uint(1)?),
+            DW_FORM_DATA2 => Value::Udata(r.uint(2)?),
+            DW_FORM_DATA4 => Value::Udata(r.uint(4)?),
+            DW_FORM_DATA8 => Value::Udata(r.uint(8)?),
+            DW_FORM_DATA16 => skip(r, 16)?,
+            DW_FORM_UDATA => Value::Udata(r.uleb()?),
+            DW_FORM_SDATA => Value::Sdata(r.sleb()?),
+            DW_FORM_IMPLICIT_CONST => Value::Sdata(implicit_const),
+            DW_FORM_FLAG => skip(r, 1)?,
+            DW_FORM_FLAG_PRESENT => Value::Other,
+            DW_FORM_STRING => Value::Str(r.cstr()?),
+            DW_FORM_STRP => Value::Str(str_at(sections.str, r.uint(self.offset_size)?)?),
+            DW_FORM_LINE_STRP => Value::Str(str_at(sections.line_str, r.uint(self.offset_size)?)?),
+            DW_FORM_STRX => Value::Strx(r.uleb()?),
+            DW_FORM_STRX1 => Value::Strx(r.uint(1)?),
+            DW_FORM_STRX2 => Value::Strx(r.uint(2)?),
+            DW_FORM_STRX3 => Value::Strx(r.uint(3)?),
+            DW_FORM_STRX4 => Value::Strx(r.uint(4)?),
+            // supplementary object files: we don't have those
+            DW_FORM_STRP_SUP | DW_FORM_REF_SUP4 => skip(r, self.offset_size as u64)?,
+            DW_FORM_REF_SUP8 | DW_FORM_REF_SIG8 => skip(r, 8)?,
+            DW_FORM_REF1 => cu_ref(r.uint(1)?),
+            DW_FORM_REF2 => cu_ref(r.uint(2)?),
+            DW_FORM_REF4 => cu_ref(r.uint(4)?),
+            DW_FORM_REF8 => cu_ref(r.uint(8)?),
+            DW_FORM_REF_UDATA => cu_ref(r.uleb()?),
+            DW_FORM_REF_ADDR => {
+                // DWARF 2 used the address size for these
+                let size = if self.version <= 2 {
+                    self.address_size
+                } else {
+                    self.offset_size
+                };
+                Value::Ref(r.uint(size)?)
+            }
+            DW_FORM_SEC_OFFSET => Value::SecOffset(r.uint(self.offset_size)?),
+            DW_FORM_RNGLISTX => Value::Rnglistx(r.uleb()?),
+            DW_FORM_LOCLISTX => {
+                r.uleb()?;
+                Value::Other
+            }
+            DW_FORM_EXPRLOC | DW_FORM_BLOCK => {
+                let len = r.uleb()?;
+                skip(r, len)?
+            }
+            DW_FORM_BLOCK1 => {
+                let len = r.uint(1)?;
+                skip(r, len)?
+            }
+            DW_FORM_BLOCK2 => {
+                let len = r.uint(2)?;
+                skip(r, len)?
+            }
+            DW_FORM_BLOCK4 => {
+                let len = r.uint(4)?;
+                skip(r, len)?
+            }
+            DW_FORM_INDIRECT => {
+                let form = r.uleb()?;
+                return self.read_value(sections, r, form, implicit_const);
+            }
+            _ => return None,
+        })
+    }
+
+    fn address(&self, sections: &Sections<'a>, value: Value<'a>) -> Option<u64> {
+        match value {
+            Value::Addr(addr) => Some(addr),
+            Value::Addrx(index) => {
+                let offset = self.addr_base + index * self.address_size as u64;
+                Reader::new(sections.addr, usize::try_from(offset).ok()?).uint(self.address_size)
+            }
+            _ => None,
+        }
+    }
+
+    fn string(&self, sections: &Sections<'a>, value: Value<'a>) -> Option<&'a [u8]> {
+        match value {
+            Value::Str(s) => Some(s),
+            Value::Strx(index) => {
+                let offset = self.str_offsets_base + index * self.offset_size as u64;
+                let str_offset = Reader::new(sections.str_offsets, usize::try_from(offset).ok()?)
+                    .uint(self.offset_size)?;
+                str_at(sections.str, str_offset)
+            }
+            _ => None,
+        }
+    }
+
+    /// The address ranges a DIE covers, from `DW_AT_low_pc` and
+    /// `DW_AT_high_pc`, or `DW_AT_ranges`.
+    fn ranges(&self, sections: &Sections<'a>, die: &Die<'a>) -> Option<Vec<Range<u64>>> {
+        let low = die
+            .attr(DW_AT_LOW_PC)
+            .and_then(|v| self.address(sections, v));
+        // without high_pc, a unit's low_pc is only the base address for its
+        // DW_AT_ranges
+        if let (Some(low), Some(high)) = (low, die.attr(DW_AT_HIGH_PC)) {
+            // high_pc is either an address, or an offset from low_pc
+            let high = match high {
+                Value::Udata(len) => low.checked_add(len)?,
+                v => self.address(sections, v)?,
+            };
+            return Some(std::iter::once(low..high).collect());
+        }
+
+        let (offset, v5) = match die.attr(DW_AT_RANGES)? {
+            Value::SecOffset(offset) | Value::Udata(offset) => (offset, self.version >= 5),
+            Value::Rnglistx(index) => {
+                // the offset table is right at the base, and its entries
+                // are relative to it
+                let entry = self.rnglists_base + index * self.offset_size as u64;
+                let relative = Reader::new(sections.rnglists, usize::try_from(entry).ok()?)
+                    .uint(self.offset_size)?;
+                (self.rnglists_base + relative, true)
+            }
+            _ => return None,
+        };
+        if v5 {
+            self.rnglist(sections, offset)
+        } else {
+            self.debug_ranges(sections, offset)
+        }
+    }
+
+    /// A DWARF 4 range list from `.debug_ranges`
+    fn debug_ranges(&self, sections: &Sections<'a>, offset: u64) -> Option<Vec<Range<u64>>> {
+        let mut r = Reader::new(sections.ranges, usize::try_from(offset).ok()?);
+        let max = u64::MAX >> (64 - 8 * self.address_size);
+        let mut base = self.base_address;
+        let mut ranges = Vec::new();
+        loop {
+            let (start, end) = (r.uint(self.address_size)?, r.uint(self.address_size)?);
+            match (start, end) {
+                (0, 0) => return Some(ranges),
+                (start, end) if start == max => base = end,
+                (start, end) => ranges.push(base + start..base + end),
+            }
+        }
+    }
+
+    /// A DWARF 5 range list from `.debug_rnglists`
+    fn rnglist(&self, sections: &Sections<'a>, offset: u64) -> Option<Vec<Range<u64>>> {
+        let mut r = Reader::new(sections.rnglists, usize::try_from(offset).ok()?);
+        let addrx =
+            |r: &mut Reader<'a>| -> Option<u64> { self.address(sections, Value::Addrx(r.uleb()?)) };
+        let mut base = self.base_address;
+        let mut ranges = Vec::new();
+        loop {
+            match r.u8()? {
+                DW_RLE_END_OF_LIST => return Some(ranges),
+                DW_RLE_BASE_ADDRESSX => base = addrx(&mut r)?,
+                DW_RLE_STARTX_ENDX => {
+                    let start = addrx(&mut r)?;
+                    ranges.push(start..addrx(&mut r)?);
+                }
+                DW_RLE_STARTX_LENGTH => {
+                    let start = addrx(&mut r)?;
+                    ranges.push(start..start + r.uleb()?);
+                }
+                DW_RLE_OFFSET_PAIR => {
+                    let start = base + r.uleb()?;
+                    ranges.push(start..base + r.uleb()?);
+                }
+                DW_RLE_BASE_ADDRESS => base = r.uint(self.address_size)?,
+                DW_RLE_START_END => {
+                    let start = r.uint(self.address_size)?;
+                    ranges.push(start..r.uint(self.address_size)?);
+                }
+                DW_RLE_START_LENGTH => {
+                    let start = r.uint(self.address_size)?;
+                    ranges.push(start..start + r.uleb()?);
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    fn die_contains(&self, sections: &Sections<'a>, die: &Die<'a>, addr: u64) -> bool {
+        self.ranges(sections, die)
+            .is_some_and(|ranges| ranges.iter().any(|r| r.contains(&addr)))
+    }
+
+    /// Whether this unit covers `addr`. Units that don't say what they
+    /// cover might.
+    fn contains(&self, sections: &Sections<'a>, addr: u64) -> bool {
+        let has_ranges =
+            self.root.attr(DW_AT_LOW_PC).is_some() || self.root.attr(DW_AT_RANGES).is_some();
+        !has_ranges || self.die_contains(sections, &self.root, addr)
+    }
+
+    /// The subprogram that contains `addr`, then every inlined call in it
+    /// that contains `addr`, outermost first.
+    fn inline_chain(&self, sections: &Sections<'a>, addr: u64) -> Option<Vec<Die<'a>>> {
+        let mut r = Reader::new(sections.info.get(..self.end)?, self.dies);
+        // entries that contain `addr`, along with their depth
+        let mut chain: Vec<(usize, Die<'a>)> = Vec::new();
+        let mut best = Vec::new();
+        let mut depth = 0;
+        while !r.at_end() {
+            let die = match self.read_die(sections, &mut r)? {
+                Some(die) => die,
+                None => {
+                    if depth == 0 {
+                        break;
+                    }
+                    depth -= 1;
+                    continue;
+                }
+            };
+            // leaving the subtrees of anything at this depth or deeper
+            while matches!(chain.last(), Some((d, _)) if *d >= depth) {
+                chain.pop();
+            }
+            if matches!(die.tag, DW_TAG_SUBPROGRAM | DW_TAG_INLINED_SUBROUTINE)
+                && self.die_contains(sections, &die, addr)
+            {
+                chain.push((depth, die.clone()));
+                // entries nest, so the latest chain is the deepest one
+                best = chain.iter().map(|(_, die)| die.clone()).collect();
+            }
+            if die.has_children {
+                depth += 1;
+            }
+        }
+        Some(best)
+    }
+}
+
+fn parse_abbrevs(section: &[u8], offset: u64) -> Option<HashMap<u64, Abbrev>> {
+    let mut r = Reader::new(section, usize::try_from(offset).ok()?);
+    let mut abbrevs = HashMap::new();
+    loop {
+        let code = r.uleb()?;
+        if code == 0 {
+            return Some(abbrevs);
+        }
+        let tag = r.uleb()?;
+        let has_children = r.u8()? != 0;
+        let mut attrs = Vec::new();
+        loop {
+            let (name, form) = (r.uleb()?, r.uleb()?);
+            if name == 0 && form == 0 {
+                break;
+            }
+            let implicit_const = if form == DW_FORM_IMPLICIT_CONST {
+                r.sleb()?
+            } else {
+                0
+            };
+            attrs.push(AttrSpec {
+                name,
+                form,
+                implicit_const,
+            });
+        }
+        abbrevs.insert(
+            code,
+            Abbrev {
+                tag,
+                has_children,
+                attrs,
+            },
+        );
+    }
+}
+
+/// The (demangled) name of a subprogram, or of what got inlined.
+/// Inlined calls and out-of-line definitions often only point at
+/// another entry that has the name.
+fn function_name<'a>(
+    sections: &Sections<'a>,
+    unit: &Unit<'a>,
+    die: &Die<'a>,
+    depth: usize,
+) -> Option<String> {
+    let linkage_name = die
+        .attr(DW_AT_LINKAGE_NAME)
+        .or_else(|| die.attr(DW_AT_MIPS_LINKAGE_NAME))
+        .and_then(|v| unit.string(sections, v));
+    if let Some(name) = linkage_name {
+        let name = String::from_utf8_lossy(name);
+        return Some(diagnostics::demangle(&name).unwrap_or_else(|| name.into_owned()));
+    }
+    if let Some(name) = die.attr(DW_AT_NAME).and_then(|v| unit.string(sections, v)) {
+        return Some(String::from_utf8_lossy(name).into_owned());
+    }
+
+    if depth >= MAX_ORIGIN_DEPTH {
+        return None;
+    }
+    let target = match die
+        .attr(DW_AT_ABSTRACT_ORIGIN)
+        .or_else(|| die.attr(DW_AT_SPECIFICATION))?
+    {
+        Value::Ref(offset) => usize::try_from(offset).ok()?,
+        _ => return None,
+    };
+    // references may point into another unit
+    let (unit, die) = die_at(sections, target)?;
+    function_name(sections, &unit, &die, depth + 1)
+}
+
+/// The DIE at `offset` in `.debug_info`, and the unit it's in
+fn die_at<'a>(sections: &Sections<'a>, offset: usize) -> Option<(Unit<'a>, Die<'a>)> {
+    let unit = units(sections).find(|unit| (unit.offset..unit.end).contains(&offset))?;
+    let mut r = Reader::new(sections.info.get(..unit.end)?, offset);
+    let die = unit.read_die(sections, &mut r)??;
+    Some((unit, die))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What `addr2line -i -f` says about addresses in `samples/inline.c`:
+    /// the function and line of each frame, innermost first.
+    const EXPECTED: &[(u64, &[(&str, u64)])] = &[
+        // `square`, inlined in `sum_of_squares`, inlined in `compute`
+        (
+            0x401000,
+            &[("square", 12), ("sum_of_squares", 16), ("compute", 20)],
+        ),
+        (
+            0x401003,
+            &[("square", 12), ("sum_of_squares", 16), ("compute", 20)],
+        ),
+        (0x401006, &[("compute", 20)]),
+        (0x40100a, &[("compute", 21)]),
+        (0x401015, &[("_start", 24)]),
+        (0x40101a, &[("_start", 25)]),
+    ];
+
+    fn check(sample: &str) {
+        let path = format!("{}/samples/{}", env!("CARGO_MANIFEST_DIR"), sample);
+        let input = std::fs::read(&path).unwrap();
+        let file = delf::File::parse_or_print_error(input).unwrap();
+        let sections = Sections::new(&file);
+
+        for &(addr, expected) in EXPECTED {
+            let frames: Vec<_> = frames(&sections, addr)
+                .into_iter()
+                .map(|frame| {
+                    let location = frame.location.unwrap();
+                    assert!(
+                        location.file.ends_with("/inline.c"),
+                        "{}: {:#x} is in {}",
+                        sample,
+                        addr,
+                        location.file
+                    );
+                    (frame.function.unwrap(), location.line)
+                })
+                .collect();
+            let expected: Vec<_> = expected
+                .iter()
+                .map(|&(function, line)| (function.to_string(), line))
+                .collect();
+            assert_eq!(frames, expected, "{}: {:#x}", sample, addr);
+        }
+        assert!(frames(&sections, 0x500000).is_empty());
+    }
+
+    #[test]
+    fn dwarf4() {
+        check("inline-dwarf4");
+    }
+
+    #[test]
+    fn dwarf5() {
+        check("inline-dwarf5");
+    }
+}
@@ -0,0 +1,230 @@
+//! Just enough ELF writing for `elk link`: 64-bit, little-endian, and
+//! only the structures we actually emit.
+
+/// ELF file types
+pub const ET_EXEC: u16 = 2;
+pub const ET_DYN: u16 = 3;
+
+/// Machine: x86-64
+pub const EM_X86_64: u16 = 0x3e;
+
+/// Segment types
+pub const PT_LOAD: u32 = 1;
+pub const PT_DYNAMIC: u32 = 2;
+pub const PT_GNU_STACK: u32 = 0x6474_e551;
+
+/// Segment flags
+pub const PF_X: u32 = 0x1;
+pub const PF_W: u32 = 0x2;
+pub const PF_R: u32 = 0x4;
+
+/// Section types
+pub const SHT_PROGBITS: u32 = 1;
+pub const SHT_SYMTAB: u32 = 2;
+pub const SHT_STRTAB: u32 = 3;
+pub const SHT_RELA: u32 = 4;
+pub const SHT_DYNAMIC: u32 = 6;
+pub const SHT_NOBITS: u32 = 8;
+pub const SHT_DYNSYM: u32 = 11;
+
+/// Dynamic tags
+pub const DT_NULL: u64 = 0;
+pub const DT_STRTAB: u64 = 5;
+pub const DT_SYMTAB: u64 = 6;
+pub const DT_RELA: u64 = 7;
+pub const DT_RELASZ: u64 = 8;
+pub const DT_RELAENT: u64 = 9;
+pub const DT_STRSZ: u64 = 10;
+pub const DT_SYMENT: u64 = 11;
+
+/// x86-64 relocation types
+pub const R_X86_64_RELATIVE: u32 = 8;
+
+pub const EHDR_SIZE: u64 = 64;
+pub const PHDR_SIZE: u64 = 56;
+pub const SHDR_SIZE: u64 = 64;
+pub const SYM_SIZE: u64 = 24;
+pub const RELA_SIZE: u64 = 24;
+pub const DYN_SIZE: u64 = 16;
+
+/// Little-endian helpers for `Vec<u8>`
+pub trait Emit {
+    fn u8(&mut self, x: u8);
+    fn u16(&mut self, x: u16);
+    fn u32(&mut self, x: u32);
+    fn u64(&mut self, x: u64);
+}
+
+impl Emit for Vec<u8> {
+    fn u8(&mut self, x: u8) {
+        self.push(x)
+    }
+    fn u16(&mut self, x: u16) {
+        self.extend(&x.to_le_bytes())
+    }
+    fn u32(&mut self, x: u32) {
+        self.extend(&x.to_le_bytes())
+    }
+    fn u64(&mut self, x: u64) {
+        self.extend(&x.to_le_bytes())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct FileHeader {
+    pub r#type: u16,
+    pub machine: u16,
+    pub entry: u64,
+    pub phoff: u64,
+    pub shoff: u64,
+    pub phnum: u16,
+    pub shnum: u16,
+    pub shstrndx: u16,
+}
+
+impl FileHeader {
+    pub fn write(&self, out: &mut Vec<u8>) {
+        // magic, 64-bit, little-endian, version 1, SysV ABI
+        out.extend(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+        out.extend(&[0u8; 8]);
+        out.u16(self.r#type);
+        out.u16(self.machine);
+        out.u32(1);
+        out.u64(self.entry);
+        out.u64(self.phoff);
+        out.u64(self.shoff);
+        out.u32(0); // flags
+        out.u16(EHDR_SIZE as u16);
+        out.u16(PHDR_SIZE as u16);
+        out.u16(self.phnum);
+        out.u16(SHDR_SIZE as u16);
+        out.u16(self.shnum);
+        out.u16(self.shstrndx);
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ProgramHeader {
+    pub r#type: u32,
+    pub flags: u32,
+    pub offset: u64,
+    pub vaddr: u64,
+    pub filesz: u64,
+    pub memsz: u64,
+    pub align: u64,
+}
+
+impl ProgramHeader {
+    pub fn write(&self, out: &mut Vec<u8>) {
+        out.u32(self.r#type);
+        out.u32(self.flags);
+        out.u64(self.offset);
+        out.u64(self.vaddr);
+        // physical address, same as virtual
+        out.u64(self.vaddr);
+        out.u64(self.filesz);
+        out.u64(self.memsz);
+        out.u64(self.align);
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct SectionHeader {
+    pub name: u32,
+    pub r#type: u32,
+    pub flags: u64,
+    pub addr: u64,
+    pub offset: u64,
+    pub size: u64,
+    pub link: u32,
+    pub info: u32,
+    pub addralign: u64,
+    pub entsize: u64,
+}
+
+impl SectionHeader {
+    pub fn write(&self, out: &mut Vec<u8>) {
+        out.u32(self.name);
+        out.u32(self.r#type);
+        out.u64(self.flags);
+        out.u64(self.addr);
+        out.u64(self.offset);
+        out.u64(self.size);
+        out.u32(self.link);
+        out.u32(self.info);
+        out.u64(self.addralign);
+        out.u64(self.entsize);
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Sym {
+    pub name: u32,
+    pub bind: u8,
+    pub r#type: u8,
+    pub shndx: u16,
+    pub value: u64,
+    pub size: u64,
+}
+
+impl Sym {
+    pub fn write(&self, out: &mut Vec<u8>) {
+        out.u32(self.name);
+        out.u8((self.bind << 4) | (self.r#type & 0xf));
+        out.u8(0); // other (visibility)
+        out.u16(self.shndx);
+        out.u64(self.value);
+        out.u64(self.size);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Rela {
+    pub offset: u64,
+    pub r#type: u32,
+    pub sym: u32,
+    pub addend: u64,
+}
+
+impl Rela {
+    pub fn write(&self, out: &mut Vec<u8>) {
+        out.u64(self.offset);
+        out.u64(((self.sym as u64) << 32) | self.r#type as u64);
+        out.u64(self.addend);
+    }
+}
+
+/// Writes a dynamic entry
+pub fn write_dyn(out: &mut Vec<u8>, tag: u64, value: u64) {
+    out.u64(tag);
+    out.u64(value);
+}
+
+/// A string table under construction. Offset 0 is always the empty string.
+#[derive(Debug)]
+pub struct StrTab {
+    data: Vec<u8>,
+}
+
+impl Default for StrTab {
+    fn default() -> Self {
+        Self { data: vec![0] }
+    }
+}
+
+impl StrTab {
+    /// Adds a string, returns its offset
+    pub fn add(&mut self, s: &[u8]) -> u32 {
+        if s.is_empty() {
+            return 0;
+        }
+        let offset = self.data.len() as u32;
+        self.data.extend(s);
+        self.data.push(0);
+        offset
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data
+    }
+}
@@ -0,0 +1,39 @@
+//! A read-only mapping of a whole file, for parsing. The kernel pages it
+//! in as needed, and can drop it under memory pressure, so sections we
+//! never look at (like debug info) cost next to nothing.
+
+use std::{fs::File, os::unix::io::AsRawFd};
+
+use mmap::{MapOption, MemoryMap};
+
+pub struct FileMap {
+    map: MemoryMap,
+    len: usize,
+}
+
+impl FileMap {
+    /// Maps all of `file`. Empty files can't be mapped, so they're
+    /// an error.
+    pub fn new(file: &File) -> std::io::Result<Self> {
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "empty file",
+            ));
+        }
+        let map = MemoryMap::new(
+            len,
+            &[MapOption::MapReadable, MapOption::MapFd(file.as_raw_fd())],
+        )
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+        Ok(Self { map, len })
+    }
+}
+
+impl AsRef<[u8]> for FileMap {
+    fn as_ref(&self) -> &[u8] {
+        // `MemoryMap::len` is rounded up to the page size
+        unsafe { std::slice::from_raw_parts(self.map.data(), self.len) }
+    }
+}
@@ -0,0 +1,131 @@
+//! `elk info`: how a process was started, and what it was told about its
+//! environment - command line, environment variables, auxiliary vector,
+//! and a few lines of `status`. Everything comes from `/proc/:pid`, so
+//! this works on processes that weren't loaded by elk, too.
+
+use std::{error::Error, fs};
+
+use crate::{
+    process::AuxType,
+    procfs::{self, AuxEntry, ProcessMemory},
+};
+
+/// Longest string we'll read from the process's memory
+const MAX_STRING_LEN: usize = 4096;
+
+pub fn run(pid: u32) -> Result<(), Box<dyn Error>> {
+    let proc_path = |name: &str| format!("/proc/{}/{}", pid, name);
+
+    if let Ok(exe) = fs::read_link(proc_path("exe")) {
+        println!("Executable: {}", exe.display());
+    }
+
+    let cmdline = fs::read(proc_path("cmdline"))?;
+    let (_, args) = procfs::nul_separated(&cmdline).map_err(|e| format!("{:?}", e))?;
+    let args: Vec<_> = args.iter().map(|s| String::from_utf8_lossy(s)).collect();
+    println!("Command line: {:?}", args);
+
+    let status = fs::read_to_string(proc_path("status"))?;
+    let (_, status) = procfs::status(&status).map_err(|e| format!("{:?}", e))?;
+    println!("Status:");
+    if let Some(name) = &status.name {
+        println!("    Name:       {}", name);
+    }
+    if let Some(tracer_pid) = status.tracer_pid {
+        let tracer = match tracer_pid {
+            0 => "not traced".to_string(),
+            pid => format!("traced by {}", pid),
+        };
+        println!("    TracerPid:  {} ({})", tracer_pid, tracer);
+    }
+    if let Some(seccomp) = status.seccomp {
+        let mode = match seccomp {
+            0 => "disabled",
+            1 => "strict",
+            2 => "filter",
+            _ => "unknown",
+        };
+        println!("    Seccomp:    {} ({})", seccomp, mode);
+    }
+    if let Some(vm_rss) = status.vm_rss {
+        println!("    VmRSS:      {} KiB", vm_rss);
+    }
+    if let Some(threads) = status.threads {
+        println!("    Threads:    {}", threads);
+    }
+    if let Some(cap_eff) = status.cap_eff {
+        println!("    CapEff:     {:016x}", cap_eff);
+    }
+
+    // auxv is in the process's native word size, which we get from its
+    // executable's ELF class
+    let word_size = match fs::read(proc_path("exe")) {
+        Ok(exe) if exe.get(4) == Some(&1) => 4,
+        _ => 8,
+    };
+    let auxv = fs::read(proc_path("auxv"))?;
+    let (_, auxv) = procfs::auxv(&auxv, word_size).map_err(|e| format!("{:?}", e))?;
+    let mem = ProcessMemory::open(pid).ok();
+    println!("Auxiliary vector:");
+    for entry in &auxv {
+        println!("    {}", describe_aux(entry, mem.as_ref()));
+    }
+
+    let environ = fs::read(proc_path("environ"))?;
+    let (_, environ) = procfs::nul_separated(&environ).map_err(|e| format!("{:?}", e))?;
+    println!("Environment:");
+    for var in environ {
+        println!("    {}", String::from_utf8_lossy(var));
+    }
+
+    Ok(())
+}
+
+/// One auxv entry, for humans. String entries are read from the process's
+/// memory, if we can.
+fn describe_aux(entry: &AuxEntry, mem: Option<&ProcessMemory>) -> String {
+    let typ = match entry.typ {
+        Some(typ) => typ,
+        None => {
+            return format!(
+                "{:<14} {:#x}",
+                format!("type {}", entry.raw_type),
+                entry.value
+            )
+        }
+    };
+    let name = format!("{:?}", typ);
+    match typ {
+        AuxType::Platform | AuxType::BasePlatform | AuxType::ExecFn => {
+            match mem.and_then(|mem| mem.read_cstr(entry.value, MAX_STRING_LEN).ok()) {
+                Some(s) => format!(
+                    "{:<14} {:#x} {:?}",
+                    name,
+                    entry.value,
+                    String::from_utf8_lossy(&s)
+                ),
+                None => format!("{:<14} {:#x}", name, entry.value),
+            }
+        }
+        AuxType::Secure => format!(
+            "{:<14} {} ({})",
+            name,
+            entry.value,
+            if entry.value != 0 {
+                "secure mode"
+            } else {
+                "not secure"
+            }
+        ),
+        AuxType::PageSz
+        | AuxType::PhEnt
+        | AuxType::PhNum
+        | AuxType::ClkTck
+        | AuxType::Uid
+        | AuxType::EUid
+        | AuxType::Gid
+        | AuxType::EGid
+        | AuxType::ExecFd => format!("{:<14} {}", name, entry.value),
+        _ => format!("{:<14} {:#x}", name, entry.value),
+    }
+}
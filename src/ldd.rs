@@ -0,0 +1,186 @@
+//! `elk ldd`: shows what an ELF object depends on, and why each dependency
+//! resolved to the file it did. Unlike the system's `ldd`, this never runs
+//! the target: objects get mapped (non-executable), but nothing is
+//! relocated, and no code of theirs is ever called.
+//!
+//! Dependencies are searched for in the same order as ld.so does: the
+//! `DT_RPATH` of the loading chain, `LD_LIBRARY_PATH`, the `DT_RUNPATH` of
+//! the object that needs them, then the default directory. There's no
+//! `ld.so.cache` lookup, though.
+
+use std::{collections::HashSet, error::Error, str::FromStr};
+
+use crate::process::{LoadError, Loader, Loading, Process, SearchReason};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Tree,
+    Flat,
+    Dot,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tree" => Ok(Self::Tree),
+            "flat" => Ok(Self::Flat),
+            "dot" => Ok(Self::Dot),
+            "json" => Ok(Self::Json),
+            _ => Err(format!(
+                "unknown format {:?}, expected one of: tree, flat, dot, json",
+                s
+            )),
+        }
+    }
+}
+
+/// Loads `exec_path` and everything it needs, without running any of it.
+/// That rules out auditors too, so `LD_AUDIT` is ignored.
+fn load(exec_path: &str) -> Result<(Process<Loading>, usize), LoadError> {
+    let mut proc = Process::new();
+    proc.map_executable(false);
+    let exec_index = proc.load_object_and_dependencies(exec_path)?;
+    Ok((proc, exec_index))
+}
+
+pub fn run(exec_path: &str, format: Format) -> Result<(), Box<dyn Error>> {
+    let (proc, exec_index) = load(exec_path)?;
+    let loader = &proc.state.loader;
+
+    match format {
+        Format::Tree => {
+            println!("{}", loader.objects[exec_index].path.display());
+            let mut seen = HashSet::new();
+            seen.insert(exec_index);
+            print_tree(loader, exec_index, "", &mut seen);
+        }
+        Format::Flat => {
+            // every object, in load order, with the reason it was first found
+            for (index, obj) in loader.objects.iter().enumerate() {
+                if let Some(dep) = loader.dependencies.iter().find(|dep| dep.to == index) {
+                    println!("\t{} => {} ({})", dep.name, obj.path.display(), dep.reason);
+                }
+            }
+        }
+        Format::Dot => {
+            println!("digraph dependencies {{");
+            println!("    node [shape=box];");
+            for (index, obj) in loader.objects.iter().enumerate() {
+                println!("    n{} [label={:?}];", index, obj.path.to_string_lossy());
+            }
+            for dep in &loader.dependencies {
+                println!(
+                    "    n{} -> n{} [label={:?}];",
+                    dep.from,
+                    dep.to,
+                    format!("{}\n{}", dep.name, dep.reason)
+                );
+            }
+            println!("}}");
+        }
+        Format::Json => {
+            #[derive(serde::Serialize)]
+            struct Object {
+                index: usize,
+                path: String,
+            }
+
+            #[derive(serde::Serialize)]
+            struct Dependency<'a> {
+                from: usize,
+                name: &'a str,
+                to: usize,
+                path: String,
+                reason: &'a SearchReason,
+                origin_expanded: bool,
+            }
+
+            #[derive(serde::Serialize)]
+            struct Report<'a> {
+                objects: Vec<Object>,
+                dependencies: Vec<Dependency<'a>>,
+            }
+
+            let report = Report {
+                objects: loader
+                    .objects
+                    .iter()
+                    .enumerate()
+                    .map(|(index, obj)| Object {
+                        index,
+                        path: obj.path.to_string_lossy().into_owned(),
+                    })
+                    .collect(),
+                dependencies: loader
+                    .dependencies
+                    .iter()
+                    .map(|dep| Dependency {
+                        from: dep.from,
+                        name: &dep.name,
+                        to: dep.to,
+                        path: loader.objects[dep.to].path.to_string_lossy().into_owned(),
+                        reason: &dep.reason,
+                        origin_expanded: dep.reason.origin_expanded(),
+                    })
+                    .collect(),
+            };
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints the dependencies of `index`, recursively. Objects we've already
+/// printed the dependencies of aren't expanded again.
+fn print_tree(loader: &Loader, index: usize, prefix: &str, seen: &mut HashSet<usize>) {
+    let deps: Vec<_> = loader
+        .dependencies
+        .iter()
+        .filter(|dep| dep.from == index)
+        .collect();
+
+    for (i, dep) in deps.iter().enumerate() {
+        let (branch, indent) = if i + 1 == deps.len() {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        let first = seen.insert(dep.to);
+        println!(
+            "{}{}{} => {} ({}){}",
+            prefix,
+            branch,
+            dep.name,
+            loader.objects[dep.to].path.display(),
+            dep.reason,
+            if first { "" } else { " [see above]" }
+        );
+        if first {
+            print_tree(loader, dep.to, &format!("{}{}", prefix, indent), seen);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignores_ld_audit() {
+        // if we tried to load it, loading would fail
+        std::env::set_var("LD_AUDIT", "/nonexistent/libaudit.so");
+        let loaded = load(concat!(env!("CARGO_MANIFEST_DIR"), "/samples/hello-dl"));
+        std::env::remove_var("LD_AUDIT");
+
+        let (proc, exec_index) = loaded.unwrap();
+        let loader = &proc.state.loader;
+        assert!(loader.audit.is_empty());
+        assert!(!loader.map_executable);
+        let dep = &loader.dependencies[0];
+        assert_eq!((dep.from, dep.name.as_str()), (exec_index, "libmsg.so"));
+    }
+}
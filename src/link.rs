@@ -0,0 +1,864 @@
+//! `elk link`: a minimal static linker. It lays out the sections of a bunch
+//! of relocatable objects in three segments (code, read-only data, and
+//! data followed by bss), and resolves every symbol at link time.
+//!
+//! Position-independent executables get a small dynamic section with
+//! `R_X86_64_RELATIVE` relocations, which `elk run` knows how to apply.
+//! They have no `PT_INTERP` and no self-relocating startup code, so they
+//! can't be run on their own.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    arch::{self, Arch, RelKind},
+    elfwrite as ew,
+    relocatable::{self, SectionKind, SectionReloc, SHN_COMMON},
+};
+
+const PAGE_SIZE: u64 = 0x1000;
+
+/// Where non-PIE executables get loaded, same as GNU ld
+const STATIC_BASE: u64 = 0x40_0000;
+
+/// Symbols with an absolute value
+const SHN_ABS: u16 = 0xfff1;
+
+#[derive(thiserror::Error, Debug)]
+pub enum LinkError {
+    #[error("I/O error on {0:?}: {1}")]
+    IO(PathBuf, std::io::Error),
+    #[error("{0:?} could not be parsed")]
+    ParseError(PathBuf),
+    #[error("{0:?} is not a relocatable object")]
+    NotRelocatable(PathBuf),
+    #[error("{0:?}: only x86-64 objects can be linked")]
+    UnsupportedMachine(PathBuf),
+    #[error("could not read symbols from {0:?}: {1}")]
+    ReadSymsError(PathBuf, delf::ReadSymsError),
+    #[error("{0:?} defines {1:?}, but so does {2:?}")]
+    DuplicateSymbol(PathBuf, String, PathBuf),
+    #[error("{0:?}: undefined symbol {1:?}")]
+    UndefinedSymbol(PathBuf, String),
+    #[error("entry point {0:?} is not defined")]
+    NoEntryPoint(String),
+    #[error("{0:?}: unsupported relocation type {1}")]
+    UnsupportedRelocation(PathBuf, u32),
+    #[error("{0:?}: relocation type {1} against {2:?} can't be used in a position-independent executable")]
+    NotPic(PathBuf, u32, String),
+    #[error("{0:?}: relocation at {1:#x} doesn't fit in 32 bits")]
+    Overflow(PathBuf, u64),
+}
+
+pub struct Options {
+    /// Produce a position-independent executable (`ET_DYN`)
+    pub pie: bool,
+    /// Name of the entry point symbol
+    pub entry: String,
+}
+
+struct Input {
+    path: PathBuf,
+    file: delf::File<Vec<u8>>,
+    syms: Vec<delf::Sym>,
+    rels: Vec<SectionReloc>,
+}
+
+impl Input {
+    fn read(path: &Path) -> Result<Self, LinkError> {
+        let contents = std::fs::read(path).map_err(|e| LinkError::IO(path.into(), e))?;
+        let file = delf::File::parse_or_print_error(contents)
+            .ok_or_else(|| LinkError::ParseError(path.into()))?;
+        if !matches!(file.typ, delf::Type::Rel) {
+            return Err(LinkError::NotRelocatable(path.into()));
+        }
+        if !matches!(file.machine, delf::Machine::X86_64) {
+            return Err(LinkError::UnsupportedMachine(path.into()));
+        }
+
+        let syms = file
+            .read_symtab_entries()
+            .map_err(|e| LinkError::ReadSymsError(path.into(), e))?;
+        let rels = relocatable::read_relocations(&file)
+            .ok_or_else(|| LinkError::ParseError(path.into()))?;
+        Ok(Self {
+            path: path.into(),
+            file,
+            syms,
+            rels,
+        })
+    }
+
+    fn sym_name(&self, index: usize) -> String {
+        String::from_utf8_lossy(self.file.strtab_entry(self.syms[index].name)).into()
+    }
+}
+
+/// A symbol of a given input, by index
+type SymRef = (usize, usize);
+
+/// What a symbol resolved to
+#[derive(Debug, Clone, Copy)]
+enum Value {
+    /// An offset from the image base - needs relocating in a PIE
+    Image(u64),
+    /// An absolute value, same no matter where we're loaded
+    Absolute(u64),
+}
+
+/// One of the sections we output, as a range of offsets from the image base
+#[derive(Debug, Default, Clone)]
+struct Span {
+    start: u64,
+    end: u64,
+}
+
+impl Span {
+    fn len(&self) -> u64 {
+        self.end - self.start
+    }
+}
+
+struct Linker<'a> {
+    arch: &'static dyn Arch,
+    opts: &'a Options,
+    inputs: Vec<Input>,
+    globals: HashMap<Vec<u8>, SymRef>,
+
+    /// Offset of each allocated input section, by (input, section index)
+    sections: HashMap<(usize, usize), u64>,
+    /// Offset of each common symbol
+    commons: HashMap<SymRef, u64>,
+    /// Offset of each GOT slot, by referencing symbol
+    got: HashMap<(usize, u32), u64>,
+}
+
+/// Links relocatable objects into an executable, returns its contents
+pub fn link<P: AsRef<Path>>(inputs: &[P], opts: &Options) -> Result<Vec<u8>, LinkError> {
+    let inputs = inputs
+        .iter()
+        .map(|path| Input::read(path.as_ref()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut linker = Linker {
+        arch: &arch::X86_64,
+        opts,
+        inputs,
+        globals: HashMap::new(),
+        sections: HashMap::new(),
+        commons: HashMap::new(),
+        got: HashMap::new(),
+    };
+    linker.collect_globals()?;
+    linker.write()
+}
+
+impl Linker<'_> {
+    /// Builds the global symbol table. Strong definitions win over weak ones,
+    /// two strong definitions are an error.
+    fn collect_globals(&mut self) -> Result<(), LinkError> {
+        for (i, input) in self.inputs.iter().enumerate() {
+            for (j, sym) in input.syms.iter().enumerate() {
+                if matches!(sym.bind, delf::SymBind::Local) || sym.shndx.is_undef() {
+                    continue;
+                }
+                let name = input.file.strtab_entry(sym.name).to_vec();
+                match self.globals.get(&name) {
+                    None => {}
+                    Some(&(pi, pj)) => {
+                        let prev = &self.inputs[pi].syms[pj];
+                        let weak = |sym: &delf::Sym| matches!(sym.bind, delf::SymBind::Weak);
+                        match (weak(prev), weak(sym)) {
+                            (false, false) => {
+                                return Err(LinkError::DuplicateSymbol(
+                                    input.path.clone(),
+                                    input.sym_name(j),
+                                    self.inputs[pi].path.clone(),
+                                ))
+                            }
+                            // the one we have wins
+                            (false, true) | (true, true) => continue,
+                            (true, false) => {}
+                        }
+                    }
+                }
+                self.globals.insert(name, (i, j));
+            }
+        }
+        Ok(())
+    }
+
+    fn defined_value(&self, (i, j): SymRef) -> Value {
+        let sym = &self.inputs[i].syms[j];
+        match sym.shndx.0 {
+            SHN_ABS => Value::Absolute(sym.value.0),
+            SHN_COMMON => Value::Image(self.commons[&(i, j)]),
+            _ => match sym
+                .shndx
+                .get()
+                .and_then(|shndx| self.sections.get(&(i, shndx)))
+            {
+                Some(offset) => Value::Image(offset + sym.value.0),
+                // defined in a section we don't load, like debug info
+                None => Value::Absolute(sym.value.0),
+            },
+        }
+    }
+
+    fn resolve(&self, (i, j): SymRef) -> Result<Value, LinkError> {
+        let input = &self.inputs[i];
+        let sym = &input.syms[j];
+        if j == 0 {
+            return Ok(Value::Absolute(0));
+        }
+        if !sym.shndx.is_undef() {
+            return Ok(self.defined_value((i, j)));
+        }
+        match self.globals.get(input.file.strtab_entry(sym.name)) {
+            Some(&target) => Ok(self.defined_value(target)),
+            None if matches!(sym.bind, delf::SymBind::Weak) => Ok(Value::Absolute(0)),
+            None => Err(LinkError::UndefinedSymbol(
+                input.path.clone(),
+                input.sym_name(j),
+            )),
+        }
+    }
+
+    fn write(mut self) -> Result<Vec<u8>, LinkError> {
+        let pie = self.opts.pie;
+        let base = if pie { 0 } else { STATIC_BASE };
+        let align = |x: u64, a: u64| x.next_multiple_of(a.max(1));
+
+        // count what we need to reserve in the GOT and in .rela.dyn
+        let mut got_slots = Vec::new();
+        let mut absolute_rels = 0;
+        for (i, input) in self.inputs.iter().enumerate() {
+            for SectionReloc { rel, .. } in &input.rels {
+                match self.arch.rel_kind(rel.r#type) {
+                    Some(RelKind::GotPcRel) if !got_slots.contains(&(i, rel.sym)) => {
+                        got_slots.push((i, rel.sym))
+                    }
+                    Some(RelKind::Absolute) => absolute_rels += 1,
+                    _ => {}
+                }
+            }
+        }
+
+        // place input sections of a given kind, NOBITS or not
+        let place = |linker: &mut Self, cursor: &mut u64, kind: SectionKind, nobits: bool| {
+            for (i, input) in linker.inputs.iter().enumerate() {
+                for (j, sh) in input.file.section_headers.iter().enumerate() {
+                    if relocatable::SectionKind::of(sh) != Some(kind)
+                        || matches!(sh.r#type, delf::SectionType::NoBits) != nobits
+                    {
+                        continue;
+                    }
+                    *cursor = align(*cursor, sh.addralign.0);
+                    linker.sections.insert((i, j), *cursor);
+                    *cursor += sh.size.0;
+                }
+            }
+        };
+
+        // the first page holds the ELF header and program headers
+        let mut cursor = PAGE_SIZE;
+
+        let text = {
+            let start = cursor;
+            place(&mut self, &mut cursor, SectionKind::Text, false);
+            Span { start, end: cursor }
+        };
+
+        cursor = align(cursor, PAGE_SIZE);
+        let rodata_start = cursor;
+        let rodata = {
+            let start = cursor;
+            place(&mut self, &mut cursor, SectionKind::ReadOnly, false);
+            Span { start, end: cursor }
+        };
+        let mut dynsym = Span::default();
+        let mut dynstr = Span::default();
+        let mut rela_dyn = Span::default();
+        if pie {
+            // an empty dynamic symbol table: just the null symbol
+            cursor = align(cursor, 8);
+            dynsym = Span {
+                start: cursor,
+                end: cursor + ew::SYM_SIZE,
+            };
+            cursor = dynsym.end;
+            dynstr = Span {
+                start: cursor,
+                end: cursor + 1,
+            };
+            cursor = align(dynstr.end, 8);
+            rela_dyn = Span {
+                start: cursor,
+                end: cursor + (absolute_rels + got_slots.len() as u64) * ew::RELA_SIZE,
+            };
+            cursor = rela_dyn.end;
+        }
+        let rodata_end = cursor;
+
+        cursor = align(cursor, PAGE_SIZE);
+        let data_start = cursor;
+        let data = {
+            let start = cursor;
+            place(&mut self, &mut cursor, SectionKind::Data, false);
+            Span { start, end: cursor }
+        };
+        cursor = align(cursor, 8);
+        let got = Span {
+            start: cursor,
+            end: cursor + got_slots.len() as u64 * 8,
+        };
+        for (index, slot) in got_slots.iter().enumerate() {
+            self.got.insert(*slot, got.start + index as u64 * 8);
+        }
+        cursor = got.end;
+        // SYMTAB, STRTAB, STRSZ, SYMENT, RELA, RELASZ, RELAENT, NULL
+        const DYNAMIC_ENTRIES: u64 = 8;
+        let mut dynamic = Span::default();
+        if pie {
+            cursor = align(cursor, 8);
+            dynamic = Span {
+                start: cursor,
+                end: cursor + DYNAMIC_ENTRIES * ew::DYN_SIZE,
+            };
+            cursor = dynamic.end;
+        }
+        // everything after this point only exists in memory
+        let file_end = cursor;
+        let bss = {
+            let start = cursor;
+            place(&mut self, &mut cursor, SectionKind::Data, true);
+            for (i, input) in self.inputs.iter().enumerate() {
+                for (j, sym) in input.syms.iter().enumerate() {
+                    if sym.shndx.0 == SHN_COMMON {
+                        // for common symbols, `value` is the alignment
+                        cursor = align(cursor, sym.value.0);
+                        self.commons.insert((i, j), cursor);
+                        cursor += sym.size;
+                    }
+                }
+            }
+            Span { start, end: cursor }
+        };
+        let data_end = cursor;
+
+        // copy section contents
+        let mut image = vec![0u8; file_end as usize];
+        for (&(i, j), &offset) in &self.sections {
+            let sh = &self.inputs[i].file.section_headers[j];
+            if matches!(sh.r#type, delf::SectionType::NoBits) {
+                continue;
+            }
+            let start: usize = sh.offset.into();
+            let contents = start
+                .checked_add(usize::from(sh.size))
+                .and_then(|end| self.inputs[i].file.input.get(start..end))
+                .ok_or_else(|| LinkError::ParseError(self.inputs[i].path.clone()))?;
+            image[offset as usize..][..contents.len()].copy_from_slice(contents);
+        }
+        // apply relocations
+        let mut relas = Vec::new();
+        let mut filled_slots = HashSet::new();
+        for (i, input) in self.inputs.iter().enumerate() {
+            for SectionReloc { section, rel } in &input.rels {
+                let place = self.sections[&(i, *section)] + rel.offset.0;
+                let p = base + place;
+                let kind = self.arch.rel_kind(rel.r#type).ok_or_else(|| {
+                    LinkError::UnsupportedRelocation(input.path.clone(), rel.r#type)
+                })?;
+                let target = self.resolve((i, rel.sym as usize))?;
+                let s = match target {
+                    Value::Image(offset) => base + offset,
+                    Value::Absolute(value) => value,
+                };
+                let a = rel.addend.0;
+                let needs_rebase = pie && matches!(target, Value::Image(_));
+
+                let write64 = |image: &mut Vec<u8>, at: u64, value: u64| {
+                    image[at as usize..][..8].copy_from_slice(&value.to_le_bytes());
+                };
+                let write32 = |image: &mut Vec<u8>, value: i64, signed: bool| {
+                    let fits = if signed {
+                        i32::try_from(value).is_ok()
+                    } else {
+                        u32::try_from(value).is_ok()
+                    };
+                    if !fits {
+                        return Err(LinkError::Overflow(input.path.clone(), p));
+                    }
+                    image[place as usize..][..4].copy_from_slice(&(value as u32).to_le_bytes());
+                    Ok(())
+                };
+                let rebase = |relas: &mut Vec<ew::Rela>, at: u64, value: u64| {
+                    relas.push(ew::Rela {
+                        offset: at,
+                        r#type: ew::R_X86_64_RELATIVE,
+                        sym: 0,
+                        addend: value,
+                    })
+                };
+
+                match kind {
+                    RelKind::None => {}
+                    RelKind::Absolute => {
+                        write64(&mut image, place, s.wrapping_add(a));
+                        if needs_rebase {
+                            rebase(&mut relas, p, s.wrapping_add(a));
+                        }
+                    }
+                    // everything is local, so we never need PLT stubs
+                    RelKind::Pc32 | RelKind::Plt32 => {
+                        write32(&mut image, s.wrapping_add(a).wrapping_sub(p) as i64, true)?
+                    }
+                    RelKind::GotPcRel => {
+                        let slot = self.got[&(i, rel.sym)];
+                        if filled_slots.insert(slot) {
+                            write64(&mut image, slot, s);
+                            if needs_rebase {
+                                rebase(&mut relas, base + slot, s);
+                            }
+                        }
+                        write32(
+                            &mut image,
+                            (base + slot).wrapping_add(a).wrapping_sub(p) as i64,
+                            true,
+                        )?
+                    }
+                    RelKind::Abs32 | RelKind::Abs32S => {
+                        if needs_rebase {
+                            return Err(LinkError::NotPic(
+                                input.path.clone(),
+                                rel.r#type,
+                                input.sym_name(rel.sym as usize),
+                            ));
+                        }
+                        write32(
+                            &mut image,
+                            s.wrapping_add(a) as i64,
+                            matches!(kind, RelKind::Abs32S),
+                        )?
+                    }
+                    _ => {
+                        return Err(LinkError::UnsupportedRelocation(
+                            input.path.clone(),
+                            rel.r#type,
+                        ))
+                    }
+                }
+            }
+        }
+
+        if pie {
+            let mut out = Vec::new();
+            for rela in &relas {
+                rela.write(&mut out);
+            }
+            image[rela_dyn.start as usize..][..out.len()].copy_from_slice(&out);
+
+            let mut out = Vec::new();
+            for (tag, value) in [
+                (ew::DT_SYMTAB, dynsym.start),
+                (ew::DT_STRTAB, dynstr.start),
+                (ew::DT_STRSZ, dynstr.len()),
+                (ew::DT_SYMENT, ew::SYM_SIZE),
+                (ew::DT_RELA, rela_dyn.start),
+                (ew::DT_RELASZ, relas.len() as u64 * ew::RELA_SIZE),
+                (ew::DT_RELAENT, ew::RELA_SIZE),
+                (ew::DT_NULL, 0),
+            ] {
+                ew::write_dyn(&mut out, tag, value);
+            }
+            image[dynamic.start as usize..][..out.len()].copy_from_slice(&out);
+        }
+
+        let entry = match self.globals.get(self.opts.entry.as_bytes()) {
+            Some(&target) => match self.defined_value(target) {
+                Value::Image(offset) => base + offset,
+                Value::Absolute(value) => value,
+            },
+            None => return Err(LinkError::NoEntryPoint(self.opts.entry.clone())),
+        };
+
+        // section headers. order matters: symbols refer to sections by index
+        let mut shstrtab = ew::StrTab::default();
+        let mut shdrs = vec![ew::SectionHeader::default()];
+        let mut add_section = |name: &str,
+                               r#type: u32,
+                               flags: u64,
+                               span: &Span,
+                               addralign: u64,
+                               entsize: u64|
+         -> u16 {
+            shdrs.push(ew::SectionHeader {
+                name: shstrtab.add(name.as_bytes()),
+                r#type,
+                flags,
+                addr: base + span.start,
+                offset: span.start,
+                size: span.len(),
+                addralign,
+                entsize,
+                ..Default::default()
+            });
+            (shdrs.len() - 1) as u16
+        };
+        use relocatable::{SHF_ALLOC, SHF_EXECINSTR, SHF_WRITE};
+        let text_index = add_section(
+            ".text",
+            ew::SHT_PROGBITS,
+            SHF_ALLOC | SHF_EXECINSTR,
+            &text,
+            16,
+            0,
+        );
+        let rodata_index = add_section(".rodata", ew::SHT_PROGBITS, SHF_ALLOC, &rodata, 16, 0);
+        let data_index = add_section(
+            ".data",
+            ew::SHT_PROGBITS,
+            SHF_ALLOC | SHF_WRITE,
+            &data,
+            16,
+            0,
+        );
+        add_section(".got", ew::SHT_PROGBITS, SHF_ALLOC | SHF_WRITE, &got, 8, 8);
+        let bss_index = add_section(".bss", ew::SHT_NOBITS, SHF_ALLOC | SHF_WRITE, &bss, 16, 0);
+        if pie {
+            let dynstr_index = add_section(".dynstr", ew::SHT_STRTAB, SHF_ALLOC, &dynstr, 1, 0);
+            let dynsym_index = add_section(
+                ".dynsym",
+                ew::SHT_DYNSYM,
+                SHF_ALLOC,
+                &dynsym,
+                8,
+                ew::SYM_SIZE,
+            );
+            let rela_index = add_section(
+                ".rela.dyn",
+                ew::SHT_RELA,
+                SHF_ALLOC,
+                &rela_dyn,
+                8,
+                ew::RELA_SIZE,
+            );
+            let dynamic_index = add_section(
+                ".dynamic",
+                ew::SHT_DYNAMIC,
+                SHF_ALLOC | SHF_WRITE,
+                &dynamic,
+                8,
+                ew::DYN_SIZE,
+            );
+            shdrs[dynsym_index as usize].link = dynstr_index as u32;
+            // everything in .dynsym is local (there's only the null symbol)
+            shdrs[dynsym_index as usize].info = 1;
+            shdrs[rela_index as usize].link = dynsym_index as u32;
+            shdrs[dynamic_index as usize].link = dynstr_index as u32;
+        }
+
+        // the symbol table: locals first, then globals
+        let out_index = |linker: &Self, (i, j): SymRef| -> u16 {
+            let sym = &linker.inputs[i].syms[j];
+            match sym.shndx.0 {
+                SHN_ABS => SHN_ABS,
+                SHN_COMMON => bss_index,
+                _ => match sym
+                    .shndx
+                    .get()
+                    .and_then(|shndx| linker.inputs[i].file.section_headers.get(shndx))
+                {
+                    Some(sh) => match SectionKind::of(sh) {
+                        Some(SectionKind::Text) => text_index,
+                        Some(SectionKind::ReadOnly) => rodata_index,
+                        Some(SectionKind::Data)
+                            if matches!(sh.r#type, delf::SectionType::NoBits) =>
+                        {
+                            bss_index
+                        }
+                        Some(SectionKind::Data) => data_index,
+                        None => SHN_ABS,
+                    },
+                    None => SHN_ABS,
+                },
+            }
+        };
+        let out_sym = |linker: &Self, strtab: &mut ew::StrTab, sym_ref: SymRef| {
+            let (i, j) = sym_ref;
+            let sym = &linker.inputs[i].syms[j];
+            ew::Sym {
+                name: strtab.add(linker.inputs[i].file.strtab_entry(sym.name)),
+                bind: sym.bind as u8,
+                r#type: sym.r#type as u8,
+                shndx: out_index(linker, sym_ref),
+                value: match linker.defined_value(sym_ref) {
+                    Value::Image(offset) => base + offset,
+                    Value::Absolute(value) => value,
+                },
+                size: sym.size,
+            }
+        };
+
+        let mut strtab = ew::StrTab::default();
+        let mut syms = vec![ew::Sym::default()];
+        for (i, input) in self.inputs.iter().enumerate() {
+            for (j, sym) in input.syms.iter().enumerate() {
+                let named = !input.file.strtab_entry(sym.name).is_empty();
+                let placed = sym
+                    .shndx
+                    .get()
+                    .map(|shndx| self.sections.contains_key(&(i, shndx)))
+                    .unwrap_or(false);
+                if matches!(sym.bind, delf::SymBind::Local) && named && placed {
+                    syms.push(out_sym(&self, &mut strtab, (i, j)));
+                }
+            }
+        }
+        let first_global = syms.len();
+        let mut globals: Vec<_> = self.globals.iter().collect();
+        globals.sort();
+        for (_, &sym_ref) in globals {
+            syms.push(out_sym(&self, &mut strtab, sym_ref));
+        }
+
+        // non-allocated stuff goes after the image
+        let mut out = image;
+        let add_blob = |out: &mut Vec<u8>, contents: &[u8]| -> Span {
+            out.resize(align(out.len() as u64, 8) as usize, 0);
+            let start = out.len() as u64;
+            out.extend(contents);
+            Span {
+                start,
+                end: out.len() as u64,
+            }
+        };
+
+        let mut symtab_contents = Vec::new();
+        for sym in &syms {
+            sym.write(&mut symtab_contents);
+        }
+        let symtab = add_blob(&mut out, &symtab_contents);
+        let strtab = add_blob(&mut out, strtab.as_slice());
+        let non_alloc = |name: u32, r#type: u32, span: &Span, entsize: u64| ew::SectionHeader {
+            name,
+            r#type,
+            offset: span.start,
+            size: span.len(),
+            addralign: 1,
+            entsize,
+            ..Default::default()
+        };
+        let symtab_name = shstrtab.add(b".symtab");
+        let strtab_name = shstrtab.add(b".strtab");
+        let shstrtab_name = shstrtab.add(b".shstrtab");
+        shdrs.push(ew::SectionHeader {
+            link: shdrs.len() as u32 + 1,
+            info: first_global as u32,
+            addralign: 8,
+            ..non_alloc(symtab_name, ew::SHT_SYMTAB, &symtab, ew::SYM_SIZE)
+        });
+        shdrs.push(non_alloc(strtab_name, ew::SHT_STRTAB, &strtab, 0));
+        let shstrndx = shdrs.len() as u16;
+        // add_blob before pushing the header: the name's already in there
+        let shstrtab = add_blob(&mut out, shstrtab.as_slice());
+        shdrs.push(non_alloc(shstrtab_name, ew::SHT_STRTAB, &shstrtab, 0));
+
+        let shoff = align(out.len() as u64, 8);
+        out.resize(shoff as usize, 0);
+        for shdr in &shdrs {
+            shdr.write(&mut out);
+        }
+
+        // program headers
+        let load = |flags: u32, start: u64, file_end: u64, mem_end: u64| ew::ProgramHeader {
+            r#type: ew::PT_LOAD,
+            flags,
+            offset: start,
+            vaddr: base + start,
+            filesz: file_end - start,
+            memsz: mem_end - start,
+            align: PAGE_SIZE,
+        };
+        let mut phdrs = Vec::new();
+        // placeholder for the headers segment, we don't know its size yet
+        phdrs.push(ew::ProgramHeader::default());
+        if text.len() > 0 {
+            phdrs.push(load(ew::PF_R | ew::PF_X, text.start, text.end, text.end));
+        }
+        if rodata_end > rodata_start {
+            phdrs.push(load(ew::PF_R, rodata_start, rodata_end, rodata_end));
+        }
+        if data_end > data_start {
+            phdrs.push(load(ew::PF_R | ew::PF_W, data_start, file_end, data_end));
+        }
+        if pie {
+            phdrs.push(ew::ProgramHeader {
+                r#type: ew::PT_DYNAMIC,
+                flags: ew::PF_R | ew::PF_W,
+                offset: dynamic.start,
+                vaddr: base + dynamic.start,
+                filesz: dynamic.len(),
+                memsz: dynamic.len(),
+                align: 8,
+            });
+        }
+        // don't make the stack executable
+        phdrs.push(ew::ProgramHeader {
+            r#type: ew::PT_GNU_STACK,
+            flags: ew::PF_R | ew::PF_W,
+            align: 16,
+            ..Default::default()
+        });
+        let headers_size = ew::EHDR_SIZE + phdrs.len() as u64 * ew::PHDR_SIZE;
+        phdrs[0] = load(ew::PF_R, 0, headers_size, headers_size);
+
+        let mut headers = Vec::new();
+        ew::FileHeader {
+            r#type: if pie { ew::ET_DYN } else { ew::ET_EXEC },
+            machine: ew::EM_X86_64,
+            entry,
+            phoff: ew::EHDR_SIZE,
+            shoff,
+            phnum: phdrs.len() as u16,
+            shnum: shdrs.len() as u16,
+            shstrndx,
+        }
+        .write(&mut headers);
+        for phdr in &phdrs {
+            phdr.write(&mut headers);
+        }
+        out[..headers.len()].copy_from_slice(&headers);
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use delf::SegmentFlag::{Execute, Read, Write};
+    use enumflags2::BitFlags;
+
+    fn sample(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("samples")
+            .join(name)
+    }
+
+    fn link_samples(names: &[&str], pie: bool) -> delf::File<Vec<u8>> {
+        let inputs: Vec<_> = names.iter().map(|name| sample(name)).collect();
+        let opts = Options {
+            pie,
+            entry: "_start".into(),
+        };
+        let out = link(&inputs, &opts).unwrap();
+        delf::File::parse_or_print_error(out).unwrap()
+    }
+
+    /// (flags, vaddr, filesz, memsz) of each `PT_LOAD`
+    fn loads(file: &delf::File<Vec<u8>>) -> Vec<(BitFlags<delf::SegmentFlag>, u64, u64, u64)> {
+        file.program_headers
+            .iter()
+            .filter(|ph| ph.r#type == delf::SegmentType::Load)
+            .map(|ph| (ph.flags, ph.vaddr.0, ph.filesz.0, ph.memsz.0))
+            .collect()
+    }
+
+    fn section<'a>(file: &'a delf::File<Vec<u8>>, name: &str) -> &'a delf::SectionHeader {
+        file.section_headers
+            .iter()
+            .find(|sh| file.shstrtab_entry(sh.name) == name.as_bytes())
+            .unwrap()
+    }
+
+    fn symbol(file: &delf::File<Vec<u8>>, name: &str) -> u64 {
+        let syms = file.read_symtab_entries().unwrap();
+        let sym = syms
+            .iter()
+            .find(|sym| file.strtab_entry(sym.name) == name.as_bytes())
+            .unwrap();
+        sym.value.0
+    }
+
+    #[test]
+    fn static_layout() {
+        // bss.o's _start takes the address of `zero`, in its .bss
+        let file = link_samples(&["bss.o", "msg.o"], false);
+        assert_eq!(file.typ, delf::Type::Exec);
+        assert_eq!(file.entry_point.0, STATIC_BASE + PAGE_SIZE);
+        assert_eq!(symbol(&file, "_start"), file.entry_point.0);
+
+        // headers (and four program headers, one is PT_GNU_STACK), code,
+        // then msg.o's .data, padded for the empty GOT, and bss.o's .bss
+        let data = STATIC_BASE + 2 * PAGE_SIZE;
+        let bss = section(&file, ".bss");
+        assert_eq!(bss.r#type, delf::SectionType::NoBits);
+        assert_eq!(bss.size.0, 16 * 8);
+        let data_mem_end = bss.addr.0 + bss.size.0;
+        assert_eq!(
+            loads(&file),
+            [
+                (BitFlags::from(Read), STATIC_BASE, 64 + 4 * 56, 64 + 4 * 56),
+                (Read | Execute, STATIC_BASE + PAGE_SIZE, 0x11, 0x11),
+                (Read | Write, data, 40, data_mem_end - data),
+            ]
+        );
+        assert_eq!(symbol(&file, "zero"), bss.addr.0);
+
+        // `lea rax, [rel zero]`: the displacement is after 3 bytes of opcode
+        let text = section(&file, ".text");
+        let at = text.offset.0 as usize + 3;
+        let disp = i32::from_le_bytes(file.input[at..at + 4].try_into().unwrap());
+        let next = text.addr.0 + 3 + 4;
+        assert_eq!(next.wrapping_add(disp as u64), bss.addr.0);
+    }
+
+    #[test]
+    fn pie_relocations() {
+        let file = link_samples(&["hello-pie.o", "msg.o"], true);
+        assert_eq!(file.typ, delf::Type::Dyn);
+        assert_eq!(file.entry_point.0, PAGE_SIZE);
+        assert!(file.segment_of_type(delf::SegmentType::Dynamic).is_some());
+        assert!(file.segment_of_type(delf::SegmentType::Interp).is_none());
+
+        // `mov rsi, msg` has a 64-bit immediate, 2 bytes into the
+        // instruction, which is 5 bytes into `_start`. That `msg` is
+        // hello-pie.o's own, not msg.o's global one.
+        let msg = section(&file, ".data").addr.0;
+        let imm = file.entry_point.0 + 7;
+        let relas = file.read_rela_entries().unwrap();
+        let relas: Vec<_> = relas
+            .iter()
+            .map(|rela| (rela.r#type as u32, rela.offset.0, rela.addend.0))
+            .collect();
+        assert_eq!(relas, [(ew::R_X86_64_RELATIVE, imm, msg)]);
+
+        // the image has the unrelocated value, for a base of zero
+        let at = imm as usize;
+        let written = u64::from_le_bytes(file.input[at..at + 8].try_into().unwrap());
+        assert_eq!(written, msg);
+    }
+
+    #[test]
+    fn duplicate_symbols() {
+        // hello-pie.o and bss.o both define _start
+        let inputs = ["hello-pie.o", "msg.o", "bss.o"].map(sample);
+        let opts = Options {
+            pie: true,
+            entry: "_start".into(),
+        };
+        match link(&inputs, &opts) {
+            Err(LinkError::DuplicateSymbol(path, name, prev)) => {
+                assert_eq!(
+                    (path, name, prev),
+                    (inputs[2].clone(), "_start".into(), inputs[0].clone())
+                );
+            }
+            other => panic!("expected a duplicate symbol, got {:?}", other.map(|_| ())),
+        }
+    }
+}
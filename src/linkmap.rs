@@ -0,0 +1,201 @@
+//! `struct link_map`, as seen by auditors and debuggers. glibc's own has
+//! a lot more fields, but only these five are public ABI. Debuggers find
+//! the chain of them through `struct r_debug`.
+
+use std::{ffi::CString, os::raw::c_char, path::Path};
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct LinkMap {
+    /// Difference between the addresses in the ELF file and in memory
+    pub l_addr: u64,
+    /// Absolute path of the object
+    pub l_name: *const c_char,
+    /// Address of the dynamic section, if any
+    pub l_ld: u64,
+    pub l_next: *mut LinkMap,
+    pub l_prev: *mut LinkMap,
+
+    // not part of the ABI, nobody else looks here
+    name: CString,
+}
+
+impl LinkMap {
+    /// Boxed, because whoever we hand it to will hold on to its address
+    pub fn new(path: &Path, base: delf::Addr, dynamic: Option<delf::Addr>) -> Box<Self> {
+        use std::os::unix::ffi::OsStrExt;
+
+        // paths can't contain NUL bytes, so this never falls back
+        let name = CString::new(path.as_os_str().as_bytes()).unwrap_or_default();
+        Box::new(Self {
+            l_addr: base.0,
+            l_name: name.as_ptr(),
+            l_ld: dynamic.map_or(0, |addr| addr.0),
+            l_next: std::ptr::null_mut(),
+            l_prev: std::ptr::null_mut(),
+            name,
+        })
+    }
+}
+
+/// `r_debug::r_state`
+pub const RT_CONSISTENT: i32 = 0;
+pub const RT_ADD: i32 = 1;
+
+/// `DT_DEBUG`, whose value the dynamic loader points at its `r_debug`
+pub const DT_DEBUG: u64 = 21;
+
+/// glibc's `struct r_debug_extended`: a plain `struct r_debug` with a
+/// link to the next namespace's. Debuggers find the first one through
+/// the executable's `DT_DEBUG`, set a breakpoint on `r_brk`, and re-read
+/// the `link_map` chain every time it's hit.
+#[repr(C)]
+#[derive(Debug)]
+pub struct RDebug {
+    r_version: i32,
+    r_map: *mut LinkMap,
+    r_brk: u64,
+    r_state: i32,
+    r_ldbase: u64,
+    r_next: *mut RDebug,
+}
+
+/// Debuggers set a breakpoint here. It has to actually exist, and be
+/// called, so it can't be inlined or optimized out.
+#[no_mangle]
+#[inline(never)]
+pub extern "C" fn _dl_debug_state() {
+    unsafe { std::arch::asm!("", options(nomem, nostack, preserves_flags)) };
+}
+
+extern "C" {
+    // defined by the linker in every dynamically-linked executable,
+    // including ours
+    #[linkage = "extern_weak"]
+    static _DYNAMIC: *const [u64; 2];
+}
+
+/// The `r_debug` for the objects we load, and its `link_map` chain.
+///
+/// Our own process has an `r_debug` too, courtesy of the dynamic loader
+/// that loaded elk, and that's the one a debugger attached to us reads.
+/// If it's recent enough to have `r_next` (glibc 2.35+), we hang ours off
+/// it, like an extra namespace - splicing our link maps into its chain
+/// instead would have it walk them as full glibc `link_map`s. That only
+/// happens in `publish`, once we're about to run the program: a debugger
+/// attached to `elk ldd` has no business seeing its objects.
+#[derive(Debug)]
+pub struct DebugMaps {
+    r_debug: Box<RDebug>,
+    tail: *mut LinkMap,
+    /// The host `r_debug` we're linked from, once published
+    host: Option<*mut RDebug>,
+}
+
+impl DebugMaps {
+    pub fn new() -> Self {
+        Self {
+            r_debug: Box::new(RDebug {
+                r_version: 2,
+                r_map: std::ptr::null_mut(),
+                r_brk: _dl_debug_state as *const () as u64,
+                r_state: RT_CONSISTENT,
+                r_ldbase: 0,
+                r_next: std::ptr::null_mut(),
+            }),
+            tail: std::ptr::null_mut(),
+            host: None,
+        }
+    }
+
+    /// Hangs our `r_debug` off the host's, and lets a debugger attached
+    /// to elk know. From then on, it hears about every change.
+    pub fn publish(&mut self) {
+        if self.host.is_some() {
+            return;
+        }
+        let host = match unsafe { host_r_debug() } {
+            Some(host) => host,
+            None => return,
+        };
+        self.r_debug.r_state = RT_ADD;
+        unsafe { call_brk(host) };
+        let last = unsafe {
+            let mut last = host;
+            while !(*last).r_next.is_null() {
+                last = (*last).r_next;
+            }
+            (*last).r_next = &mut *self.r_debug;
+            last
+        };
+        self.host = Some(last);
+        self.notify(RT_CONSISTENT);
+    }
+
+    /// What goes in the executable's `DT_DEBUG`
+    pub fn r_debug(&mut self) -> *mut RDebug {
+        &mut *self.r_debug
+    }
+
+    /// Appends `map` to the chain, and lets debuggers know. `map` must
+    /// stay put (and alive) for as long as we do.
+    pub fn add(&mut self, map: &mut LinkMap) {
+        self.notify(RT_ADD);
+        map.l_prev = self.tail;
+        map.l_next = std::ptr::null_mut();
+        if self.tail.is_null() {
+            self.r_debug.r_map = map;
+        } else {
+            unsafe { (*self.tail).l_next = map };
+        }
+        self.tail = map;
+        self.notify(RT_CONSISTENT);
+    }
+
+    fn notify(&mut self, state: i32) {
+        self.r_debug.r_state = state;
+        _dl_debug_state();
+        // a debugger attached to elk only watches the host's breakpoint
+        if let Some(host) = self.host {
+            unsafe { call_brk(host) };
+        }
+    }
+}
+
+unsafe fn call_brk(r_debug: *mut RDebug) {
+    let brk: extern "C" fn() = std::mem::transmute((*r_debug).r_brk as usize);
+    brk();
+}
+
+impl Drop for DebugMaps {
+    fn drop(&mut self) {
+        // our link maps are going away, don't leave the host pointing at them
+        if let Some(host) = self.host {
+            unsafe {
+                if std::ptr::eq((*host).r_next, &*self.r_debug) {
+                    (*host).r_next = self.r_debug.r_next;
+                }
+            }
+        }
+    }
+}
+
+/// Our own process's `r_debug`, if the dynamic loader filled in our
+/// `DT_DEBUG`, and it supports `r_next`.
+unsafe fn host_r_debug() -> Option<*mut RDebug> {
+    if _DYNAMIC.is_null() {
+        return None;
+    }
+    let mut entry = _DYNAMIC;
+    while (*entry)[0] != 0 {
+        if (*entry)[0] == DT_DEBUG {
+            let r_debug = (*entry)[1] as *mut RDebug;
+            return match r_debug.as_ref() {
+                Some(r) if r.r_version >= 2 && r.r_brk != 0 => Some(r_debug),
+                _ => None,
+            };
+        }
+        entry = entry.add(1);
+    }
+    None
+}
@@ -1,11 +1,30 @@
 #![feature(asm)]
+#![feature(linkage)]
 
 use core::str;
-use std::error::Error;
-
+use std::{error::Error, path::Path};
+
+mod arch;
+mod audit;
+mod buildid;
+mod debuginfo;
+mod diagnostics;
+mod dig;
+mod dwarf;
+mod elfwrite;
+mod filemap;
+mod info;
+mod ldd;
+mod link;
+mod linkmap;
+mod mem;
 mod name;
 mod process;
 mod procfs;
+mod reloc_cache;
+mod relocatable;
+mod trace;
+mod versions;
 
 use argh::FromArgs;
 
@@ -22,6 +41,12 @@ enum SubCommand {
     Autosym(AutosymArgs),
     Run(RunArgs),
     Dig(DigArgs),
+    Link(LinkArgs),
+    Ldd(LddArgs),
+    Bindings(BindingsArgs),
+    Check(CheckArgs),
+    Mem(MemArgs),
+    Info(InfoArgs),
 }
 
 #[derive(FromArgs, PartialEq, Debug)]
@@ -34,6 +59,23 @@ struct DigArgs {
     #[argh(option)]
     /// the address to look for
     addr: u64,
+    #[argh(option, default = "Format::Text")]
+    /// output format: text or json (defaults to text)
+    format: Format,
+    #[argh(option)]
+    /// hexdump this many bytes at the address
+    dump: Option<usize>,
+    #[argh(option, long = "as")]
+    /// read the value at the address, as u64, cstr or ptr
+    read_as: Option<dig::ReadAs>,
+    #[argh(option, default = "0")]
+    /// follow pointers this many levels deep, digging into each address
+    /// they point to (implies --as ptr)
+    follow: u32,
+    #[argh(option)]
+    /// also look for separate debug files in this directory (can be
+    /// repeated, /usr/lib/debug is always searched)
+    debug_dir: Vec<String>,
 }
 
 #[derive(FromArgs, PartialEq, Debug)]
@@ -44,14 +86,74 @@ struct AutosymArgs {
     #[argh(positional)]
     /// the PID of the process to examine
     pid: u32,
+    #[argh(option, default = "Format::Text")]
+    /// output format: text (GDB commands) or json (defaults to text)
+    format: Format,
+    #[argh(option)]
+    /// also look for separate debug files in this directory (can be
+    /// repeated, /usr/lib/debug is always searched)
+    debug_dir: Vec<String>,
+}
+
+/// Output format for commands that have a machine-readable one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("unknown format {:?}, expected text or json", s)),
+        }
+    }
 }
 
 #[derive(FromArgs, PartialEq, Debug)]
 #[argh(subcommand, name = "run")]
 /// Load and run an ELF executable
 struct RunArgs {
+    #[argh(switch)]
+    /// load and relocate everything, then print a JSON report instead
+    /// of running the program. Nothing from the objects runs, not even
+    /// IFUNC resolvers, and LD_AUDIT is ignored (--audit still applies)
+    dry_run: bool,
+
+    #[argh(option)]
+    /// cache symbol bindings in this directory, and reuse them when
+    /// running the same set of objects again
+    reloc_cache: Option<String>,
+
+    #[argh(option)]
+    /// redirect a symbol to a function from another object, as
+    /// sym=path/to/lib.so:replacement. If lib.so defines a pointer
+    /// `__real_sym`, it's set to the original. Can be repeated.
+    intercept: Vec<InterceptSpec>,
+
+    #[argh(option)]
+    /// load an rtld-audit library, in addition to those listed in
+    /// LD_AUDIT. Can be repeated.
+    audit: Vec<String>,
+
+    #[argh(option)]
+    /// log calls to (and returns from) functions whose name matches
+    /// this glob, like `str*`. C++ exceptions and longjmp through a
+    /// traced call aren't supported
+    trace_calls: Option<String>,
+
+    #[argh(option)]
+    /// argv[0] for the program, defaults to the executable's path (or
+    /// to `memfd:stdin` when reading it from standard input)
+    argv0: Option<String>,
+
     #[argh(positional)]
-    /// the absolute path of an executable file to load and run
+    /// the absolute path of an executable file to load and run, or `-`
+    /// to read it from standard input
     exec_path: String,
 
     #[argh(positional)]
@@ -59,6 +161,107 @@ struct RunArgs {
     args: Vec<String>,
 }
 
+/// A `--intercept` argument
+#[derive(PartialEq, Debug)]
+struct InterceptSpec {
+    symbol: String,
+    lib: String,
+    replacement: String,
+}
+
+impl std::str::FromStr for InterceptSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("expected sym=lib.so:replacement, got {:?}", s);
+        let (symbol, rest) = s.split_once('=').ok_or_else(err)?;
+        let (lib, replacement) = rest.rsplit_once(':').ok_or_else(err)?;
+        if symbol.is_empty() || lib.is_empty() || replacement.is_empty() {
+            return Err(err());
+        }
+        Ok(Self {
+            symbol: symbol.into(),
+            lib: lib.into(),
+            replacement: replacement.into(),
+        })
+    }
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand, name = "link")]
+/// Link relocatable objects into an executable
+struct LinkArgs {
+    #[argh(option, short = 'o')]
+    /// where to write the executable
+    output: String,
+
+    #[argh(switch)]
+    /// produce a position-independent executable. It has no interpreter
+    /// and doesn't relocate itself, so it only runs under `elk run`
+    pie: bool,
+
+    #[argh(option, default = "String::from(\"_start\")")]
+    /// the entry point symbol (defaults to _start)
+    entry: String,
+
+    #[argh(positional)]
+    /// the relocatable objects to link
+    inputs: Vec<String>,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand, name = "ldd")]
+/// Show the dependency tree of an ELF object, without ever running it
+struct LddArgs {
+    #[argh(option, default = "ldd::Format::Tree")]
+    /// output format: tree, flat, dot or json (defaults to tree)
+    format: ldd::Format,
+
+    #[argh(positional)]
+    /// the ELF object to examine
+    exec_path: String,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand, name = "bindings")]
+/// Show which object provides each imported symbol, and which
+/// definitions it interposes
+struct BindingsArgs {
+    #[argh(positional)]
+    /// the executable to examine
+    exec_path: String,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand, name = "check")]
+/// Verify that every symbol (and symbol version) an executable and its
+/// dependencies need can be resolved, without running anything.
+/// Exits with status 1 if some can't.
+struct CheckArgs {
+    #[argh(positional)]
+    /// the executable to check
+    exec_path: String,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand, name = "mem")]
+/// Show how much memory each ELF object mapped in a process uses
+struct MemArgs {
+    #[argh(option)]
+    /// the PID of the process to examine
+    pid: u32,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand, name = "info")]
+/// Show how a process was started: command line, environment, auxiliary
+/// vector and status
+struct InfoArgs {
+    #[argh(option)]
+    /// the PID of the process to examine
+    pid: u32,
+}
+
 fn main() {
     if let Err(e) = do_main() {
         eprintln!("Fatal error: {}", e);
@@ -73,6 +276,12 @@ fn do_main() -> Result<(), Box<dyn Error>> {
         SubCommand::Run(args) => cmd_run(args),
         SubCommand::Autosym(args) => cmd_autosym(args),
         SubCommand::Dig(args) => cmd_dig(args),
+        SubCommand::Link(args) => cmd_link(args),
+        SubCommand::Ldd(args) => ldd::run(&args.exec_path, args.format),
+        SubCommand::Bindings(args) => cmd_bindings(args),
+        SubCommand::Check(args) => cmd_check(args),
+        SubCommand::Mem(args) => mem::run(args.pid),
+        SubCommand::Info(args) => info::run(args.pid),
     }
 }
 
@@ -105,157 +314,124 @@ where
 
 // Does the same as previously, just refactored to use `with_mappings`:
 fn cmd_autosym(args: AutosymArgs) -> Result<(), Box<dyn Error>> {
-    fn analyze(mapping: &procfs::Mapping) -> Result<(), AnyError> {
+    #[derive(serde::Serialize)]
+    struct Object {
+        path: String,
+        /// The separate debug file to load symbols from, if there is one
+        debug_file: Option<String>,
+        /// Where each (allocated) section ended up in memory
+        sections: Vec<Section>,
+    }
+    #[derive(serde::Serialize)]
+    struct Section {
+        name: String,
+        addr: u64,
+    }
+
+    fn analyze(
+        mapping: &procfs::Mapping,
+        debug: &debuginfo::Search,
+    ) -> Result<Option<Object>, AnyError> {
         if mapping.deleted {
             // skip deleted mappings
-            return Ok(());
+            return Ok(None);
         }
 
-        let path = match mapping.source {
+        let path = match &mapping.source {
             procfs::Source::File(path) => path,
-            _ => return Ok(()),
+            _ => return Ok(None),
         };
 
-        let contents = std::fs::read(path)?;
-        let file = match delf::File::parse_or_print_error(&contents) {
+        let contents = std::fs::read(path.as_ref())?;
+        let file = match dig::parse_or_eprint_error(&contents) {
             Some(x) => x,
-            _ => return Ok(()),
+            _ => return Ok(None),
+        };
+        let bias = match dig::load_bias(&file, mapping) {
+            Some(bias) => bias,
+            None => return Ok(None),
         };
 
-        let section = match file
+        // sections with an address of 0 aren't loaded at all. the others
+        // (.bss included) are wherever the object was moved, plus their
+        // address.
+        let sections = file
             .section_headers
             .iter()
-            .find(|sh| file.shstrtab_entry(sh.name) == b".text")
-        {
-            Some(section) => section,
-            _ => return Ok(()),
-        };
-
-        let textaddress = mapping.addr_range.start - mapping.offset + section.offset;
-        println!("add-symbol-file {:?} 0x{:?}", path, textaddress);
-
-        Ok(())
+            .filter(|sh| sh.addr.0 != 0)
+            .map(|sh| Section {
+                name: String::from_utf8_lossy(file.shstrtab_entry(sh.name)).into_owned(),
+                addr: (bias + sh.addr).0,
+            })
+            .collect();
+        // the debug file has the same sections at the same addresses,
+        // gdb only needs to be told to read it instead
+        let debug_file = debug
+            .find(Path::new(&**path), &file)
+            .map(|debug_file| debug_file.path.display().to_string());
+        Ok(Some(Object {
+            path: path.to_string(),
+            debug_file,
+            sections,
+        }))
     }
 
+    let format = args.format;
+    let debug = debuginfo::Search::new(&args.debug_dir);
     with_mappings(args.pid, |mappings| {
+        let mut objects = Vec::new();
         for mapping in mappings.iter().filter(|m| m.perms.x && m.source.is_file()) {
-            analyze(mapping)?;
+            objects.extend(analyze(mapping, &debug)?);
         }
-        Ok(())
-    })
-}
 
-struct Size(pub delf::Addr);
-use std::fmt;
-impl fmt::Debug for Size {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        const KIB: u64 = 1024;
-        const MIB: u64 = 1024 * KIB;
-
-        let x = (self.0).0;
-        #[allow(overlapping_range_endpoints)]
-        #[allow(clippy::clippy::match_overlapping_arm)]
-        match x {
-            0..=KIB => write!(f, "{} B", x),
-            KIB..=MIB => write!(f, "{} KiB", x / KIB),
-            _ => write!(f, "{} MiB", x / MIB),
+        match format {
+            Format::Text => {
+                for object in &objects {
+                    if let Some(text) = object.sections.iter().find(|s| s.name == ".text") {
+                        println!(
+                            "add-symbol-file {:?} 0x{:?}",
+                            object.debug_file.as_ref().unwrap_or(&object.path),
+                            delf::Addr(text.addr)
+                        );
+                    }
+                }
+            }
+            Format::Json => println!("{}", serde_json::to_string_pretty(&objects)?),
         }
-    }
+        Ok(())
+    })
 }
 
 fn cmd_dig(args: DigArgs) -> Result<(), Box<dyn Error>> {
     let addr = delf::Addr(args.addr);
+    let format = args.format;
+    let opts = dig::ReadOptions {
+        dump: args.dump,
+        read_as: args.read_as,
+        follow: args.follow,
+    };
+    if let Err(e) = opts.check() {
+        // the same way argh reports bad arguments
+        eprintln!("{}\nRun elk dig --help for more information.", e);
+        std::process::exit(1)
+    }
+    let debug = debuginfo::Search::new(&args.debug_dir);
+    let mem = if opts.reads_memory() {
+        Some(procfs::ProcessMemory::open(args.pid)?)
+    } else {
+        None
+    };
 
     with_mappings(args.pid, |mappings| {
-        if let Some(mapping) = mappings.iter().find(|m| m.addr_range.contains(&addr)) {
-            println!("Mapped {:?} from {:?}", mapping.perms, mapping.source);
-            println!(
-                "(Map range: {:?}, {:?} total)",
-                mapping.addr_range,
-                Size(mapping.addr_range.end - mapping.addr_range.start)
-            );
-
-            // we've used this pattern a bunch of times already, but in case you don't
-            // see the point: this avoids deep indentation. If we didn't use that, we'd
-            // soon find ourselves several levels deep into `if let` statements, whereas
-            // really, if we get a `None` or an `Err`, we just want to bail out early
-            // and gracefully.
-            let path = match mapping.source {
-                procfs::Source::File(path) => path,
-                // if it's not a file mapping, bail out
-                _ => return Ok(()),
-            };
-
-            let contents = std::fs::read(path)?;
-            let file = match delf::File::parse_or_print_error(&contents) {
-                Some(x) => x,
-                // if we couldn't parse the file, a message was printed,
-                // and we can bail out
-                _ => return Ok(()),
-            };
-
-            let offset = addr + mapping.offset - mapping.addr_range.start;
-
-            // Segments (loader view, `delf::ProgramHeader` type) determine what parts
-            // of the ELF file get mapped where, so we try to determine which
-            // segment this mapping corresponds to.
-            let segment = match file
-                .program_headers
-                .iter()
-                .find(|ph| ph.file_range().contains(&offset))
-            {
-                Some(s) => s,
-                None => return Ok(()),
-            };
-
-            // This is the main thing I wanted `elk dig` to do - display
-            // the virtual address *for this ELF object*, so that it matches
-            // up with the output from `objdump` and `readelf`
-            let vaddr = offset + segment.vaddr - segment.offset;
-            println!("Object virtual address: {:?}", vaddr);
-
-            // But we can go a bit further: we can find to which section
-            // this corresponds, and show *where* in this section the
-            // dug address was.
-            let section = match file
-                .section_headers
-                .iter()
-                .find(|sh| sh.mem_range().contains(&vaddr))
-            {
-                Some(s) => s,
-                None => return Ok(()),
-            };
-
-            let name = file.shstrtab_entry(section.name);
-            let sect_offset = vaddr - section.addr;
-            println!(
-                "At section {:?} + {} (0x{:x})",
-                String::from_utf8_lossy(name),
-                sect_offset.0,
-                sect_offset.0
-            );
-
-            // And, even further, we can try to map it to a symbol. This is all
-            // stuff GDB does in its `info addr 0xABCD` command, but isn't it
-            // satisfying to re-implement it ourselves?
-            match file.read_symtab_entries() {
-                Ok(syms) => {
-                    for sym in &syms {
-                        let sym_range = sym.value..(sym.value + delf::Addr(sym.size));
-                        // the first check is for zero-sized symbols, since `sym_range`
-                        // ends up being a 0-sized range.
-                        if sym.value == vaddr || sym_range.contains(&vaddr) {
-                            let sym_offset = vaddr - sym.value;
-                            let sym_name = String::from_utf8_lossy(file.strtab_entry(sym.name));
-                            println!(
-                                "At symbol {:?} + {} (0x{:x})",
-                                sym_name, sym_offset.0, sym_offset.0
-                            );
-                        }
-                    }
+        let report = dig::dig(mappings, addr, mem.as_ref(), &opts, &debug)?;
+        match format {
+            Format::Text => {
+                if let Some(report) = report {
+                    print!("{}", report);
                 }
-                Err(e) => println!("Could not read syms: {:?}", e),
             }
+            // unmapped addresses are `null`
+            Format::Json => println!("{}", serde_json::to_string_pretty(&report)?),
         }
         Ok(())
     })
@@ -263,12 +439,64 @@ fn cmd_dig(args: DigArgs) -> Result<(), Box<dyn Error>> {
 
 fn cmd_run(args: RunArgs) -> Result<(), Box<dyn Error>> {
     // these are the usual steps
+    let dry_run = args.dry_run;
     let mut proc = process::Process::new();
-    let exec_index = proc.load_object_and_dependencies(&args.exec_path)?;
+    proc.record_relocations(dry_run);
+    proc.map_executable(!dry_run);
+    proc.relocation_cache(args.reloc_cache.as_ref().map(Into::into));
+
+    // auditors run code of their own, a dry run only loads the ones
+    // it's explicitly asked to
+    let mut audit: Vec<String> = std::env::var("LD_AUDIT")
+        .ok()
+        .filter(|_| !dry_run)
+        .map(|var| {
+            var.split(':')
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default();
+    audit.extend(args.audit.iter().cloned());
+    proc.audit(&audit)?;
+    if let Some(pattern) = &args.trace_calls {
+        proc.trace_calls(pattern);
+    }
+    let exec_index = if args.exec_path == "-" {
+        use std::io::Read;
+        let mut input = Vec::new();
+        std::io::stdin().read_to_end(&mut input)?;
+        let index = proc.load_object_from_bytes("stdin", input)?;
+        proc.load_dependencies(index)?;
+        index
+    } else {
+        proc.load_object_and_dependencies(&args.exec_path)?
+    };
+    // like with fexecve, a program that came from stdin has no path of its
+    // own - it goes by its memfd's name unless told otherwise.
+    let argv0 = match &args.argv0 {
+        Some(argv0) => argv0.clone(),
+        None if args.exec_path == "-" => proc.state.loader.objects[exec_index]
+            .path
+            .to_string_lossy()
+            .into_owned(),
+        None => args.exec_path.clone(),
+    };
+    for spec in &args.intercept {
+        // several intercepts may share a library, only load it once
+        let loaded = std::fs::canonicalize(&spec.lib)
+            .ok()
+            .and_then(|path| proc.state.loader.objects_by_path.get(&path).copied());
+        let index = match loaded {
+            Some(index) => index,
+            None => proc.load_object_and_dependencies(&spec.lib)?,
+        };
+        proc.intercept_with(&spec.symbol, index, &spec.replacement)?;
+    }
 
     // each of these now returns a different type - we simply
     // shadow the previous `proc` with it.
-    let proc = proc.allocate_tls();
+    let proc = proc.allocate_tls()?;
     let proc = proc.apply_relocations()?;
     let proc = proc.initialize_tls();
     let proc = proc.adjust_protections()?;
@@ -277,7 +505,7 @@ fn cmd_run(args: RunArgs) -> Result<(), Box<dyn Error>> {
 
     // the first argument is typically the path to the executable itself.
     // that's not something `argh` gives us, so let's add it ourselves
-    let args = std::iter::once(CString::new(args.exec_path.as_bytes()).unwrap())
+    let args = std::iter::once(CString::new(argv0.as_bytes()).unwrap())
         .chain(
             args.args
                 .iter()
@@ -297,11 +525,138 @@ fn cmd_run(args: RunArgs) -> Result<(), Box<dyn Error>> {
         // right now we pass all *our* auxiliary vectors to the underlying process.
         // note that some of those aren't quite correct - there's a `Base` auxiliary
         // vector, for example, which is set to `elk`'s base address, not `echidna`'s!
-        auxv: process::Auxv::get_known(),
+        auxv: process::Auxv::get_known(proc.arch()),
     };
+
+    if dry_run {
+        let report = proc.dry_run(&opts);
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
     proc.start(&opts);
 }
 
+fn cmd_bindings(args: BindingsArgs) -> Result<(), Box<dyn Error>> {
+    let mut proc = process::Process::new();
+    proc.map_executable(false);
+    proc.load_object_and_dependencies(&args.exec_path)?;
+
+    let objects = &proc.state.loader.objects;
+    let path = |index: usize| objects[index].path.display();
+
+    let bindings = proc.bindings();
+    let mut last_object = None;
+    for binding in &bindings {
+        if last_object != Some(binding.object) {
+            println!("{}:", path(binding.object));
+            last_object = Some(binding.object);
+        }
+
+        let weak = if binding.weak_ref { " (weak)" } else { "" };
+        match &binding.provider {
+            Some(provider) => println!(
+                "    {}{} => {}",
+                binding.symbol,
+                weak,
+                path(provider.object)
+            ),
+            None => println!("    {}{} => (undefined)", binding.symbol, weak),
+        }
+        for shadowed in &binding.shadowed {
+            let weak = if shadowed.weak { " (weak)" } else { "" };
+            println!("        interposes {}{}", path(shadowed.object), weak);
+        }
+        if binding.duplicate_strong {
+            println!("        WARNING: more than one strong definition");
+        }
+    }
+
+    let duplicates = proc.duplicate_definitions();
+    if !duplicates.is_empty() {
+        println!();
+        println!("Duplicate strong definitions:");
+        for (symbol, defined_in) in &duplicates {
+            println!("    {}", symbol);
+            for &index in defined_in {
+                println!("        {}", path(index));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn cmd_check(args: CheckArgs) -> Result<(), Box<dyn Error>> {
+    use process::Unresolved;
+
+    let mut proc = process::Process::new();
+    proc.map_executable(false);
+    proc.load_object_and_dependencies(&args.exec_path)?;
+
+    let arch = proc.arch();
+    let objects = &proc.state.loader.objects;
+    let unresolved = proc.unresolved();
+    if unresolved.is_empty() {
+        println!("{}: all symbols resolved", args.exec_path);
+        return Ok(());
+    }
+
+    let mut last_object = None;
+    for problem in &unresolved {
+        let object = match problem {
+            Unresolved::Symbol { object, .. } | Unresolved::Version { object, .. } => *object,
+        };
+        if last_object != Some(object) {
+            println!("{}:", objects[object].path.display());
+            last_object = Some(object);
+        }
+
+        match problem {
+            Unresolved::Symbol {
+                name,
+                version,
+                r#type,
+                ..
+            } => {
+                let name = match version {
+                    Some(version) => format!("{}@{}", name, version),
+                    None => name.clone(),
+                };
+                let kind = match arch.rel_kind(*r#type) {
+                    Some(kind) => format!("{:?}", kind),
+                    None => format!("type {}", r#type),
+                };
+                println!("    undefined symbol {} ({} relocation)", name, kind);
+            }
+            Unresolved::Version { file, version, .. } => {
+                println!("    version {} not found in {}", version, file);
+            }
+        }
+    }
+
+    eprintln!("{} unresolved symbol(s) or version(s)", unresolved.len());
+    std::process::exit(1)
+}
+
+fn cmd_link(args: LinkArgs) -> Result<(), Box<dyn Error>> {
+    let opts = link::Options {
+        pie: args.pie,
+        entry: args.entry,
+    };
+    let contents = link::link(&args.inputs, &opts)?;
+
+    use std::{io::Write, os::unix::fs::OpenOptionsExt};
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o755)
+        .open(&args.output)?
+        .write_all(&contents)?;
+
+    Ok(())
+}
+
 fn _pause(reason: &str) -> Result<(), Box<dyn Error>> {
     println!("Press Enter to {}...", reason);
     {
@@ -339,49 +694,3 @@ fn _ndisasm(code: &[u8], origin: delf::Addr) -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
-
-#[allow(named_asm_labels)]
-#[inline(never)]
-unsafe fn jmp(entry_point: *const u8, stack_contents: *const u64, qword_count: usize) -> ! {
-    use std::arch::asm;
-    asm!(
-        // allocate (qword_count * 8) bytes
-        "mov {tmp}, {qword_count}",
-        "sal {tmp}, 3",
-        "sub rsp, {tmp}",
-
-        "l1:",
-        // start at i = (n-1)
-        "sub {qword_count}, 1",
-        // copy qwords to the stack
-        "mov {tmp}, QWORD PTR [{stack_contents}+{qword_count}*8]",
-        "mov QWORD PTR [rsp+{qword_count}*8], {tmp}",
-        // loop if i isn't zero, break otherwise
-        "test {qword_count}, {qword_count}",
-        "jnz l1",
-
-        "jmp {entry_point}",
-
-        entry_point = in(reg) entry_point,
-        stack_contents = in(reg) stack_contents,
-        qword_count = in(reg) qword_count,
-        tmp = out(reg) _,
-    );
-
-    asm!("ud2", options(noreturn));
-}
-
-#[inline(never)]
-unsafe fn set_fs(addr: u64) {
-    let syscall_number: u64 = 158;
-    let arch_set_fs: u64 = 0x1002;
-
-    use std::arch::asm;
-    asm!(
-        "syscall",
-        inout("rax") syscall_number => _,
-        in("rdi") arch_set_fs,
-        in("rsi") addr,
-        lateout("rcx") _, lateout("r11") _,
-    )
-}
@@ -0,0 +1,156 @@
+//! `elk mem`: where a process's memory goes, per ELF object. Numbers come
+//! from `/proc/:pid/smaps`, and get attributed to objects by mapping:
+//! file-backed mappings of an ELF file belong to it, and so does the
+//! anonymous mapping right after its data segment, which is where the
+//! part of `.bss` that doesn't fit in the last file page ends up.
+
+use std::{collections::HashMap, error::Error, fmt, fs, io::Read};
+
+use crate::procfs::{self, MemStats, SmapsEntry};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SegmentKind {
+    Text,
+    RoData,
+    Data,
+    /// Anonymous memory right after an object's data segment
+    Bss,
+}
+
+impl fmt::Display for SegmentKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Text => "text",
+            Self::RoData => "rodata",
+            Self::Data => "data",
+            Self::Bss => "bss",
+        })
+    }
+}
+
+impl SegmentKind {
+    /// What a file-backed mapping of an ELF object holds, judging by its
+    /// permissions. RELRO ends up as rodata, since that's what it is by
+    /// the time anyone looks.
+    fn of(perms: &procfs::Perms) -> Option<Self> {
+        match perms {
+            procfs::Perms { x: true, .. } => Some(Self::Text),
+            procfs::Perms { w: true, .. } => Some(Self::Data),
+            procfs::Perms { r: true, .. } => Some(Self::RoData),
+            // gaps between segments, nothing's ever mapped there
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ObjectUsage {
+    total: MemStats,
+    segments: HashMap<SegmentKind, MemStats>,
+}
+
+pub fn run(pid: u32) -> Result<(), Box<dyn Error>> {
+    let smaps = fs::read_to_string(format!("/proc/{}/smaps", pid))?;
+    let entries = match procfs::smaps(&smaps) {
+        Ok((_, entries)) => entries,
+        // parsing errors borrow the input, format them early
+        Err(e) => return Err(format!("parsing smaps failed: {:?}", e).into()),
+    };
+
+    let mut is_elf = HashMap::new();
+    let mut objects: HashMap<&str, ObjectUsage> = HashMap::new();
+    let mut other: HashMap<String, MemStats> = HashMap::new();
+    // the ELF object that owns the previous mapping, if it was a data segment
+    let mut last_data: Option<(&str, delf::Addr)> = None;
+
+    for entry in &entries {
+        let SmapsEntry { mapping, stats, .. } = entry;
+        let owner = match &mapping.source {
+            procfs::Source::File(path)
+                if *is_elf
+                    .entry(path.as_ref())
+                    .or_insert_with(|| has_elf_magic(path)) =>
+            {
+                SegmentKind::of(&mapping.perms).map(|kind| (path.as_ref(), kind))
+            }
+            procfs::Source::Anonymous => match last_data {
+                Some((path, end)) if end == mapping.addr_range.start => {
+                    Some((path, SegmentKind::Bss))
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        last_data = match owner {
+            Some((path, SegmentKind::Data)) => Some((path, mapping.addr_range.end)),
+            _ => None,
+        };
+
+        match owner {
+            Some((path, kind)) => {
+                let usage = objects.entry(path).or_default();
+                usage.total += *stats;
+                *usage.segments.entry(kind).or_default() += *stats;
+            }
+            None => {
+                let label = match &mapping.source {
+                    procfs::Source::File(path) => path.to_string(),
+                    procfs::Source::Anonymous => "[anonymous]".to_string(),
+                    source => source.to_string(),
+                };
+                *other.entry(label).or_default() += *stats;
+            }
+        }
+    }
+
+    println!(
+        "{:>10} {:>10} {:>10} {:>10} {:>10}  OBJECT",
+        "RSS", "PSS", "SHARED", "PRIVATE", "SWAP"
+    );
+    let mut objects: Vec<_> = objects.into_iter().collect();
+    objects.sort_by_key(|(path, usage)| (std::cmp::Reverse(usage.total.rss), *path));
+    for (path, usage) in &objects {
+        println!("{}  {}", row(&usage.total), path);
+        let mut segments: Vec<_> = usage.segments.iter().collect();
+        segments.sort_by_key(|(kind, _)| **kind);
+        for (kind, stats) in segments {
+            println!("{}    {}", row(stats), kind);
+        }
+    }
+
+    let mut other: Vec<_> = other.into_iter().collect();
+    other.sort_by_key(|(label, stats)| (std::cmp::Reverse(stats.rss), label.clone()));
+    for (label, stats) in &other {
+        println!("{}  {}", row(stats), label);
+    }
+
+    // the kernel's own sums, to check ours against
+    if let Ok(rollup) = fs::read_to_string(format!("/proc/{}/smaps_rollup", pid)) {
+        if let Ok((_, total)) = procfs::smaps_rollup(&rollup) {
+            println!("{}  (total)", row(&total));
+        }
+    }
+
+    Ok(())
+}
+
+/// One line worth of counters, in KiB
+fn row(stats: &MemStats) -> String {
+    format!(
+        "{:>10} {:>10} {:>10} {:>10} {:>10}",
+        stats.rss,
+        stats.pss,
+        stats.shared_clean + stats.shared_dirty,
+        stats.private_clean + stats.private_dirty,
+        stats.swap
+    )
+}
+
+/// Whether `path` starts like an ELF file. Data files get mapped too
+/// (locale archives, fonts, caches), and have no segments to speak of.
+fn has_elf_magic(path: &str) -> bool {
+    let mut magic = [0u8; 4];
+    fs::File::open(path)
+        .and_then(|mut f| f.read_exact(&mut magic))
+        .is_ok_and(|_| &magic == b"\x7fELF")
+}
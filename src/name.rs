@@ -21,17 +21,14 @@ pub enum Name {
 }
 
 impl Name {
-    pub unsafe fn mapped(map: &Arc<MemoryMap>, offset: usize) -> Self {
-        let len = map
-            .as_slice()
-            .iter()
-            .skip(offset)
-            .position(|&c| c == 0)
-            .expect("scanned 2048 bytes without finding null-terminator for name");
-        Self::Mapped {
+    /// Returns `None` if there's no null terminator between `offset`
+    /// and the end of the mapping.
+    pub unsafe fn mapped(map: &Arc<MemoryMap>, offset: usize) -> Option<Self> {
+        let len = map.as_slice().get(offset..)?.iter().position(|&c| c == 0)?;
+        Some(Self::Mapped {
             map: map.clone(),
             range: offset..offset + len,
-        }
+        })
     }
 
     pub fn owned<T: Into<Vec<u8>>>(value: T) -> Self {
@@ -1,6 +1,6 @@
 use core::unimplemented;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
     path::{Path, PathBuf},
     sync::Arc,
 };
@@ -9,10 +9,25 @@ use mmap::{MapOption, MemoryMap};
 
 use std::ffi::CString;
 
-#[derive(Debug)]
+use crate::{
+    arch::{self, Arch, RelKind},
+    audit::{self, Audit, AuditError, ObjectAudit},
+    diagnostics::{self, UndefinedSymbol},
+    filemap::FileMap,
+    linkmap::{self, DebugMaps, LinkMap},
+    reloc_cache::{self, CachedBinding},
+    relocatable::{self, SectionReloc},
+    trace::Tracer,
+    versions::Versions,
+};
+
+#[derive(CustomDebug)]
 pub struct TLS {
-    offsets: HashMap<delf::Addr, delf::Addr>,
-    block: Vec<u8>,
+    // offset of each object's TLS block from the thread pointer,
+    // keyed by object base
+    offsets: HashMap<delf::Addr, i64>,
+    #[debug(skip)]
+    block: MemoryMap,
     tcb_addr: delf::Addr,
 }
 
@@ -80,6 +95,41 @@ pub enum AuxType {
     SysInfoEHdr = 33,
 }
 
+impl AuxType {
+    /// The auxv type with that value, if it's one we know about
+    pub fn from_u64(typ: u64) -> Option<Self> {
+        use AuxType::*;
+        Some(match typ {
+            0 => Null,
+            1 => Ignore,
+            2 => ExecFd,
+            3 => PHdr,
+            4 => PhEnt,
+            5 => PhNum,
+            6 => PageSz,
+            7 => Base,
+            8 => Flags,
+            9 => Entry,
+            10 => NotElf,
+            11 => Uid,
+            12 => EUid,
+            13 => Gid,
+            14 => EGid,
+            15 => Platform,
+            16 => HwCap,
+            17 => ClkTck,
+            23 => Secure,
+            24 => BasePlatform,
+            25 => Random,
+            26 => HwCap2,
+            31 => ExecFn,
+            32 => SysInfo,
+            33 => SysInfoEHdr,
+            _ => return None,
+        })
+    }
+}
+
 // Here's our "auxiliary vector" struct -
 // just two `u64` in a trench coat.
 pub struct Auxv {
@@ -88,33 +138,6 @@ pub struct Auxv {
 }
 
 impl Auxv {
-    // A list of all the auxiliary types we know (and care) about
-    const KNOWN_TYPES: &'static [AuxType] = &[
-        AuxType::ExecFd,
-        AuxType::PHdr,
-        AuxType::PhEnt,
-        AuxType::PhNum,
-        AuxType::PageSz,
-        AuxType::Base,
-        AuxType::Flags,
-        AuxType::Entry,
-        AuxType::NotElf,
-        AuxType::Uid,
-        AuxType::EUid,
-        AuxType::Gid,
-        AuxType::EGid,
-        AuxType::Platform,
-        AuxType::HwCap,
-        AuxType::ClkTck,
-        AuxType::Secure,
-        AuxType::BasePlatform,
-        AuxType::Random,
-        AuxType::HwCap2,
-        AuxType::ExecFn,
-        AuxType::SysInfo,
-        AuxType::SysInfoEHdr,
-    ];
-
     // this is a quick libc binding thrown together (so we don't
     // have to pull in the `libc` crate).
     pub fn get(typ: AuxType) -> Option<Self> {
@@ -132,9 +155,9 @@ impl Auxv {
     }
 
     // returns a list of all aux vectors passed to us
-    // *that we know about*.
-    pub fn get_known() -> Vec<Self> {
-        Self::KNOWN_TYPES
+    // *that we know about*, and that make sense for `arch`.
+    pub fn get_known(arch: &dyn Arch) -> Vec<Self> {
+        arch.aux_types()
             .iter()
             .copied()
             .filter_map(Self::get)
@@ -152,24 +175,60 @@ pub enum LoadError {
     IO(PathBuf, std::io::Error),
     #[error("ELF object could not be parsed: {0}")]
     ParseError(PathBuf),
-    #[error("ELF object has no load segments")]
-    NoLoadSegments,
-    #[error("ELF object could not be mapped in memory: {0}")]
-    MapError(#[from] mmap::MapError),
-    #[error("Could not read symbols from ELF object: {0}")]
-    ReadSymsError(#[from] delf::ReadSymsError),
-    #[error("Could not read relocations from ELF object: {0}")]
-    ReadRelaError(#[from] delf::ReadRelaError),
+    #[error("{0:?}: ELF object has no load segments")]
+    NoLoadSegments(PathBuf),
+    #[error("{0:?}: unsupported machine {1:?}")]
+    UnsupportedMachine(PathBuf, delf::Machine),
+    #[error("{0:?}: GOT slot at {1} is out of reach of its PLT stub")]
+    PltOutOfReach(PathBuf, delf::Addr),
+    #[error("{0:?}: invalid ELF header: {1}")]
+    InvalidHeader(PathBuf, &'static str),
+    #[error("{0:?}: unsupported OS ABI {1}")]
+    UnsupportedOsAbi(PathBuf, u8),
+    #[error("{0:?}: load segment {1}: {2}")]
+    InvalidSegment(PathBuf, usize, &'static str),
+    #[error("{0:?}: TLS segment: {1}")]
+    InvalidTls(PathBuf, &'static str),
+    #[error("{0}: refused by an audit library")]
+    AuditRefused(String),
+    #[error("{0:?}: doesn't define replacement symbol {1:?}")]
+    MissingReplacement(PathBuf, String),
+    #[error("{0:?}: {1:?} must be an object of at least 8 bytes to hold the original's address")]
+    InvalidRealSlot(PathBuf, String),
+    #[error("{0:?}: has dynamic symbols but no dynamic string table")]
+    MissingDynStr(PathBuf),
+    #[error("{0:?}: dynamic string table at {1} isn't in any load segment")]
+    DynStrOutsideSegments(PathBuf, delf::Addr),
+    #[error("{0:?}: name of dynamic symbol {1} isn't null-terminated within its segment")]
+    InvalidSymbolName(PathBuf, usize),
+    #[error("{0:?}: could not be mapped in memory: {1}")]
+    MapError(PathBuf, mmap::MapError),
+    #[error("Thread-local storage could not be mapped in memory: {0}")]
+    TlsMapError(mmap::MapError),
+    #[error("{0:?}: could not read symbols: {1}")]
+    ReadSymsError(PathBuf, delf::ReadSymsError),
+    #[error("{0:?}: could not read relocations: {1}")]
+    ReadRelaError(PathBuf, delf::ReadRelaError),
 }
 
 #[derive(thiserror::Error, Debug)]
 pub enum RelocationError {
-    #[error("{0:?}: unimplemented relocation type {1:?}")]
-    UnimplementedRelocation(PathBuf, delf::RelType),
-    #[error("unknown symbol number: {0}")]
-    UnknownSymbolNumber(u32),
-    #[error("undefined symbol: {0:?}")]
-    UndefinedSymbol(NamedSym),
+    #[error("{0:?}: unimplemented {1} relocation type {2}")]
+    UnimplementedRelocation(PathBuf, &'static str, u32),
+    #[error("{0:?}: relocation at offset {1} refers to unknown symbol number {2}")]
+    UnknownSymbolNumber(PathBuf, delf::Addr, u32),
+    #[error("{0:?}: relocation at offset {1} is outside of the object's segments")]
+    OffsetOutOfBounds(PathBuf, delf::Addr),
+    #[error("{0:?}: relocation at offset {1} refers to thread-local storage, but {2:?} has none")]
+    MissingTls(PathBuf, delf::Addr, PathBuf),
+    #[error("{0}")]
+    UndefinedSymbol(Box<UndefinedSymbol>),
+    #[error("{}", diagnostics::display_all(.0))]
+    UndefinedSymbols(Vec<UndefinedSymbol>),
+    #[error("{0:?}: relocation at offset {1} doesn't fit in 32 bits")]
+    Overflow(PathBuf, delf::Addr),
+    #[error("Could not map call tracing trampolines: {0}")]
+    TrampolineMap(#[from] mmap::MapError),
 }
 
 #[derive(Debug)]
@@ -179,6 +238,12 @@ pub enum GetResult {
 }
 
 impl GetResult {
+    fn index(&self) -> usize {
+        match *self {
+            Self::Cached(index) | Self::Fresh(index) => index,
+        }
+    }
+
     fn fresh(self) -> Option<usize> {
         if let Self::Fresh(index) = self {
             Some(index)
@@ -188,12 +253,138 @@ impl GetResult {
     }
 }
 
+/// Why a directory is in the search path
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum SearchReason {
+    /// The name has a slash in it, so it's a path and wasn't searched for
+    Path,
+    /// From the `DT_RPATH` entry of `object`
+    RPath { object: PathBuf, entry: String },
+    /// From `LD_LIBRARY_PATH`
+    LibraryPath { entry: String },
+    /// From the `DT_RUNPATH` entry of `object`
+    RunPath { object: PathBuf, entry: String },
+    /// Built into elk
+    Default,
+}
+
+impl SearchReason {
+    /// Whether `$ORIGIN` was expanded to get this directory
+    pub fn origin_expanded(&self) -> bool {
+        match self {
+            Self::RPath { entry, .. } | Self::RunPath { entry, .. } => entry.contains("$ORIGIN"),
+            Self::Path | Self::LibraryPath { .. } | Self::Default => false,
+        }
+    }
+
+    /// The `la_objsearch` flag for candidates found this way. ld.so
+    /// reports RPATH and RUNPATH entries alike.
+    fn audit_flag(&self) -> u32 {
+        match self {
+            Self::Path => audit::LA_SER_ORIG,
+            Self::RPath { .. } | Self::RunPath { .. } => audit::LA_SER_RUNPATH,
+            Self::LibraryPath { .. } => audit::LA_SER_LIBPATH,
+            Self::Default => audit::LA_SER_DEFAULT,
+        }
+    }
+}
+
+impl fmt::Display for SearchReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (tag, object, entry) = match self {
+            Self::RPath { object, entry } => ("RPATH", object, entry),
+            Self::RunPath { object, entry } => ("RUNPATH", object, entry),
+            Self::Path => return write!(f, "path"),
+            Self::LibraryPath { entry } => return write!(f, "LD_LIBRARY_PATH entry {:?}", entry),
+            Self::Default => return write!(f, "default path"),
+        };
+        write!(f, "{} {:?} of {:?}", tag, entry, object)?;
+        if self.origin_expanded() {
+            write!(f, ", $ORIGIN expanded")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchDir {
+    pub path: PathBuf,
+    pub reason: SearchReason,
+}
+
+/// Splits `LD_LIBRARY_PATH`. Like ld.so, entries are separated by colons
+/// or semicolons, and an empty entry is the current directory.
+fn library_path(var: &str) -> Vec<SearchDir> {
+    var.split([':', ';'])
+        .map(|entry| SearchDir {
+            path: if entry.is_empty() { "." } else { entry }.into(),
+            reason: SearchReason::LibraryPath {
+                entry: entry.into(),
+            },
+        })
+        .collect()
+}
+
+/// A `DT_NEEDED` entry, and how we resolved it. Objects are
+/// indices into `Loader::objects`.
+#[derive(Debug, Clone)]
+pub struct Dependency {
+    pub from: usize,
+    pub name: String,
+    pub to: usize,
+    pub reason: SearchReason,
+}
+
 pub struct Loader {
-    pub search_path: Vec<PathBuf>,
+    /// From `LD_LIBRARY_PATH`, searched after `DT_RPATH`
+    pub library_path: Vec<SearchDir>,
+
+    /// Searched last, after everything the objects asked for
+    pub default_path: Vec<SearchDir>,
+
+    pub dependencies: Vec<Dependency>,
 
     pub objects: Vec<Object>,
 
     pub objects_by_path: HashMap<PathBuf, usize>,
+
+    // only filled out if `record_relocations` is set, see `Process::dry_run`
+    pub record_relocations: bool,
+    pub applied_relocations: Vec<AppliedRelocation>,
+
+    // where to cache symbol bindings, if anywhere, see `reloc_cache`
+    pub relocation_cache: Option<PathBuf>,
+
+    // symbols to redirect, see `Process::intercept`
+    pub intercepts: HashMap<Name, Intercept>,
+
+    // rtld-audit libraries, see `audit`
+    pub audit: Audit,
+
+    // JUMP_SLOT bindings to log calls through, see `trace`
+    pub tracer: Option<Tracer>,
+
+    // what debuggers see of our objects, see `linkmap`
+    pub debug_maps: DebugMaps,
+
+    // when false, nothing gets mapped executable - for when we're
+    // only looking at objects, not running them.
+    pub map_executable: bool,
+}
+
+/// A symbol whose bindings get redirected elsewhere
+#[derive(Debug, Clone)]
+pub struct Intercept {
+    /// Where bindings go instead
+    pub target: delf::Addr,
+    /// The object the replacement lives in, if it's one of ours. Its own
+    /// references to the symbol aren't redirected, so it can call the
+    /// original.
+    pub owner: Option<delf::Addr>,
+    /// Pointer-sized slots that get the original's address once
+    /// relocation is done (`__real_sym`)
+    pub real_slots: Vec<delf::Addr>,
 }
 
 pub trait ProcessState {
@@ -206,20 +397,294 @@ pub struct Process<S: ProcessState> {
 }
 
 impl<S: ProcessState> Process<S> {
+    pub fn arch(&self) -> &'static dyn Arch {
+        arch::HOST
+    }
+
     pub fn lookup_symbol(&self, wanted: &ObjectSym, ignore_self: bool) -> ResolvedSym {
-        for obj in &self.state.loader().objects {
-            if ignore_self && std::ptr::eq(wanted.obj, obj) {
-                continue;
+        let skip = if ignore_self { Some(wanted.obj) } else { None };
+        let version = wanted.sym.version.as_deref();
+        match self.providers(&wanted.sym.name, version, skip).next() {
+            Some(sym) => ResolvedSym::Defined(sym),
+            None => ResolvedSym::Undefined,
+        }
+    }
+
+    /// Every definition of `name` that satisfies `version`, in lookup
+    /// order - `lookup_symbol` picks the first one.
+    fn providers<'a: 'b, 'b>(
+        &'a self,
+        name: &'b Name,
+        version: Option<&'b str>,
+        skip: Option<&'b Object>,
+    ) -> impl Iterator<Item = ObjectSym<'a>> + 'b {
+        self.state
+            .loader()
+            .objects
+            .iter()
+            .filter(move |obj| !skip.is_some_and(|skip| std::ptr::eq(skip, *obj)))
+            .filter_map(move |obj| {
+                obj.sym_map
+                    .get_vec(name)?
+                    .iter()
+                    .find(|sym| !sym.sym.shndx.is_undef() && sym.provides(version))
+                    .map(|sym| ObjectSym { obj, sym })
+            })
+    }
+
+    /// Explains why `sym`, needed by `rel` in `obj`, couldn't be found.
+    fn diagnose_undefined(
+        &self,
+        obj: &Object,
+        rel: &Reloc,
+        sym: &NamedSym,
+        skip: Option<&Object>,
+    ) -> UndefinedSymbol {
+        let arch = self.arch();
+        let scope: Vec<&Object> = self
+            .state
+            .loader()
+            .objects
+            .iter()
+            .filter(|o| !skip.is_some_and(|skip| std::ptr::eq(skip, *o)))
+            .collect();
+
+        let mut diag = UndefinedSymbol {
+            object: obj.path.clone(),
+            name: String::from_utf8_lossy(sym.name.as_slice()).into_owned(),
+            version: sym.version.clone(),
+            relocation: match arch.rel_kind(rel.r#type) {
+                Some(kind) => format!("{:?}", kind),
+                None => format!("type {}", rel.r#type),
+            },
+            offset: rel.offset,
+            searched: scope.iter().map(|o| o.path.clone()).collect(),
+            near_misses: Vec::new(),
+        };
+        diag.find_near_misses(scope.iter().flat_map(|o| {
+            o.sym_map.iter_all().flat_map(move |(name, syms)| {
+                syms.iter()
+                    .filter(|s| !s.sym.shndx.is_undef())
+                    .map(move |s| {
+                        (
+                            String::from_utf8_lossy(name.as_slice()).into_owned(),
+                            s.version.as_deref(),
+                            &o.path,
+                        )
+                    })
+            })
+        }));
+        diag
+    }
+
+    /// For every symbol referenced by a relocation, which object provides it,
+    /// and which other definitions it shadows.
+    pub fn bindings(&self) -> Vec<Binding> {
+        let arch = self.arch();
+        let objects = &self.state.loader().objects;
+        let index_of: HashMap<delf::Addr, usize> = objects
+            .iter()
+            .enumerate()
+            .map(|(index, obj)| (obj.base, index))
+            .collect();
+        let is_weak = |sym: &NamedSym| matches!(sym.sym.bind, delf::SymBind::Weak);
+
+        let mut bindings = Vec::new();
+        for (index, obj) in objects.iter().enumerate() {
+            let mut seen = HashSet::new();
+            for rel in &obj.rels {
+                let sym = match obj.syms.get(rel.sym as usize) {
+                    Some(sym) if rel.sym != 0 => sym,
+                    _ => continue,
+                };
+                if matches!(sym.sym.bind, delf::SymBind::Local) || !seen.insert(&sym.name) {
+                    continue;
+                }
+
+                // copy relocations skip the object they're in, same as
+                // in `apply_relocation`
+                let skip = match arch.rel_kind(rel.r#type) {
+                    Some(RelKind::Copy) => Some(obj),
+                    _ => None,
+                };
+                let version = sym.version.as_deref();
+                let mut providers =
+                    self.providers(&sym.name, version, skip)
+                        .map(|found| Provider {
+                            object: index_of[&found.obj.base],
+                            weak: is_weak(found.sym),
+                        });
+                let provider = providers.next();
+                let shadowed: Vec<_> = providers.collect();
+                let strong_definitions = provider
+                    .iter()
+                    .chain(shadowed.iter())
+                    .filter(|p| !p.weak)
+                    .count();
+
+                bindings.push(Binding {
+                    object: index,
+                    symbol: String::from_utf8_lossy(sym.name.as_slice()).into_owned(),
+                    weak_ref: is_weak(sym),
+                    provider,
+                    shadowed,
+                    duplicate_strong: strong_definitions > 1,
+                });
             }
+        }
+        bindings
+    }
+
+    /// Everything that would make relocation fail: undefined non-weak
+    /// symbols, and missing symbol versions. Unlike `apply_relocations`,
+    /// this doesn't stop at the first problem, and doesn't touch memory.
+    pub fn unresolved(&self) -> Vec<Unresolved> {
+        let arch = self.arch();
+        let loader = self.state.loader();
+        let mut unresolved = Vec::new();
+
+        for (index, obj) in loader.objects.iter().enumerate() {
+            if let Some(versions) = &obj.versions {
+                let mut needed: Vec<_> = versions.needed.values().collect();
+                needed.sort();
+                for (file, version) in needed {
+                    // a version needed from an unversioned object only
+                    // gets a warning from glibc, so we let it slide too.
+                    let dep = loader
+                        .dependencies
+                        .iter()
+                        .filter(|dep| &dep.name == file)
+                        .min_by_key(|dep| dep.from != index);
+                    let defined = dep
+                        .and_then(|dep| loader.objects[dep.to].versions.as_ref())
+                        .is_none_or(|versions| versions.defined.values().any(|v| v == version));
+                    if !defined {
+                        unresolved.push(Unresolved::Version {
+                            object: index,
+                            file: file.clone(),
+                            version: version.clone(),
+                        });
+                    }
+                }
+            }
+
+            let mut seen = HashSet::new();
+            for rel in &obj.rels {
+                let sym = match obj.syms.get(rel.sym as usize) {
+                    Some(sym) if rel.sym != 0 => sym,
+                    _ => continue,
+                };
+                if !matches!(sym.sym.bind, delf::SymBind::Global) {
+                    // local symbols resolve to themselves, weak ones may
+                    // stay undefined.
+                    continue;
+                }
 
-            if let Some(syms) = obj.sym_map.get_vec(&wanted.sym.name) {
-                if let Some(sym) = syms.iter().find(|sym| !sym.sym.shndx.is_undef()) {
-                    return ResolvedSym::Defined(ObjectSym { obj, sym });
+                let skip = match arch.rel_kind(rel.r#type) {
+                    Some(RelKind::Copy) => Some(obj),
+                    _ => None,
+                };
+                let version = sym.version.as_deref();
+                if self.providers(&sym.name, version, skip).next().is_none()
+                    && seen.insert((&sym.name, version))
+                {
+                    unresolved.push(Unresolved::Symbol {
+                        object: index,
+                        name: String::from_utf8_lossy(sym.name.as_slice()).into_owned(),
+                        version: sym.version.clone(),
+                        r#type: rel.r#type,
+                    });
                 }
             }
         }
-        ResolvedSym::Undefined
+        unresolved
     }
+
+    /// Symbols that have a strong (global) definition in more than one
+    /// object, sorted by name. Objects are given by index. Versions are
+    /// part of the symbol, so `foo@V1` and `foo@@V2` don't clash, and the
+    /// executable's copies of data symbols (the targets of its COPY
+    /// relocations) aren't definitions of their own.
+    pub fn duplicate_definitions(&self) -> Vec<(String, Vec<usize>)> {
+        let arch = self.arch();
+        let mut definitions: HashMap<(&Name, Option<&str>), Vec<usize>> = HashMap::new();
+        for (index, obj) in self.state.loader().objects.iter().enumerate() {
+            let copies: HashSet<u32> = obj
+                .rels
+                .iter()
+                .filter(|rel| arch.rel_kind(rel.r#type) == Some(RelKind::Copy))
+                .map(|rel| rel.sym)
+                .collect();
+            for sym in &obj.syms {
+                let strong =
+                    matches!(sym.sym.bind, delf::SymBind::Global) && !sym.sym.shndx.is_undef();
+                if !strong || copies.contains(&(sym.index as u32)) {
+                    continue;
+                }
+                let objects = definitions
+                    .entry((&sym.name, sym.version.as_deref()))
+                    .or_default();
+                // count each object once
+                if !objects.contains(&index) {
+                    objects.push(index);
+                }
+            }
+        }
+
+        let mut duplicates: Vec<_> = definitions
+            .into_iter()
+            .filter(|(_, objects)| objects.len() > 1)
+            .map(|((name, version), objects)| {
+                let name = String::from_utf8_lossy(name.as_slice()).into_owned();
+                match version {
+                    Some(version) => (format!("{}@{}", name, version), objects),
+                    None => (name, objects),
+                }
+            })
+            .collect();
+        duplicates.sort();
+        duplicates
+    }
+}
+
+/// Something `Process::unresolved` couldn't find. Objects are given by index.
+#[derive(Debug, Clone)]
+pub enum Unresolved {
+    /// A symbol referenced by a relocation, that nobody defines
+    Symbol {
+        object: usize,
+        name: String,
+        version: Option<String>,
+        r#type: u32,
+    },
+    /// A version the object needs, that the dependency doesn't define
+    Version {
+        object: usize,
+        file: String,
+        version: String,
+    },
+}
+
+/// See `Process::bindings`. Objects are given by index.
+#[derive(Debug, Clone)]
+pub struct Binding {
+    /// The object that references the symbol
+    pub object: usize,
+    pub symbol: String,
+    /// Whether the reference is weak (ie. may stay undefined)
+    pub weak_ref: bool,
+    /// The definition that wins, if any
+    pub provider: Option<Provider>,
+    /// Definitions that lost to `provider`, in lookup order
+    pub shadowed: Vec<Provider>,
+    /// Whether more than one of those definitions is strong
+    pub duplicate_strong: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Provider {
+    pub object: usize,
+    pub weak: bool,
 }
 
 pub struct Loading {
@@ -233,13 +698,122 @@ impl ProcessState for Loading {
 }
 
 impl Process<Loading> {
+    /// Keep track of every relocation we apply, for `dry_run`.
+    pub fn record_relocations(&mut self, record: bool) {
+        self.state.loader.record_relocations = record;
+    }
+
+    /// Redirects every GLOB_DAT, JUMP_SLOT and absolute binding to `symbol`
+    /// to `target`. Returns the address of a pointer that'll hold the
+    /// original's address once relocation is done, so `target` can call
+    /// it.
+    pub fn intercept(&mut self, symbol: &str, target: delf::Addr) -> delf::Addr {
+        // this needs to outlive the loaded program, which never returns
+        let slot: &'static mut u64 = Box::leak(Box::new(0));
+        let slot = delf::Addr(slot as *mut u64 as u64);
+        self.state.loader.intercepts.insert(
+            Name::owned(symbol),
+            Intercept {
+                target,
+                owner: None,
+                real_slots: vec![slot],
+            },
+        );
+        slot
+    }
+
+    /// Like `intercept`, with a replacement defined by a loaded object.
+    /// If that object defines `__real_<symbol>`, it's set to the original's
+    /// address once relocation is done: it must be a data object
+    /// (`STT_OBJECT`) of at least 8 bytes, like `void *__real_sym;`.
+    pub fn intercept_with(
+        &mut self,
+        symbol: &str,
+        object: usize,
+        replacement: &str,
+    ) -> Result<(), LoadError> {
+        let obj = &self.state.loader.objects[object];
+        let defined = |name: &str| {
+            obj.sym_map
+                .get_vec(&Name::owned(name))?
+                .iter()
+                .find(|sym| !sym.sym.shndx.is_undef())
+        };
+        let target = defined(replacement)
+            .map(|sym| obj.base + sym.sym.value)
+            .ok_or_else(|| {
+                LoadError::MissingReplacement(obj.path.clone(), replacement.to_string())
+            })?;
+        // we write a whole pointer there, so it'd better have room for one
+        let real_name = format!("__real_{}", symbol);
+        let real_slots = match defined(&real_name) {
+            Some(sym) if matches!(sym.sym.r#type, delf::SymType::Object) && sym.sym.size >= 8 => {
+                vec![obj.base + sym.sym.value]
+            }
+            Some(_) => return Err(LoadError::InvalidRealSlot(obj.path.clone(), real_name)),
+            None => vec![],
+        };
+
+        let intercept = Intercept {
+            target,
+            owner: Some(obj.base),
+            real_slots,
+        };
+        self.state
+            .loader
+            .intercepts
+            .insert(Name::owned(symbol), intercept);
+        Ok(())
+    }
+
+    /// Loads rtld-audit libraries (like `LD_AUDIT`). This must happen
+    /// before loading any object, or auditors won't hear about it.
+    pub fn audit(&mut self, paths: &[String]) -> Result<(), AuditError> {
+        self.state.loader.audit = Audit::load(paths)?;
+        Ok(())
+    }
+
+    /// Logs calls (and returns) through JUMP_SLOT bindings to symbols
+    /// matching `pattern`, a shell-style glob.
+    pub fn trace_calls(&mut self, pattern: &str) {
+        self.state.loader.tracer = Some(Tracer::new(pattern));
+    }
+
+    /// Cache symbol bindings in `dir`, and replay them when loading the
+    /// exact same objects again.
+    pub fn relocation_cache(&mut self, dir: Option<PathBuf>) {
+        self.state.loader.relocation_cache = dir;
+    }
+
+    /// Whether segments get mapped executable. Turn this off if nothing
+    /// is ever going to run: IFUNC resolvers aren't called either, their
+    /// address is used instead of whatever they'd pick.
+    pub fn map_executable(&mut self, executable: bool) {
+        self.state.loader.map_executable = executable;
+    }
+
     pub fn new() -> Self {
         Self {
             state: Loading {
                 loader: Loader {
                     objects: Vec::new(),
                     objects_by_path: HashMap::new(),
-                    search_path: vec!["/usr/lib/x86_64-linux-gnu".into()],
+                    record_relocations: false,
+                    applied_relocations: Vec::new(),
+                    relocation_cache: None,
+                    intercepts: HashMap::new(),
+                    audit: Audit::default(),
+                    tracer: None,
+                    debug_maps: DebugMaps::new(),
+                    library_path: std::env::var("LD_LIBRARY_PATH")
+                        .map(|var| library_path(&var))
+                        .unwrap_or_default(),
+                    default_path: vec![SearchDir {
+                        path: "/usr/lib/x86_64-linux-gnu".into(),
+                        reason: SearchReason::Default,
+                    }],
+                    dependencies: Vec::new(),
+                    map_executable: true,
                 },
             },
         }
@@ -250,35 +824,81 @@ impl Process<Loading> {
         path: P,
     ) -> Result<usize, LoadError> {
         let index = self.load_object(path)?;
+        self.load_dependencies(index)?;
+        Ok(index)
+    }
+
+    /// Loads everything the object at `index` needs, recursively.
+    /// Auditors hear `LA_ACT_ADD` before the first dependency is searched
+    /// for, and `LA_ACT_CONSISTENT` once they're all loaded. Like ld.so,
+    /// that's reported through the executable's cookie, so the executable
+    /// itself was `la_objopen`ed by then.
+    pub fn load_dependencies(&mut self, index: usize) -> Result<(), LoadError> {
+        self.activity(audit::LA_ACT_ADD);
+        self.load_needed(index)?;
+        self.activity(audit::LA_ACT_CONSISTENT);
+        Ok(())
+    }
+
+    fn activity(&self, flag: u32) {
+        let loader = &self.state.loader;
+        if let Some(exec) = loader.objects.first() {
+            loader.audit.activity(&exec.audit, flag);
+        }
+    }
 
+    fn load_needed(&mut self, index: usize) -> Result<(), LoadError> {
         let mut a = vec![index];
         while !a.is_empty() {
             use delf::DynamicTag::Needed;
-            a = a
-                .into_iter()
-                .map(|index| &self.state.loader.objects[index].file)
-                .flat_map(|file| file.dynamic_entry_strings(Needed))
-                .map(|s| String::from_utf8_lossy(s).to_string())
-                .collect::<Vec<_>>()
-                .into_iter()
-                .map(|dep| self.get_object(&dep))
-                .collect::<Result<Vec<_>, _>>()?
+            let needed = a
                 .into_iter()
-                .filter_map(GetResult::fresh)
-                .collect();
+                .flat_map(|from| {
+                    self.state.loader.objects[from]
+                        .file
+                        .dynamic_entry_strings(Needed)
+                        .map(move |s| (from, String::from_utf8_lossy(s).to_string()))
+                })
+                .collect::<Vec<_>>();
+
+            a = Vec::new();
+            for (from, name) in needed {
+                let loader = &self.state.loader;
+                let search_name = loader
+                    .audit
+                    .objsearch(&loader.objects[from].audit, &name, audit::LA_SER_ORIG)
+                    .ok_or_else(|| LoadError::AuditRefused(name.clone()))?;
+                let (res, reason) = self.get_object_with_reason(Some(from), &search_name)?;
+                let to = res.index();
+                a.extend(res.fresh());
+                self.state.loader.dependencies.push(Dependency {
+                    from,
+                    name,
+                    to,
+                    reason,
+                });
+            }
         }
 
-        Ok(index)
+        Ok(())
     }
 
-    pub fn get_object(&mut self, name: &str) -> Result<GetResult, LoadError> {
-        let path = self.object_path(name)?;
-        self.state
+    /// Finds `name` as a dependency of the object at `from` (which decides
+    /// what's in the search path), and loads it if it isn't yet.
+    fn get_object_with_reason(
+        &mut self,
+        from: Option<usize>,
+        name: &str,
+    ) -> Result<(GetResult, SearchReason), LoadError> {
+        let (path, reason) = self.find_object(from, name)?;
+        let res = self
+            .state
             .loader
             .objects_by_path
             .get(&path)
             .map(|&index| Ok(GetResult::Cached(index)))
-            .unwrap_or_else(|| self.load_object(path).map(GetResult::Fresh))
+            .unwrap_or_else(|| self.load_object(path).map(GetResult::Fresh))?;
+        Ok((res, reason))
     }
 
     pub fn load_object<P: AsRef<Path>>(&mut self, path: P) -> Result<usize, LoadError> {
@@ -287,31 +907,82 @@ impl Process<Loading> {
             .canonicalize()
             .map_err(|e| LoadError::IO(path.as_ref().to_path_buf(), e))?;
 
-        use std::io::Read;
-        let mut fs_file = std::fs::File::open(&path).map_err(|e| LoadError::IO(path.clone(), e))?;
-        let mut input = Vec::new();
-        fs_file
-            .read_to_end(&mut input)
-            .map_err(|e| LoadError::IO(path.clone(), e))?;
+        let fs_file = std::fs::File::open(&path).map_err(|e| LoadError::IO(path.clone(), e))?;
+        self.load_object_from_file(path, fs_file)
+    }
 
-        println!("Loading {:?}", path);
+    /// Loads an object that only exists in memory. It's moved to a memfd,
+    /// so its segments can be mapped like any other file's, and it never
+    /// touches the disk. Its path will be `memfd:<name>`, and `$ORIGIN`
+    /// expands to an empty string in its RPATH/RUNPATH.
+    pub fn load_object_from_bytes(
+        &mut self,
+        name: &str,
+        input: Vec<u8>,
+    ) -> Result<usize, LoadError> {
+        let path = PathBuf::from(format!("memfd:{}", name));
+        let fs_file = memfd(name, &input).map_err(|e| LoadError::IO(path.clone(), e))?;
+        // from now on, the memfd is the only copy
+        drop(input);
+        self.load_object_from_file(path, fs_file)
+    }
+
+    /// Parses `fs_file` from a read-only mapping, which the object keeps
+    /// around, and maps its segments.
+    fn load_object_from_file(
+        &mut self,
+        path: PathBuf,
+        fs_file: std::fs::File,
+    ) -> Result<usize, LoadError> {
+        eprintln!("Loading {:?}", path);
+
+        let input = FileMap::new(&fs_file).map_err(|e| LoadError::IO(path.clone(), e))?;
+        check_ident(&path, input.as_ref())?;
+        let file_len = input.as_ref().len() as u64;
 
         let file = delf::File::parse_or_print_error(input)
             .ok_or_else(|| LoadError::ParseError(path.clone()))?;
 
+        let arch = arch::for_machine(&file.machine)
+            .ok_or_else(|| LoadError::UnsupportedMachine(path.clone(), file.machine))?;
+
+        if matches!(file.typ, delf::Type::Rel) {
+            let object = self.load_relocatable(path, file, arch)?;
+            return Ok(self.push_object(object));
+        }
+
         let origin = path
             .parent()
             .ok_or_else(|| LoadError::InvalidPath(path.clone()))?
             .to_str()
             .ok_or_else(|| LoadError::InvalidPath(path.clone()))?;
-        self.state.loader.search_path.extend(
-            file.dynamic_entry_strings(delf::DynamicTag::RPath)
-                .chain(file.dynamic_entry_strings(delf::DynamicTag::RUNPATH))
-                .map(|path| String::from_utf8_lossy(path))
-                .map(|path| path.replace("$ORIGIN", origin))
-                .inspect(|path| println!("Found RPATH entry {:?}", path))
-                .map(PathBuf::from),
-        );
+        let search_dirs = |tag: delf::DynamicTag, rpath: bool| {
+            file.dynamic_entry_strings(tag)
+                .flat_map(|entries| entries.split(|&b| b == b':'))
+                .filter(|entry| !entry.is_empty())
+                .map(|entry| String::from_utf8_lossy(entry).into_owned())
+                .inspect(|entry| {
+                    let kind = if rpath { "RPATH" } else { "RUNPATH" };
+                    eprintln!("Found {} entry {:?}", kind, entry)
+                })
+                .map(|entry| SearchDir {
+                    path: PathBuf::from(entry.replace("$ORIGIN", origin)),
+                    reason: if rpath {
+                        SearchReason::RPath {
+                            object: path.clone(),
+                            entry,
+                        }
+                    } else {
+                        SearchReason::RunPath {
+                            object: path.clone(),
+                            entry,
+                        }
+                    },
+                })
+                .collect::<Vec<_>>()
+        };
+        let rpath = search_dirs(delf::DynamicTag::RPath, true);
+        let runpath = search_dirs(delf::DynamicTag::RUNPATH, false);
 
         let load_segments = || {
             file.program_headers
@@ -319,22 +990,31 @@ impl Process<Loading> {
                 .filter(|ph| ph.r#type == delf::SegmentType::Load)
         };
 
+        validate_segments(&path, load_segments(), file_len)?;
+        if let Some(tls) = file.segment_of_type(delf::SegmentType::TLS) {
+            validate_tls(&path, tls, load_segments())?;
+        }
+
         let mem_range = load_segments()
             .map(|ph| ph.mem_range())
             .fold(None, |acc, range| match acc {
                 None => Some(range),
                 Some(acc) => Some(convex_hull(acc, range)),
             })
-            .ok_or(LoadError::NoLoadSegments)?;
+            .ok_or_else(|| LoadError::NoLoadSegments(path.clone()))?;
+        // segments get mapped from the start of their page, so that
+        // page must be part of our reservation too.
+        let mem_range = delf::Addr(mem_range.start.0 & !0xFFF)..mem_range.end;
 
         let mem_size: usize = (mem_range.end - mem_range.start).into();
-        let mem_map = std::mem::ManuallyDrop::new(MemoryMap::new(
-            mem_size,
-            &[MapOption::MapReadable, MapOption::MapWritable],
-        )?);
+        let mem_map = std::mem::ManuallyDrop::new(
+            MemoryMap::new(mem_size, &[MapOption::MapReadable, MapOption::MapWritable])
+                .map_err(|e| LoadError::MapError(path.clone(), e))?,
+        );
         let base = delf::Addr(mem_map.data() as _) - mem_range.start;
 
         use std::os::unix::io::AsRawFd;
+        let map_executable = self.state.loader.map_executable;
         let segments = load_segments()
             .filter(|ph| ph.memsz.0 > 0)
             .map(|ph| -> Result<_, LoadError> {
@@ -342,17 +1022,18 @@ impl Process<Loading> {
                 let padding = ph.vaddr - vaddr;
                 let offset = ph.offset - padding;
                 let filesz = ph.filesz + padding;
-                let map = MemoryMap::new(
-                    filesz.into(),
-                    &[
-                        MapOption::MapReadable,
-                        MapOption::MapWritable,
-                        MapOption::MapExecutable,
-                        MapOption::MapFd(fs_file.as_raw_fd()),
-                        MapOption::MapOffset(offset.into()),
-                        MapOption::MapAddr((base + vaddr).as_ptr()),
-                    ],
-                )?;
+                let mut options = vec![
+                    MapOption::MapReadable,
+                    MapOption::MapWritable,
+                    MapOption::MapFd(fs_file.as_raw_fd()),
+                    MapOption::MapOffset(offset.into()),
+                    MapOption::MapAddr((base + vaddr).as_ptr()),
+                ];
+                if map_executable {
+                    options.push(MapOption::MapExecutable);
+                }
+                let map = MemoryMap::new(filesz.into(), &options)
+                    .map_err(|e| LoadError::MapError(path.clone(), e))?;
                 if ph.memsz > ph.filesz {
                     // ...then we zero them!
                     // note: this works because we already reserved the *convex hull*
@@ -369,38 +1050,56 @@ impl Process<Loading> {
                 }
                 // this new - we store a Vec<Segment> now, and Segment structs
                 // contain the padding we used, and the flags (for later mprotect-ing)
-                Ok(Segment {
-                    map: Arc::new(map),
-                    vaddr_range: vaddr..(ph.vaddr + ph.memsz),
+                Ok(Segment::new(
+                    map,
+                    vaddr..(ph.vaddr + ph.memsz),
                     padding,
-                    flags: ph.flags,
-                })
+                    ph.flags,
+                ))
             })
             .collect::<Result<Vec<_>, _>>()?;
 
-        let syms = file.read_dynsym_entries()?;
+        let versions = Versions::parse(&file);
+        let syms = file
+            .read_dynsym_entries()
+            .map_err(|e| LoadError::ReadSymsError(path.clone(), e))?;
         let syms: Vec<_> = if syms.is_empty() {
             vec![]
         } else {
             let dynstr = file
                 .get_dynamic_entry(delf::DynamicTag::StrTab)
-                .unwrap_or_else(|_| panic!("String table not found in {:?}", path));
+                .map_err(|_| LoadError::MissingDynStr(path.clone()))?;
             let segment = segments
                 .iter()
                 // and here's where `vaddr_range` comes in handy
                 .find(|seg| seg.vaddr_range.contains(&dynstr))
-                .unwrap_or_else(|| panic!("Segment not found for string table in {:#?}", path));
+                .ok_or_else(|| LoadError::DynStrOutsideSegments(path.clone(), dynstr))?;
 
             syms.into_iter()
-                .map(|sym| unsafe {
+                .enumerate()
+                .map(|(index, sym)| unsafe {
                     let name = Name::mapped(
                         &segment.map,
                         // a little bit of maths can't hurt
                         (dynstr + sym.name - segment.vaddr_range.start).into(),
-                    );
-                    NamedSym { sym, name }
+                    )
+                    .ok_or_else(|| LoadError::InvalidSymbolName(path.clone(), index))?;
+                    let (version, hidden) = match &versions {
+                        Some(versions) => (
+                            versions.symbol_version(index).map(String::from),
+                            versions.is_hidden(index),
+                        ),
+                        None => (None, false),
+                    };
+                    Ok(NamedSym {
+                        sym,
+                        index,
+                        name,
+                        version,
+                        hidden,
+                    })
                 })
-                .collect()
+                .collect::<Result<_, LoadError>>()?
         };
 
         let mut sym_map = MultiMap::new();
@@ -409,10 +1108,26 @@ impl Process<Loading> {
         }
 
         let mut rels = Vec::new();
-        rels.extend(file.read_rela_entries()?);
-        rels.extend(file.read_jmp_rel_entries()?);
+        let read_rela_error = |e| LoadError::ReadRelaError(path.clone(), e);
+        rels.extend(
+            file.read_rela_entries()
+                .map_err(read_rela_error)?
+                .into_iter()
+                .map(Reloc::from),
+        );
+        rels.extend(
+            file.read_jmp_rel_entries()
+                .map_err(read_rela_error)?
+                .into_iter()
+                .map(Reloc::from),
+        );
 
+        let dynamic = file
+            .segment_of_type(delf::SegmentType::Dynamic)
+            .map(|ph| base + ph.vaddr);
         let object = Object {
+            link_map: LinkMap::new(&path, base, dynamic),
+            audit: ObjectAudit::default(),
             path: path.clone(),
             base,
             segments,
@@ -421,37 +1136,264 @@ impl Process<Loading> {
             syms,
             sym_map,
             rels,
+            versions,
+            rpath,
+            runpath,
+            got: HashMap::new(),
+            plt: HashMap::new(),
         };
 
-        if path.to_str().unwrap().ends_with("libmsg.so") {
-            let msg_addr: *const u8 = (base + delf::Addr(0x2000)).as_ptr();
-            dbg!(msg_addr);
-            let msg_slice = unsafe { std::slice::from_raw_parts(msg_addr, 0x26) };
-            let msg = std::str::from_utf8(msg_slice).unwrap();
-            dbg!(msg);
-        }
+        Ok(self.push_object(object))
+    }
 
+    fn push_object(&mut self, mut object: Object) -> usize {
+        object.audit = self.state.loader.audit.objopen(&mut object.link_map);
         let index = self.state.loader.objects.len();
+        if index == 0 {
+            // the first object is the executable, debuggers look for
+            // `r_debug` in its dynamic section.
+            let r_debug = self.state.loader.debug_maps.r_debug();
+            object.set_dt_debug(delf::Addr(r_debug as u64));
+        }
+        // the link map is boxed, so it stays put when `object` moves
+        self.state.loader.debug_maps.add(&mut object.link_map);
+        self.state
+            .loader
+            .objects_by_path
+            .insert(object.path.clone(), index);
         self.state.loader.objects.push(object);
-        self.state.loader.objects_by_path.insert(path, index);
+        index
+    }
 
-        Ok(index)
+    /// Relocatable objects have no segments, so we lay out their sections
+    /// ourselves, along with a GOT and PLT stubs for external symbols.
+    fn load_relocatable(
+        &mut self,
+        path: PathBuf,
+        file: delf::File<FileMap>,
+        arch: &'static dyn Arch,
+    ) -> Result<Object, LoadError> {
+        let syms = file
+            .read_symtab_entries()
+            .map_err(|e| LoadError::ReadSymsError(path.clone(), e))?;
+        let rels = relocatable::read_relocations(&file)
+            .ok_or_else(|| LoadError::ParseError(path.clone()))?;
+        // anything this object doesn't define may end up far away, so
+        // calls to it go through the PLT.
+        let layout = relocatable::layout(arch, &file, &syms, &rels, |sym| sym.shndx.is_undef());
+
+        let mem_size = layout.size() as usize;
+        if mem_size == 0 {
+            return Err(LoadError::NoLoadSegments(path));
+        }
+        let mem_map = std::mem::ManuallyDrop::new(
+            MemoryMap::new(mem_size, &[MapOption::MapReadable, MapOption::MapWritable])
+                .map_err(|e| LoadError::MapError(path.clone(), e))?,
+        );
+        let base = delf::Addr(mem_map.data() as _);
+
+        let map_executable = self.state.loader.map_executable;
+        let segments = layout
+            .segments
+            .iter()
+            .map(|(kind, range)| -> Result<_, LoadError> {
+                let vaddr_range = delf::Addr(range.start)..delf::Addr(range.end);
+                let mut options = vec![
+                    MapOption::MapReadable,
+                    MapOption::MapWritable,
+                    MapOption::MapAddr((base + vaddr_range.start).as_ptr()),
+                ];
+                if map_executable {
+                    options.push(MapOption::MapExecutable);
+                }
+                let map = MemoryMap::new((range.end - range.start) as usize, &options)
+                    .map_err(|e| LoadError::MapError(path.clone(), e))?;
+                Ok(Segment::new(map, vaddr_range, delf::Addr(0), kind.flags()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // fresh anonymous mappings are zeroed, so NOBITS sections are
+        // taken care of already.
+        let input = file.input.as_ref();
+        for (&index, &offset) in &layout.sections {
+            let sh = &file.section_headers[index];
+            if matches!(sh.r#type, delf::SectionType::NoBits) {
+                continue;
+            }
+            let start: usize = sh.offset.into();
+            let data = start
+                .checked_add(usize::from(sh.size))
+                .and_then(|end| input.get(start..end))
+                .ok_or_else(|| LoadError::ParseError(path.clone()))?;
+            unsafe {
+                (base + delf::Addr(offset)).write(data);
+            }
+        }
+
+        for (sym, &stub) in &layout.plt {
+            let stub_addr = base + delf::Addr(stub);
+            let slot_addr = base + delf::Addr(layout.got[sym]);
+            let code = arch
+                .plt_stub(stub_addr, slot_addr)
+                .ok_or_else(|| LoadError::PltOutOfReach(path.clone(), slot_addr))?;
+            unsafe {
+                stub_addr.write(&code);
+            }
+        }
+
+        // symbol values are section-relative, make them relative to
+        // our base instead, like they would be in a shared object.
+        let syms: Vec<_> = syms
+            .into_iter()
+            .enumerate()
+            .map(|(index, mut sym)| {
+                if let Some(offset) = layout.sym_offset(index, &sym) {
+                    sym.value = delf::Addr(offset);
+                }
+                let name = Name::owned(file.strtab_entry(sym.name));
+                NamedSym {
+                    sym,
+                    index,
+                    name,
+                    version: None,
+                    hidden: false,
+                }
+            })
+            .collect();
+
+        // local symbols are only visible from this object
+        let mut sym_map = MultiMap::new();
+        for sym in syms
+            .iter()
+            .filter(|sym| !matches!(sym.sym.bind, delf::SymBind::Local))
+        {
+            sym_map.insert(sym.name.clone(), sym.clone())
+        }
+
+        let rels = rels
+            .into_iter()
+            .map(|SectionReloc { section, mut rel }| {
+                rel.offset = delf::Addr(layout.sections[&section]) + rel.offset;
+                rel
+            })
+            .collect();
+
+        let to_addrs = |map: &HashMap<u32, u64>| {
+            map.iter()
+                .map(|(&sym, &offset)| (sym, delf::Addr(offset)))
+                .collect()
+        };
+
+        Ok(Object {
+            link_map: LinkMap::new(&path, base, None),
+            audit: ObjectAudit::default(),
+            path,
+            base,
+            segments,
+            mem_range: delf::Addr(0)..delf::Addr(mem_size as u64),
+            got: to_addrs(&layout.got),
+            plt: to_addrs(&layout.plt),
+            file,
+            syms,
+            sym_map,
+            rels,
+            versions: None,
+            rpath: Vec::new(),
+            runpath: Vec::new(),
+        })
     }
 
-    pub fn object_path(&self, name: &str) -> Result<PathBuf, LoadError> {
+    /// Finds the file for `name`, and says which search path entry matched.
+    /// `from` is the object that needs `name`, if any.
+    pub fn find_object(
+        &self,
+        from: Option<usize>,
+        name: &str,
+    ) -> Result<(PathBuf, SearchReason), LoadError> {
+        if name.contains('/') {
+            let path = Path::new(name)
+                .canonicalize()
+                .map_err(|_| LoadError::NotFound(name.into()))?;
+            return Ok((path, SearchReason::Path));
+        }
+
+        let loader = &self.state.loader;
+        self.search_path(from)
+            .into_iter()
+            .filter_map(|dir| {
+                let candidate = dir.path.join(name);
+                // auditors may rewrite every candidate, or skip it
+                let candidate = match from {
+                    Some(from) => PathBuf::from(loader.audit.objsearch(
+                        &loader.objects[from].audit,
+                        candidate.to_str()?,
+                        dir.reason.audit_flag(),
+                    )?),
+                    None => candidate,
+                };
+                candidate
+                    .canonicalize()
+                    .ok()
+                    .map(|path| (path, dir.reason.clone()))
+            })
+            .find(|(path, _)| path.exists())
+            .ok_or_else(|| LoadError::NotFound(name.into()))
+    }
+
+    /// The directories ld.so would search for a dependency of `from`, in
+    /// order: `DT_RPATH` of `from` and of each object up the chain that
+    /// loaded it (unless `from` has a `DT_RUNPATH`), `LD_LIBRARY_PATH`,
+    /// `DT_RUNPATH` of `from` only, then the defaults.
+    fn search_path(&self, from: Option<usize>) -> Vec<&SearchDir> {
+        let loader = &self.state.loader;
+        let mut dirs = Vec::new();
+
+        if let Some(from) = from {
+            if loader.objects[from].runpath.is_empty() {
+                let mut chain = vec![from];
+                while let Some(loaded_by) = self.loaded_by(*chain.last().unwrap()) {
+                    chain.push(loaded_by);
+                }
+                // the executable's RPATH applies to everything
+                if !chain.contains(&0) {
+                    chain.push(0);
+                }
+                dirs.extend(
+                    chain
+                        .iter()
+                        .map(|&index| &loader.objects[index])
+                        // objects with a RUNPATH don't get their RPATH used
+                        .filter(|obj| obj.runpath.is_empty())
+                        .flat_map(|obj| &obj.rpath),
+                );
+            }
+        }
+        dirs.extend(&loader.library_path);
+        if let Some(from) = from {
+            dirs.extend(&loader.objects[from].runpath);
+        }
+        dirs.extend(&loader.default_path);
+        dirs
+    }
+
+    /// The object whose `DT_NEEDED` entry first caused `index` to be
+    /// loaded. Objects are loaded in order, so it always comes before.
+    fn loaded_by(&self, index: usize) -> Option<usize> {
         self.state
             .loader
-            .search_path
+            .dependencies
             .iter()
-            .filter_map(|prefix| prefix.join(name).canonicalize().ok())
-            .find(|path| path.exists())
-            .ok_or_else(|| LoadError::NotFound(name.into()))
+            .find(|dep| dep.to == index && dep.from < index)
+            .map(|dep| dep.from)
     }
 
-    pub fn allocate_tls(mut self) -> Process<TLSAllocated> {
+    pub fn allocate_tls(self) -> Result<Process<TLSAllocated>, LoadError> {
+        let arch = self.arch();
+        let tcb_size = arch.tcb_size();
+
         let mut offsets = HashMap::new();
         let mut storage_space = 0;
-        for obj in &mut self.state.loader.objects {
+        for obj in &self.state.loader.objects {
             let needed = obj
                 .file
                 .segment_of_type(delf::SegmentType::TLS)
@@ -459,38 +1401,28 @@ impl Process<Loading> {
                 .unwrap_or_default() as u64;
 
             if needed > 0 {
-                let offset = delf::Addr(storage_space + needed);
+                // blocks are laid out backwards, right before the TCB
+                let offset = -((storage_space + needed) as i64);
                 offsets.insert(obj.base, offset);
                 storage_space += needed;
             }
         }
 
         let storage_space = storage_space as usize;
-        let tcbhead_size = 704;
-        let total_size = storage_space + tcbhead_size;
-
-        // Allocate the whole capacity upfront so the vector doesn't
-        // get resized, and `tcb_addr` doesn't get invalidated
-        let mut block = Vec::with_capacity(total_size);
-        // This is what we'll be setting `%fs` to
-        let tcb_addr = delf::Addr(block.as_ptr() as u64 + storage_space as u64);
-        for _ in 0..storage_space {
-            // For now, zero out storage
-            block.push(0u8);
-        }
-
-        // Build a "somewhat fake" tcbhead structure
-        block.extend(&tcb_addr.0.to_le_bytes()); // tcb
-        block.extend(&0_u64.to_le_bytes()); // dtv
-        block.extend(&tcb_addr.0.to_le_bytes()); // thread pointer
-        block.extend(&0_u32.to_le_bytes()); // multiple_threads
-        block.extend(&0_u32.to_le_bytes()); // gscope_flag
-        block.extend(&0_u64.to_le_bytes()); // sysinfo
-        block.extend(&0xDEADBEEF_u64.to_le_bytes()); // stack guard
-        block.extend(&0xFEEDFACE_u64.to_le_bytes()); // pointer guard
-        while block.len() < block.capacity() {
-            // We don't care about the other fields, just pad out with zeros
-            block.push(0u8);
+        let total_size = storage_space + tcb_size;
+
+        // Anonymous mappings are zeroed, which is what we want for
+        // the storage, and for the TCB fields we don't care about
+        let block = MemoryMap::new(
+            total_size,
+            &[MapOption::MapReadable, MapOption::MapWritable],
+        )
+        .map_err(LoadError::TlsMapError)?;
+        let block_addr = delf::Addr(block.data() as u64);
+        // This is what we'll be setting the thread pointer to
+        let tcb_addr = block_addr + delf::Addr(storage_space as u64);
+        unsafe {
+            tcb_addr.write(&arch.tcb_head(tcb_addr));
         }
 
         let tls = TLS {
@@ -499,12 +1431,12 @@ impl Process<Loading> {
             tcb_addr,
         };
 
-        Process {
+        Ok(Process {
             state: TLSAllocated {
                 loader: self.state.loader,
                 tls,
             },
-        }
+        })
     }
 }
 
@@ -522,123 +1454,479 @@ impl ProcessState for TLSAllocated {
 impl Process<TLSAllocated> {
     pub fn apply_relocations(self) -> Result<Process<Relocated>, RelocationError> {
         let rels: Vec<_> = self
-            .state
-            .loader
+            .relocation_order()
+            .into_iter()
+            .map(|index| &self.state.loader.objects[index])
+            .flat_map(|obj| obj.rels.iter().map(move |rel| ObjectRel { obj, rel }))
+            .collect();
+
+        let loader = &self.state.loader;
+        let cache = loader
+            .relocation_cache
+            .as_ref()
+            .and_then(|dir| reloc_cache::Key::new(&loader.objects).map(|key| (dir.as_path(), key)));
+        let cached = cache
+            .as_ref()
+            .and_then(|(dir, key)| reloc_cache::load(dir, key))
+            .filter(|bindings| bindings.len() == rels.len());
+        if cached.is_some() {
+            eprintln!("Replaying symbol bindings from the relocation cache");
+        }
+        let index_of: HashMap<delf::Addr, usize> = loader
             .objects
             .iter()
-            .rev()
-            .flat_map(|obj| obj.rels.iter().map(move |rel| ObjectRel { obj, rel }))
+            .enumerate()
+            .map(|(index, obj)| (obj.base, index))
             .collect();
 
-        for rel in rels {
-            self.apply_relocation(rel)?;
+        let mut applied = Vec::new();
+        let mut bindings = vec![None; rels.len()];
+        let mut undefined: Vec<UndefinedSymbol> = Vec::new();
+
+        // IFUNC resolvers may call into any object, so none of them run
+        // until the first pass has applied every non-IFUNC relocation: the
+        // dynamic loader's first, then each dependency's, see
+        // `relocation_order`. The second pass runs the resolvers in that
+        // same order, so the dynamic loader's own resolvers come first -
+        // glibc's `__x86_cpu_features` counts on that to initialize CPU
+        // features before libc's resolvers look at them.
+        let ifunc_args = IfuncArgs::from_auxv();
+        let mut pass: Vec<(usize, ObjectRel, Option<IfuncArgs>)> = rels
+            .into_iter()
+            .enumerate()
+            .map(|(i, rel)| (i, rel, None))
+            .collect();
+        while !pass.is_empty() {
+            let mut deferred = Vec::new();
+            for (i, rel, ifunc) in pass {
+                let replay = cached
+                    .as_ref()
+                    .and_then(|cached| self.replay_binding(cached[i]));
+                match self.apply_relocation(rel, replay, ifunc) {
+                    Ok(Some(record)) => {
+                        bindings[i] = record
+                            .target
+                            .zip(record.target_index)
+                            .map(|((base, _), sym)| (index_of[&base], sym));
+                        if self.state.loader.record_relocations {
+                            applied.push(record);
+                        }
+                    }
+                    Ok(None) => deferred.push((i, rel, Some(ifunc_args))),
+                    // keep going, so we can report all of them at once
+                    Err(RelocationError::UndefinedSymbol(sym)) => {
+                        diagnostics::push_unique(&mut undefined, *sym)
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            // don't run any resolvers if we're going to fail anyway
+            if !undefined.is_empty() {
+                return Err(RelocationError::UndefinedSymbols(undefined));
+            }
+            // the second pass has `ifunc_args` set, so nothing gets
+            // deferred again.
+            pass = deferred;
+        }
+        self.fill_real_slots(ifunc_args);
+        if let (Some((dir, key)), None) = (&cache, &cached) {
+            // the cache is an optimization, failing to write it is fine
+            if let Err(e) = reloc_cache::store(dir, key, bindings) {
+                eprintln!("Could not write relocation cache: {}", e);
+            }
         }
 
+        let mut loader = self.state.loader;
+        loader.applied_relocations = applied;
         Ok(Process {
             state: Relocated {
-                loader: self.state.loader,
+                loader,
                 tls: self.state.tls,
             },
         })
     }
 
-    fn apply_relocation(&self, objrel: ObjectRel) -> Result<(), RelocationError> {
-        use delf::RelType as RT;
+    /// The order objects are relocated in: the dynamic loader (if we
+    /// loaded one), then every other object after the objects it depends
+    /// on, which is reverse load order since dependencies are loaded
+    /// breadth-first. The executable comes last.
+    fn relocation_order(&self) -> Vec<usize> {
+        let interpreter = self.interpreter();
+        interpreter
+            .into_iter()
+            .chain(
+                (0..self.state.loader.objects.len())
+                    .rev()
+                    .filter(|&index| Some(index) != interpreter),
+            )
+            .collect()
+    }
+
+    /// The object the executable names in its `PT_INTERP`, if we loaded it
+    fn interpreter(&self) -> Option<usize> {
+        let loader = &self.state.loader;
+        let exec = loader.objects.first()?;
+        let ph = exec.file.segment_of_type(delf::SegmentType::Interp)?;
+        let range = ph.file_range();
+        let bytes = exec
+            .file
+            .input
+            .as_ref()
+            .get(usize::from(range.start)..usize::from(range.end))?;
+        let name = bytes.split(|&b| b == 0).next()?;
+        let path = Path::new(std::str::from_utf8(name).ok()?)
+            .canonicalize()
+            .ok()?;
+        loader.objects_by_path.get(&path).copied()
+    }
+
+    /// The intercept that applies to this relocation, if any
+    fn intercept_for(
+        &self,
+        obj: &Object,
+        rel: &Reloc,
+        reltype: RelKind,
+        sym: &NamedSym,
+    ) -> Option<&Intercept> {
+        if rel.sym == 0
+            || !matches!(
+                reltype,
+                RelKind::Absolute | RelKind::GlobDat | RelKind::JumpSlot
+            )
+        {
+            return None;
+        }
+        self.state
+            .loader
+            .intercepts
+            .get(&sym.name)
+            .filter(|intercept| intercept.owner != Some(obj.base))
+    }
+
+    /// Writes the address of every intercepted symbol's original to its
+    /// `__real` slots.
+    fn fill_real_slots(&self, ifunc_args: IfuncArgs) {
+        let arch = self.arch();
+        let loader = &self.state.loader;
+        for (name, intercept) in &loader.intercepts {
+            let owner = intercept
+                .owner
+                .and_then(|base| loader.objects.iter().find(|obj| obj.base == base));
+            let original = match self.providers(name, None, owner).next() {
+                Some(sym) if sym.is_ifunc() && loader.map_executable => unsafe {
+                    ifunc_args.call(sym.value())
+                },
+                Some(sym) => sym.value(),
+                None => continue,
+            };
+            for &slot in &intercept.real_slots {
+                unsafe { arch.write_word(slot, original.0) };
+            }
+        }
+    }
+
+    /// Turns a cached binding back into a symbol. Returns `None` if the
+    /// binding doesn't make sense, in which case we look the symbol up
+    /// as usual.
+    fn replay_binding(&self, binding: CachedBinding) -> Option<ResolvedSym<'_>> {
+        match binding {
+            None => Some(ResolvedSym::Undefined),
+            Some((obj, sym)) => {
+                let obj = self.state.loader.objects.get(obj)?;
+                let sym = obj.syms.get(sym)?;
+                Some(ResolvedSym::Defined(ObjectSym { obj, sym }))
+            }
+        }
+    }
+
+    /// Applies a single relocation. If `cached` is set, that's the symbol
+    /// it resolves to, and we don't look it up.
+    ///
+    /// Relocations that involve an IFUNC are only applied if `ifunc` is
+    /// set, otherwise this returns `Ok(None)` and they should be retried
+    /// later.
+    fn apply_relocation(
+        &self,
+        objrel: ObjectRel,
+        cached: Option<ResolvedSym>,
+        ifunc: Option<IfuncArgs>,
+    ) -> Result<Option<AppliedRelocation>, RelocationError> {
+        let arch = self.arch();
 
         // destructure a bit, for convenience
         let ObjectRel { obj, rel } = objrel;
-        let reltype = rel.r#type;
+        let reltype = arch.rel_kind(rel.r#type).ok_or_else(|| {
+            RelocationError::UnimplementedRelocation(obj.path.clone(), arch.name(), rel.r#type)
+        })?;
+        // everything below writes (or reads) at the relocation site, so
+        // make sure that's actually ours first.
+        let site_len = match reltype {
+            RelKind::None | RelKind::DtpMod => 0,
+            RelKind::Pc32
+            | RelKind::Plt32
+            | RelKind::GotPcRel
+            | RelKind::Abs32
+            | RelKind::Abs32S => 4,
+            _ => arch.word_size() as u64,
+        };
+        if site_len > 0 && !obj.contains(rel.offset, site_len) {
+            return Err(RelocationError::OffsetOutOfBounds(
+                obj.path.clone(),
+                rel.offset,
+            ));
+        }
+
         let addend = rel.addend;
 
         // this is the symbol we're looking for.
         // note that it may be symbol 0, which has an empty name - that's fine.
         let wanted = ObjectSym {
             obj,
-            sym: &obj.syms[rel.sym as usize],
+            sym: obj.syms.get(rel.sym as usize).ok_or_else(|| {
+                RelocationError::UnknownSymbolNumber(obj.path.clone(), rel.offset, rel.sym)
+            })?,
         };
 
         // when doing a lookup, only ignore the relocation's object if
         // we're performing a Copy relocation.
-        let ignore_self = matches!(reltype, RT::Copy);
+        let ignore_self = matches!(reltype, RelKind::Copy);
 
         // perform symbol lookup early
-        let found = match rel.sym {
-            // the relocation isn't bound to any symbol, go with undef
-            0 => ResolvedSym::Undefined,
-            _ => match self.lookup_symbol(&wanted, ignore_self) {
-                undef @ ResolvedSym::Undefined => match wanted.sym.sym.bind {
-                    // undefined symbols are fine if our local symbol is weak
-                    delf::SymBind::Weak => undef,
-                    // otherwise, error out now
-                    _ => return Err(RelocationError::UndefinedSymbol(wanted.sym.clone())),
+        let found = match cached {
+            Some(found) => found,
+            None => match rel.sym {
+                // the relocation isn't bound to any symbol, go with undef
+                0 => ResolvedSym::Undefined,
+                // local symbols (from relocatable objects) can't be looked up by
+                // name, but we already know where they are.
+                _ if matches!(wanted.sym.sym.bind, delf::SymBind::Local)
+                    && !wanted.sym.sym.shndx.is_undef() =>
+                {
+                    ResolvedSym::Defined(wanted.clone())
+                }
+                _ => match self.lookup_symbol(&wanted, ignore_self) {
+                    undef @ ResolvedSym::Undefined => match wanted.sym.sym.bind {
+                        // undefined symbols are fine if our local symbol is weak
+                        delf::SymBind::Weak => undef,
+                        // otherwise, error out now
+                        _ => {
+                            let skip = if ignore_self { Some(obj) } else { None };
+                            return Err(RelocationError::UndefinedSymbol(Box::new(
+                                self.diagnose_undefined(obj, rel, wanted.sym, skip),
+                            )));
+                        }
+                    },
+                    // defined symbols are always fine
+                    x => x,
                 },
-                // defined symbols are always fine
-                x => x,
             },
         };
 
-        match reltype {
-            RT::_64 => unsafe {
-                // we're using `set<T>()` and passing a `delf::Addr` - which is
-                // just a newtype over `u64`, so everything works out!
-                println!(
-                    "_64: at {}, {:?} set to {}",
+        let is_ifunc = match (&found, reltype) {
+            (_, RelKind::IRelative) => true,
+            // copy relocations copy the resolver's code, if anything
+            (_, RelKind::Copy) => false,
+            (ResolvedSym::Defined(sym), _) => sym.is_ifunc(),
+            (ResolvedSym::Undefined, _) => false,
+        };
+        let ifunc = match ifunc {
+            Some(args) => args,
+            None if is_ifunc => return Ok(None),
+            None => IfuncArgs::default(),
+        };
+        // resolvers are code from the object, if we're not mapping
+        // anything executable we can't (and shouldn't) run them, the
+        // resolver's address stands in for whatever it would've picked.
+        let run_resolvers = self.state.loader.map_executable;
+        let mut ifunc_resolver = None;
+        let mut resolve = |resolver: delf::Addr| {
+            if run_resolvers {
+                unsafe { ifunc.call(resolver) }
+            } else {
+                ifunc_resolver = Some(resolver);
+                resolver
+            }
+        };
+
+        // where the symbol is, as far as the relocation is concerned: for
+        // IFUNC symbols, that's whatever their resolver picks.
+        let target = match &found {
+            ResolvedSym::Defined(sym) if is_ifunc => resolve(sym.value()),
+            _ => found.value(),
+        };
+        let target = match self.intercept_for(obj, rel, reltype, wanted.sym) {
+            Some(intercept) => intercept.target,
+            None => target,
+        };
+        // auditors see (and may change) every symbol binding
+        let target = match (&found, reltype) {
+            (
+                ResolvedSym::Defined(sym),
+                RelKind::Absolute | RelKind::GlobDat | RelKind::JumpSlot,
+            ) if !self.state.loader.audit.is_empty() => {
+                delf::Addr(self.state.loader.audit.symbind(
+                    &obj.audit,
+                    &sym.obj.audit,
+                    sym.audit_sym(target),
+                    sym.sym.index as u32,
+                    sym.sym.name.as_slice(),
+                ))
+            }
+            _ => target,
+        };
+        // traced calls go through a trampoline, which forwards to wherever
+        // the call was going to go anyway.
+        let target = match (&self.state.loader.tracer, reltype) {
+            (Some(tracer), RelKind::JumpSlot) if tracer.matches(wanted.sym.name.as_slice()) => {
+                tracer.trampoline(wanted.sym.name.as_slice(), &obj.path, target)?
+            }
+            _ => target,
+        };
+
+        // what we end up writing, for reporting purposes
+        let value = match reltype {
+            RelKind::None | RelKind::DtpMod => delf::Addr(0),
+            RelKind::Absolute => unsafe {
+                let value = target + addend;
+                eprintln!(
+                    "Absolute: at {}, {:?} set to {}",
                     objrel.addr(),
-                    *objrel.addr().as_ptr::<u64>(),
-                    found.value() + addend
+                    arch.read_word(objrel.addr()),
+                    value
                 );
-                objrel.addr().set(found.value() + addend);
+                arch.write_word(objrel.addr(), value.0);
+                value
             },
-            RT::Relative => unsafe {
-                objrel.addr().set(obj.base + addend);
+            RelKind::Relative => unsafe {
+                let value = obj.base + addend;
+                arch.write_word(objrel.addr(), value.0);
+                value
             },
-            RT::IRelative => unsafe {
-                type Selector = unsafe extern "C" fn() -> delf::Addr;
-                let selector: Selector = std::mem::transmute(obj.base + addend);
-                objrel.addr().set(selector());
+            RelKind::IRelative => unsafe {
+                // we're about to call it, it'd better be ours
+                if !obj.contains(addend, 1) {
+                    return Err(RelocationError::OffsetOutOfBounds(obj.path.clone(), addend));
+                }
+                let value = resolve(obj.base + addend);
+                arch.write_word(objrel.addr(), value.0);
+                value
             },
-            RT::Copy => unsafe {
+            RelKind::Copy => unsafe {
+                let len = found.size() as u64;
+                let copy_in_bounds = match &found {
+                    ResolvedSym::Defined(sym) => {
+                        obj.contains(rel.offset, len) && sym.obj.contains(sym.sym.sym.value, len)
+                    }
+                    ResolvedSym::Undefined => true,
+                };
+                if !copy_in_bounds {
+                    return Err(RelocationError::OffsetOutOfBounds(
+                        obj.path.clone(),
+                        rel.offset,
+                    ));
+                }
                 // write() takes a &[u8], so `as_slice`'s type is inferred correctly.
-                println!(
+                eprintln!(
                     "Copy: {} written to {:?} from {}",
                     objrel.addr(),
                     String::from_utf8_lossy(found.value().as_slice::<u8>(found.size())),
                     found.value()
                 );
                 objrel.addr().write(found.value().as_slice(found.size()));
+                found.value()
             },
-            RT::GlobDat | RT::JumpSlot => unsafe {
-                println!(
+            RelKind::GlobDat | RelKind::JumpSlot => unsafe {
+                eprintln!(
                     "{reltype:?}: at {}, {:?} set to {}",
                     objrel.addr(),
-                    *objrel.addr().as_ptr::<u64>(),
-                    found.value()
+                    arch.read_word(objrel.addr()),
+                    target
                 );
-                objrel.addr().set(found.value());
+                arch.write_word(objrel.addr(), target.0);
+                target
             },
-            RT::TPOff64 => unsafe {
-                if let ResolvedSym::Defined(sym) = found {
-                    let obj_offset = self
-                        .state
-                        .tls
-                        .offsets
-                        .get(&sym.obj.base)
-                        .unwrap_or_else(|| panic!("No thread-local storage allocated for object {:?}", sym.obj.file));
-                    let obj_offset = -(obj_offset.0 as i64);
-                    // sym sym sym hurray!
-                    let offset = obj_offset + sym.sym.sym.value.0 as i64 + objrel.rel.addend.0 as i64;
-                    objrel.addr().set(offset);
+            RelKind::TpOff => unsafe {
+                match &found {
+                    ResolvedSym::Defined(sym) => {
+                        let obj_offset =
+                            self.state.tls.offsets.get(&sym.obj.base).ok_or_else(|| {
+                                RelocationError::MissingTls(
+                                    obj.path.clone(),
+                                    rel.offset,
+                                    sym.obj.path.clone(),
+                                )
+                            })?;
+                        // sym sym sym hurray!
+                        let offset = obj_offset + sym.sym.sym.value.0 as i64 + addend.0 as i64;
+                        arch.write_word(objrel.addr(), offset as u64);
+                        delf::Addr(offset as u64)
+                    }
+                    ResolvedSym::Undefined => delf::Addr(0),
                 }
             },
-            RT::DTPMOD64 => {}
-            _ => {
-                return Err(RelocationError::UnimplementedRelocation(
-                    obj.path.clone(),
-                    reltype,
-                ))
+            RelKind::Pc32
+            | RelKind::Plt32
+            | RelKind::GotPcRel
+            | RelKind::Abs32
+            | RelKind::Abs32S => {
+                let got_slot = |sym| {
+                    obj.got
+                        .get(&sym)
+                        .map(|&offset| obj.base + offset)
+                        .ok_or_else(|| {
+                            RelocationError::UnknownSymbolNumber(obj.path.clone(), rel.offset, sym)
+                        })
+                };
+                let place = objrel.addr().0;
+
+                let value = match reltype {
+                    RelKind::Plt32 if obj.plt.contains_key(&rel.sym) => {
+                        // the stub jumps through the GOT, fill out its slot
+                        unsafe { arch.write_word(got_slot(rel.sym)?, target.0) };
+                        let stub = obj.base + obj.plt[&rel.sym];
+                        stub.0.wrapping_add(addend.0).wrapping_sub(place) as i64
+                    }
+                    RelKind::GotPcRel => {
+                        let slot = got_slot(rel.sym)?;
+                        unsafe { arch.write_word(slot, target.0) };
+                        slot.0.wrapping_add(addend.0).wrapping_sub(place) as i64
+                    }
+                    RelKind::Abs32 | RelKind::Abs32S => target.0.wrapping_add(addend.0) as i64,
+                    _ => target.0.wrapping_add(addend.0).wrapping_sub(place) as i64,
+                };
+
+                let fits = match reltype {
+                    RelKind::Abs32 => u32::try_from(value).is_ok(),
+                    _ => i32::try_from(value).is_ok(),
+                };
+                if !fits {
+                    return Err(RelocationError::Overflow(obj.path.clone(), rel.offset));
+                }
+                unsafe { objrel.addr().set(value as u32) };
+                delf::Addr(value as u64)
             }
-        }
-        Ok(())
+        };
+
+        Ok(Some(AppliedRelocation {
+            object: obj.base,
+            offset: rel.offset,
+            r#type: rel.r#type,
+            kind: reltype,
+            symbol: match rel.sym {
+                0 => None,
+                _ => Some(wanted.sym.name.clone()),
+            },
+            target: match &found {
+                ResolvedSym::Defined(sym) => Some((sym.obj.base, sym.value())),
+                ResolvedSym::Undefined => None,
+            },
+            target_index: match &found {
+                ResolvedSym::Defined(sym) => Some(sym.sym.index),
+                ResolvedSym::Undefined => None,
+            },
+            ifunc_resolver,
+            value,
+        }))
     }
 }
 
@@ -661,7 +1949,7 @@ impl Process<Relocated> {
             if let Some(ph) = obj.file.segment_of_type(delf::SegmentType::TLS) {
                 if let Some(offset) = tls.offsets.get(&obj.base).cloned() {
                     unsafe {
-                        (tls.tcb_addr - offset)
+                        delf::Addr((tls.tcb_addr.0 as i64 + offset) as u64)
                             .write((ph.vaddr + obj.base).as_slice(ph.filesz.into()));
                     }
                 }
@@ -692,21 +1980,30 @@ impl Process<TLSInitialized> {
     pub fn adjust_protections(self) -> Result<Process<Protected>, region::Error> {
         use region::{protect, Protection};
 
+        let map_executable = self.state.loader.map_executable;
         for obj in &self.state.loader.objects {
-            for seg in &obj.segments {
+            for (pages, flags) in obj.page_protections() {
                 let mut protection = Protection::NONE;
-                for flag in seg.flags.iter() {
+                for flag in flags.iter() {
                     protection |= match flag {
                         delf::SegmentFlag::Read => Protection::READ,
                         delf::SegmentFlag::Write => Protection::WRITE,
+                        delf::SegmentFlag::Execute if !map_executable => Protection::NONE,
                         delf::SegmentFlag::Execute => Protection::EXECUTE,
                     }
                 }
                 unsafe {
-                    protect(seg.map.data(), seg.map.len(), protection)?;
+                    protect(
+                        (obj.base + pages.start).as_ptr::<u8>(),
+                        (pages.end - pages.start).into(),
+                        protection,
+                    )?;
                 }
             }
         }
+        if let Some(tracer) = &self.state.loader.tracer {
+            tracer.protect()?;
+        }
 
         Ok(Process {
             state: Protected {
@@ -729,65 +2026,197 @@ impl ProcessState for Protected {
 }
 
 impl Process<Protected> {
-    pub fn start(self, opts: &StartOptions) -> ! {
+    pub fn start(mut self, opts: &StartOptions) -> ! {
+        let arch = self.arch();
+        // only now is there a program for a debugger attached to us to see
+        self.state.loader.debug_maps.publish();
         let exec = &self.state.loader.objects[opts.exec_index];
-        let entry_point = exec.file.entry_point + exec.base;
+        let entry_point = exec.entry_point();
         let stack = Self::build_stack(opts);
+        self.state.loader.audit.preinit(&exec.audit);
 
         unsafe {
-            crate::set_fs(self.state.tls.tcb_addr.0);
-            crate::jmp(entry_point.as_ptr(), stack.as_ptr(), stack.len())
+            arch.set_thread_pointer(self.state.tls.tcb_addr);
+            arch.enter(entry_point, &stack)
         };
     }
 
     fn build_stack(opts: &StartOptions) -> Vec<u64> {
+        Self::plan_stack(opts)
+            .into_iter()
+            .map(|(value, _)| value)
+            .collect()
+    }
+
+    /// Returns the initial stack contents, along with what each word is
+    fn plan_stack(opts: &StartOptions) -> Vec<(u64, String)> {
         let mut stack = Vec::new();
 
         let null = 0_u64;
 
         macro_rules! push {
-            ($x:expr) => {
-                stack.push($x as u64)
+            ($x:expr, $($role:tt)*) => {
+                stack.push(($x as u64, format!($($role)*)))
             };
         }
 
         // note: everything is pushed in reverse order
 
         // argc
-        push!(opts.args.len());
+        push!(opts.args.len(), "argc");
 
         // argv
-        for v in &opts.args {
+        for (i, v) in opts.args.iter().enumerate() {
             // `CString.as_ptr()` gives us the address of a memory
             // location containing a null-terminated string.
             // Note that we borrow `StartOptions`, so as long as it's
             // still live by the time we jump to the entry point, we
             // don't have to worry about it being freed too early.
-            push!(v.as_ptr());
+            push!(v.as_ptr(), "argv[{}] = {:?}", i, v);
         }
-        push!(null);
+        push!(null, "argv terminator");
 
         // envp
-        for v in &opts.env {
-            push!(v.as_ptr());
+        for (i, v) in opts.env.iter().enumerate() {
+            push!(v.as_ptr(), "envp[{}] = {:?}", i, v);
         }
-        push!(null);
+        push!(null, "envp terminator");
 
         // auxv
         for v in &opts.auxv {
-            push!(v.typ);
-            push!(v.value);
+            push!(v.typ, "auxv type {:?}", v.typ);
+            push!(v.value, "auxv value for {:?}", v.typ);
         }
-        push!(AuxType::Null);
-        push!(null);
+        push!(AuxType::Null, "auxv terminator");
+        push!(null, "auxv terminator");
 
         // align stack to 16-byte boundary
         if stack.len() % 2 == 1 {
-            stack.push(0);
+            push!(0, "padding");
         }
 
         stack
     }
+
+    /// Does everything `start` would do, short of actually starting the
+    /// program, and reports on it.
+    pub fn dry_run(&self, opts: &StartOptions) -> DryRunReport {
+        let loader = &self.state.loader;
+        let arch = self.arch();
+        let exec = &loader.objects[opts.exec_index];
+
+        let mut relocations: HashMap<delf::Addr, Vec<RelocationReport>> = HashMap::new();
+        for applied in &loader.applied_relocations {
+            let target = applied.target.map(|(base, addr)| {
+                let path = loader
+                    .objects
+                    .iter()
+                    .find(|obj| obj.base == base)
+                    .map(|obj| obj.path.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                (path, addr.0)
+            });
+            relocations
+                .entry(applied.object)
+                .or_default()
+                .push(RelocationReport {
+                    offset: applied.offset.0,
+                    r#type: applied.r#type,
+                    kind: format!("{:?}", applied.kind),
+                    symbol: applied
+                        .symbol
+                        .as_ref()
+                        .map(|name| String::from_utf8_lossy(name.as_slice()).into_owned()),
+                    target_addr: target.as_ref().map(|(_, addr)| *addr),
+                    target: target.map(|(path, _)| path),
+                    ifunc_resolver: applied.ifunc_resolver.map(|addr| addr.0),
+                    value: applied.value.0,
+                });
+        }
+
+        let objects = loader
+            .objects
+            .iter()
+            .map(|obj| ObjectReport {
+                path: obj.path.to_string_lossy().into_owned(),
+                base: obj.base.0,
+                segments: obj
+                    .segments
+                    .iter()
+                    .map(|seg| SegmentReport {
+                        vaddr_start: seg.vaddr_range.start.0,
+                        vaddr_end: seg.vaddr_range.end.0,
+                        addr: seg.map.data() as u64,
+                        len: seg.map.len() as u64,
+                        flags: seg.flags.iter().map(|flag| format!("{:?}", flag)).collect(),
+                    })
+                    .collect(),
+                relocations: relocations.remove(&obj.base).unwrap_or_default(),
+            })
+            .collect();
+
+        DryRunReport {
+            arch: arch.name(),
+            entry_point: exec.entry_point().0,
+            thread_pointer: self.state.tls.tcb_addr.0,
+            objects,
+            stack: Self::plan_stack(opts)
+                .into_iter()
+                .map(|(value, role)| StackReport { value, role })
+                .collect(),
+        }
+    }
+}
+
+/// What `Process::dry_run` found out
+#[derive(Debug, serde::Serialize)]
+pub struct DryRunReport {
+    pub arch: &'static str,
+    pub entry_point: u64,
+    pub thread_pointer: u64,
+    pub objects: Vec<ObjectReport>,
+    pub stack: Vec<StackReport>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ObjectReport {
+    pub path: String,
+    pub base: u64,
+    pub segments: Vec<SegmentReport>,
+    pub relocations: Vec<RelocationReport>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct SegmentReport {
+    pub vaddr_start: u64,
+    pub vaddr_end: u64,
+    /// where it's mapped
+    pub addr: u64,
+    pub len: u64,
+    pub flags: Vec<String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct RelocationReport {
+    pub offset: u64,
+    pub r#type: u32,
+    pub kind: String,
+    pub symbol: Option<String>,
+    /// path of the object that defines the symbol
+    pub target: Option<String>,
+    /// address of the symbol
+    pub target_addr: Option<u64>,
+    /// address of the IFUNC resolver that'd pick the real target, which
+    /// a dry run doesn't call
+    pub ifunc_resolver: Option<u64>,
+    /// what we wrote at the relocation site
+    pub value: u64,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct StackReport {
+    pub value: u64,
+    pub role: String,
 }
 
 use custom_debug_derive::Debug as CustomDebug;
@@ -803,10 +2232,46 @@ pub struct Segment {
     pub flags: BitFlags<delf::SegmentFlag>,
 }
 
+impl Segment {
+    fn new(
+        map: MemoryMap,
+        vaddr_range: Range<delf::Addr>,
+        padding: delf::Addr,
+        flags: BitFlags<delf::SegmentFlag>,
+    ) -> Self {
+        Self {
+            map: Arc::new(map),
+            vaddr_range,
+            padding,
+            flags,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct NamedSym {
     sym: delf::Sym,
+    /// Index in the object's symbol table
+    index: usize,
     name: Name,
+    /// For defined symbols, the version they define. For undefined
+    /// symbols, the version they need.
+    version: Option<String>,
+    /// Whether this is a non-default version (`foo@VER`, not `foo@@VER`)
+    hidden: bool,
+}
+
+impl NamedSym {
+    /// Whether this definition can satisfy a reference that needs `version`
+    fn provides(&self, version: Option<&str>) -> bool {
+        match (version, &self.version) {
+            // unversioned references bind to the default version
+            (None, _) => !self.hidden,
+            // unversioned definitions satisfy any version, like in glibc
+            (Some(_), None) => true,
+            (Some(wanted), Some(version)) => wanted == version,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -819,6 +2284,52 @@ impl ObjectSym<'_> {
     fn value(&self) -> delf::Addr {
         self.obj.base + self.sym.sym.value
     }
+
+    /// What auditors get to see, with `value` as the symbol's address
+    fn audit_sym(&self, value: delf::Addr) -> audit::Elf64Sym {
+        let sym = &self.sym.sym;
+        audit::Elf64Sym {
+            st_name: sym.name.0 as u32,
+            st_info: ((sym.bind as u8) << 4) | (sym.r#type as u8 & 0xf),
+            st_other: 0,
+            st_shndx: sym.shndx.0,
+            st_value: value.0,
+            st_size: sym.size,
+        }
+    }
+
+    /// `STT_GNU_IFUNC` symbols point to a resolver, not to the
+    /// function itself.
+    fn is_ifunc(&self) -> bool {
+        matches!(self.sym.sym.r#type, delf::SymType::IFunc)
+    }
+}
+
+/// What IFUNC resolvers get called with. glibc passes nothing on x86-64,
+/// but other architectures pass the hwcaps, and some resolvers look at
+/// them anyway - extra arguments are harmless.
+#[derive(Debug, Clone, Copy, Default)]
+struct IfuncArgs {
+    hwcap: u64,
+    hwcap2: u64,
+}
+
+impl IfuncArgs {
+    fn from_auxv() -> Self {
+        let get = |typ| Auxv::get(typ).map(|auxv| auxv.value).unwrap_or_default();
+        Self {
+            hwcap: get(AuxType::HwCap),
+            hwcap2: get(AuxType::HwCap2),
+        }
+    }
+
+    /// Calls the resolver at `resolver`, returns what it picked
+    unsafe fn call(self, resolver: delf::Addr) -> delf::Addr {
+        // resolvers return a pointer, which may be narrower than `Addr`
+        type Resolver = unsafe extern "C" fn(u64, u64) -> usize;
+        let resolver: Resolver = std::mem::transmute(resolver);
+        delf::Addr(resolver(self.hwcap, self.hwcap2) as u64)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -843,10 +2354,48 @@ impl ResolvedSym<'_> {
     }
 }
 
-#[derive(Debug)]
+/// A relocation entry, with its type left as a raw number: what it means
+/// depends on the object's architecture.
+#[derive(Debug, Clone)]
+pub struct Reloc {
+    pub offset: delf::Addr,
+    pub r#type: u32,
+    pub sym: u32,
+    pub addend: delf::Addr,
+}
+
+impl From<delf::Rela> for Reloc {
+    fn from(rela: delf::Rela) -> Self {
+        Self {
+            offset: rela.offset,
+            r#type: rela.r#type as u32,
+            sym: rela.sym,
+            addend: rela.addend,
+        }
+    }
+}
+
+/// What `apply_relocation` did. Objects are identified by their base.
+#[derive(Debug, Clone)]
+pub struct AppliedRelocation {
+    pub object: delf::Addr,
+    pub offset: delf::Addr,
+    pub r#type: u32,
+    pub kind: RelKind,
+    pub symbol: Option<Name>,
+    /// The object that defined the symbol, and the symbol's address
+    pub target: Option<(delf::Addr, delf::Addr)>,
+    /// Index of the symbol in the defining object's symbol table
+    pub target_index: Option<usize>,
+    /// The IFUNC resolver we didn't call, see `Process::map_executable`
+    pub ifunc_resolver: Option<delf::Addr>,
+    pub value: delf::Addr,
+}
+
+#[derive(Debug, Clone, Copy)]
 struct ObjectRel<'a> {
     obj: &'a Object,
-    rel: &'a delf::Rela,
+    rel: &'a Reloc,
 }
 
 impl ObjectRel<'_> {
@@ -863,7 +2412,7 @@ pub struct Object {
 
     // we're skipping this one because it would get *real* verbose
     #[debug(skip)]
-    pub file: delf::File<Vec<u8>>,
+    pub file: delf::File<FileMap>,
 
     pub mem_range: Range<delf::Addr>,
 
@@ -876,16 +2425,251 @@ pub struct Object {
     pub sym_map: MultiMap<Name, NamedSym>,
 
     #[debug(skip)]
-    pub rels: Vec<delf::Rela>,
+    pub rels: Vec<Reloc>,
+
+    #[debug(skip)]
+    pub versions: Option<Versions>,
+
+    // where to look for its dependencies, `$ORIGIN` already expanded
+    #[debug(skip)]
+    pub rpath: Vec<SearchDir>,
+    #[debug(skip)]
+    pub runpath: Vec<SearchDir>,
+
+    // what auditors and debuggers see
+    #[debug(skip)]
+    pub link_map: Box<LinkMap>,
+    #[debug(skip)]
+    pub audit: ObjectAudit,
+
+    // GOT slots and PLT stubs we allocated, keyed by symbol index.
+    // only relocatable objects have those.
+    #[debug(skip)]
+    pub got: HashMap<u32, delf::Addr>,
+    #[debug(skip)]
+    pub plt: HashMap<u32, delf::Addr>,
+}
+
+impl Object {
+    /// What each page of the object should be mapped as, once relocation
+    /// is done, as page-aligned ranges relative to our base.
+    ///
+    /// Pages shared by two segments get both sets of flags. The RELRO
+    /// range loses write access, but only for the pages it covers fully:
+    /// like glibc, we round its end down, so a page it shares with `.data`
+    /// stays writable.
+    pub fn page_protections(&self) -> Vec<(Range<delf::Addr>, BitFlags<delf::SegmentFlag>)> {
+        const PAGE_SIZE: u64 = 0x1000;
+        let align_down = |x: u64| x & !(PAGE_SIZE - 1);
+        let align_up = |x: u64| align_down(x + PAGE_SIZE - 1);
+
+        let mut pages: BTreeMap<u64, BitFlags<delf::SegmentFlag>> = BTreeMap::new();
+        for seg in &self.segments {
+            let start = align_down(seg.vaddr_range.start.0);
+            let end = align_up(seg.vaddr_range.end.0);
+            for page in (start..end).step_by(PAGE_SIZE as usize) {
+                *pages.entry(page).or_default() |= seg.flags;
+            }
+        }
+
+        if let Some(relro) = self.file.segment_of_type(delf::SegmentType::GnuRelRo) {
+            let range = relro.mem_range();
+            let start = align_down(range.start.0);
+            let end = align_down(range.end.0);
+            for (_, flags) in pages.range_mut(start..end) {
+                flags.remove(delf::SegmentFlag::Write);
+            }
+        }
+
+        // merge runs of contiguous pages with the same flags
+        let mut res: Vec<(Range<delf::Addr>, BitFlags<delf::SegmentFlag>)> = Vec::new();
+        for (page, flags) in pages {
+            let page = delf::Addr(page);
+            match res.last_mut() {
+                Some((range, last)) if range.end == page && *last == flags => {
+                    range.end = page + delf::Addr(PAGE_SIZE)
+                }
+                _ => res.push((page..page + delf::Addr(PAGE_SIZE), flags)),
+            }
+        }
+        res
+    }
+
+    /// Whether `len` bytes at `offset` (relative to our base) all fall
+    /// inside one of our segments.
+    pub fn contains(&self, offset: delf::Addr, len: u64) -> bool {
+        let end = match offset.0.checked_add(len) {
+            Some(end) => end,
+            None => return false,
+        };
+        self.segments
+            .iter()
+            .any(|seg| seg.vaddr_range.start <= offset && end <= seg.vaddr_range.end.0)
+    }
+
+    /// Points the `DT_DEBUG` entry of our dynamic section, if there is
+    /// one, at `r_debug`.
+    fn set_dt_debug(&self, r_debug: delf::Addr) {
+        if self.link_map.l_ld == 0 {
+            return;
+        }
+        // each entry is a tag and a value, and the last one's tag is DT_NULL
+        let mut offset = delf::Addr(self.link_map.l_ld) - self.base;
+        while self.contains(offset, 16) {
+            let entry = self.base + offset;
+            match unsafe { entry.as_slice::<u64>(2) } {
+                [0, _] => return,
+                [linkmap::DT_DEBUG, _] => {
+                    unsafe { (entry + delf::Addr(8)).set(r_debug.0) };
+                    return;
+                }
+                _ => offset = offset + delf::Addr(16),
+            }
+        }
+    }
+
+    /// Where execution starts, if this is the main executable. Relocatable
+    /// objects don't have an entry point, so we go with `_start`, like
+    /// a linker would.
+    pub fn entry_point(&self) -> delf::Addr {
+        if matches!(self.file.typ, delf::Type::Rel) {
+            self.sym_map
+                .get(&Name::owned("_start"))
+                .map(|sym| self.base + sym.sym.value)
+                .unwrap_or(self.base)
+        } else {
+            self.file.entry_point + self.base
+        }
+    }
 }
 
 use std::{
     cmp::{max, min},
+    fmt,
     ops::Range,
 };
 
 use crate::name::Name;
 
+/// Creates an anonymous, memory-backed file holding `contents`
+fn memfd(name: &str, contents: &[u8]) -> std::io::Result<std::fs::File> {
+    use std::{io::Write, os::unix::io::FromRawFd};
+
+    const MFD_CLOEXEC: u32 = 0x1;
+    extern "C" {
+        fn memfd_create(name: *const std::os::raw::c_char, flags: u32) -> i32;
+    }
+
+    let name =
+        CString::new(name).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let fd = unsafe { memfd_create(name.as_ptr(), MFD_CLOEXEC) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let mut file = unsafe { std::fs::File::from_raw_fd(fd) };
+    file.write_all(contents)?;
+    Ok(file)
+}
+
+const EI_CLASS: usize = 4;
+const ELFCLASS32: u8 = 1;
+const ELFCLASS64: u8 = 2;
+const EI_DATA: usize = 5;
+const EI_OSABI: usize = 7;
+const ELFOSABI_SYSV: u8 = 0;
+const ELFOSABI_GNU: u8 = 3;
+
+/// Checks `e_ident` before we hand the file to the parser, which only
+/// knows about 64-bit little-endian objects.
+fn check_ident(path: &Path, input: &[u8]) -> Result<(), LoadError> {
+    let invalid = |reason| LoadError::InvalidHeader(path.to_path_buf(), reason);
+
+    let ident = input.get(..16).ok_or_else(|| invalid("file too short"))?;
+    if &ident[..4] != b"\x7fELF" {
+        return Err(invalid("bad magic"));
+    }
+    match ident[EI_CLASS] {
+        ELFCLASS64 => {}
+        ELFCLASS32 => return Err(invalid("32-bit objects are not supported")),
+        _ => return Err(invalid("unknown class")),
+    }
+    if ident[EI_DATA] != 1 {
+        return Err(invalid("only little-endian objects are supported"));
+    }
+    match ident[EI_OSABI] {
+        ELFOSABI_SYSV | ELFOSABI_GNU => Ok(()),
+        abi => Err(LoadError::UnsupportedOsAbi(path.to_path_buf(), abi)),
+    }
+}
+
+/// Makes sure load segments can be mapped without stepping on anything:
+/// they must come from inside the file, and not overlap each other.
+fn validate_segments<'a>(
+    path: &Path,
+    segments: impl Iterator<Item = &'a delf::ProgramHeader>,
+    file_len: u64,
+) -> Result<(), LoadError> {
+    let mut ranges: Vec<(u64, u64, usize)> = Vec::new();
+    for (index, ph) in segments.enumerate() {
+        let invalid = |reason| Err(LoadError::InvalidSegment(path.to_path_buf(), index, reason));
+
+        match ph.offset.0.checked_add(ph.filesz.0) {
+            Some(end) if end <= file_len => {}
+            _ => return invalid("extends past the end of the file"),
+        }
+        if ph.filesz > ph.memsz {
+            return invalid("file size is larger than memory size");
+        }
+        if ph.vaddr.0 % 0x1000 != ph.offset.0 % 0x1000 {
+            return invalid("address and file offset aren't congruent modulo the page size");
+        }
+        let end = match ph.vaddr.0.checked_add(ph.memsz.0) {
+            Some(end) => end,
+            None => return invalid("extends past the end of the address space"),
+        };
+        if ph.memsz.0 > 0 {
+            ranges.push((ph.vaddr.0, end, index));
+        }
+    }
+
+    ranges.sort_unstable();
+    for pair in ranges.windows(2) {
+        if pair[1].0 < pair[0].1 {
+            return Err(LoadError::InvalidSegment(
+                path.to_path_buf(),
+                pair[1].2,
+                "overlaps another segment",
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Makes sure the TLS initialization image can be copied to each thread's
+/// block: it must fit in the block, and be part of a load segment, since
+/// that's where we copy it from. Load segments must be validated first.
+fn validate_tls<'a>(
+    path: &Path,
+    tls: &delf::ProgramHeader,
+    mut load_segments: impl Iterator<Item = &'a delf::ProgramHeader>,
+) -> Result<(), LoadError> {
+    let invalid = |reason| Err(LoadError::InvalidTls(path.to_path_buf(), reason));
+
+    if tls.filesz > tls.memsz {
+        return invalid("file size is larger than memory size");
+    }
+    let end = match tls.vaddr.0.checked_add(tls.filesz.0) {
+        Some(end) => end,
+        None => return invalid("extends past the end of the address space"),
+    };
+    if tls.filesz.0 > 0
+        && !load_segments.any(|ph| ph.vaddr.0 <= tls.vaddr.0 && end <= ph.vaddr.0 + ph.memsz.0)
+    {
+        return invalid("initialization image isn't part of a load segment");
+    }
+    Ok(())
+}
+
 fn convex_hull(a: Range<delf::Addr>, b: Range<delf::Addr>) -> Range<delf::Addr> {
     (min(a.start, b.start))..(max(a.end, b.end))
 }
@@ -901,3 +2685,196 @@ fn dump_maps(msg: &str) {
         .for_each(|line| println!("{}", line));
     println!("=============================");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Where the `samples/search.c` fixtures are
+    fn search_dir() -> PathBuf {
+        Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/samples/search"))
+            .canonicalize()
+            .unwrap()
+    }
+
+    /// Loads `search/<object>` with `library_path` as `LD_LIBRARY_PATH`,
+    /// and `search/default` as the only default directory. Returns each
+    /// dependency's name, the file it resolved to, and why.
+    fn search(object: &str, library_path: Option<&str>) -> Vec<(String, PathBuf, SearchReason)> {
+        let dir = search_dir();
+        let mut proc = Process::new();
+        proc.map_executable(false);
+        proc.state.loader.library_path = library_path.map(super::library_path).unwrap_or_default();
+        proc.state.loader.default_path = vec![SearchDir {
+            path: dir.join("default"),
+            reason: SearchReason::Default,
+        }];
+        proc.load_object_and_dependencies(dir.join(object)).unwrap();
+
+        let loader = &proc.state.loader;
+        loader
+            .dependencies
+            .iter()
+            .map(|dep| {
+                (
+                    dep.name.clone(),
+                    loader.objects[dep.to].path.clone(),
+                    dep.reason.clone(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rpath_chain() {
+        let dir = search_dir();
+        let libpath = dir.join("libpath");
+        let reason = SearchReason::RPath {
+            object: dir.join("with-rpath.so"),
+            entry: "$ORIGIN/rpath".into(),
+        };
+        assert!(reason.origin_expanded());
+
+        // RPATH comes before LD_LIBRARY_PATH, and `libchain.so` is found
+        // through the RPATH of the object that loaded `libmid.so`
+        let found = search("with-rpath.so", libpath.to_str());
+        assert_eq!(
+            found,
+            [
+                (
+                    "libleaf.so".into(),
+                    dir.join("rpath/libleaf.so"),
+                    reason.clone()
+                ),
+                (
+                    "libmid.so".into(),
+                    dir.join("rpath/libmid.so"),
+                    reason.clone()
+                ),
+                ("libchain.so".into(), dir.join("rpath/libchain.so"), reason),
+            ]
+        );
+    }
+
+    #[test]
+    fn library_path_before_runpath() {
+        let dir = search_dir();
+        let libpath = dir.join("libpath");
+        let libpath = libpath.to_str().unwrap();
+        // the missing directory and the empty entry (the current directory)
+        // don't have it, they're skipped
+        let found = search(
+            "with-runpath.so",
+            Some(&format!("/nonexistent:;{}", libpath)),
+        );
+        assert_eq!(
+            found,
+            [(
+                "libleaf.so".into(),
+                dir.join("libpath/libleaf.so"),
+                SearchReason::LibraryPath {
+                    entry: libpath.into()
+                },
+            )]
+        );
+    }
+
+    #[test]
+    fn runpath() {
+        let dir = search_dir();
+        let reason = SearchReason::RunPath {
+            object: dir.join("with-runpath.so"),
+            entry: "$ORIGIN/runpath".into(),
+        };
+        assert!(reason.origin_expanded());
+        assert_eq!(
+            search("with-runpath.so", None),
+            [("libleaf.so".into(), dir.join("runpath/libleaf.so"), reason)]
+        );
+    }
+
+    #[test]
+    fn default_path() {
+        let dir = search_dir();
+        assert_eq!(
+            search("plain.so", None),
+            [(
+                "libleaf.so".into(),
+                dir.join("default/libleaf.so"),
+                SearchReason::Default
+            )]
+        );
+    }
+
+    #[test]
+    fn intercept() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/samples/intercept.so");
+        let mut proc = Process::new();
+        proc.map_executable(false);
+        proc.record_relocations(true);
+        let index = proc.load_object_and_dependencies(path).unwrap();
+        // nothing runs, so the replacements don't need to be functions
+        let (greet, wave) = (delf::Addr(0x1000), delf::Addr(0x2000));
+        let real_greet = proc.intercept("greet", greet);
+        let real_wave = proc.intercept("wave", wave);
+        let proc = proc.allocate_tls().unwrap().apply_relocations().unwrap();
+
+        let arch = proc.arch();
+        let loader = &proc.state.loader;
+        let obj = &loader.objects[index];
+        let libgreet = &loader.objects[loader.dependencies[0].to];
+        let original = |name: &str| {
+            let sym = &libgreet.sym_map.get_vec(&Name::owned(name)).unwrap()[0];
+            (libgreet.base + sym.sym.value).0
+        };
+        unsafe {
+            assert_eq!(arch.read_word(real_greet), original("greet"));
+            assert_eq!(arch.read_word(real_wave), original("wave"));
+        }
+
+        // every binding to the originals now goes to the replacements
+        let mut bindings: Vec<_> = loader
+            .applied_relocations
+            .iter()
+            .filter(|applied| applied.object == obj.base)
+            .map(|applied| {
+                let name = applied.symbol.as_ref().unwrap().as_slice();
+                let name = String::from_utf8_lossy(name).into_owned();
+                let written = unsafe { arch.read_word(obj.base + applied.offset) };
+                (name, format!("{:?}", applied.kind), delf::Addr(written))
+            })
+            .collect();
+        bindings.sort();
+        assert_eq!(
+            bindings,
+            [
+                ("greet".into(), "JumpSlot".into(), greet),
+                ("wave".into(), "Absolute".into(), wave),
+                ("wave".into(), "GlobDat".into(), wave),
+            ]
+        );
+    }
+
+    #[test]
+    fn library_path_entries() {
+        let dirs = library_path("/a:/b;;/c:");
+        let found: Vec<_> = dirs
+            .iter()
+            .map(|dir| match &dir.reason {
+                SearchReason::LibraryPath { entry } => (dir.path.to_str().unwrap(), entry.as_str()),
+                reason => panic!("unexpected reason {:?}", reason),
+            })
+            .collect();
+        // empty entries are the current directory, like in ld.so
+        assert_eq!(
+            found,
+            [
+                ("/a", "/a"),
+                ("/b", "/b"),
+                (".", ""),
+                ("/c", "/c"),
+                (".", "")
+            ]
+        );
+    }
+}
@@ -1,13 +1,16 @@
 use nom::{
     branch::alt,
     bytes::complete::{tag, take_while, take_while1},
-    combinator::{all_consuming, map, opt, value},
+    combinator::{all_consuming, map, opt, rest, value, verify},
     error::ParseError,
     multi::many0,
-    sequence::{delimited, preceded, separated_pair, terminated, tuple},
+    number::complete::{le_u32, le_u64},
+    sequence::{pair, preceded, separated_pair, terminated, tuple},
     IResult, InputTakeAtPosition,
 };
-use std::fmt;
+use std::{borrow::Cow, fmt};
+
+use crate::process::AuxType;
 
 /// returns true if a character is a (lower-case) hexadecimal digit
 fn is_hex_digit(c: char) -> bool {
@@ -56,7 +59,7 @@ pub struct Perms {
     pub w: bool,
     /// executable
     pub x: bool,
-    /// not sure, tbh
+    /// private (copy-on-write), rather than shared
     pub p: bool,
 }
 
@@ -89,7 +92,11 @@ fn perms(i: &str) -> IResult<&str, Perms> {
             alt((value(false, tag("-")), value(true, tag(c))))(i)
         }
     }
-    let (i, (r, w, x, p)) = tuple((bit("r"), bit("w"), bit("x"), bit("p")))(i)?;
+    /// the last one is never "-": mappings are either private or shared
+    fn private(i: &str) -> IResult<&str, bool> {
+        alt((value(true, tag("p")), value(false, tag("s"))))(i)
+    }
+    let (i, (r, w, x, p)) = tuple((bit("r"), bit("w"), bit("x"), private))(i)?;
     Ok((i, Perms { r, w, x, p }))
 }
 
@@ -134,10 +141,23 @@ fn dev(i: &str) -> IResult<&str, Dev> {
 pub enum Source<'a> {
     /// not backed by a file
     Anonymous,
-    /// not backed by a file either, *and* special-purpose
+    /// anonymous, named with `prctl(PR_SET_VMA_ANON_NAME)`
+    AnonNamed(&'a str),
+    /// anonymous shared memory, named the same way
+    AnonShmem(&'a str),
+    /// the main thread's stack, or another thread's (older kernels only)
+    Stack(Option<u32>),
+    /// kernel data for the vDSO
+    Vvar,
+    /// the legacy fixed-address system call page
+    Vsyscall,
+    /// not backed by a file either, *and* special-purpose ([heap],
+    /// [vdso], etc.)
     Special(&'a str),
+    /// a `memfd_create` file, which has no path
+    Memfd(Cow<'a, str>),
     /// backed by a file
-    File(&'a str),
+    File(Cow<'a, str>),
 }
 
 impl<'a> Source<'_> {
@@ -146,27 +166,72 @@ impl<'a> Source<'_> {
     }
 }
 
-fn source(i: &str) -> IResult<&str, Source<'_>> {
-    fn is_path_character(c: char) -> bool {
-        // kinda jank, and won't support files with actual spaces in them,
-        // but largely good enough for our use case.
-        c != ']' && !c.is_whitespace()
+impl fmt::Display for Source<'_> {
+    /// Shows the source like `/proc/:pid/maps` does, minus the escaping
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Anonymous => write!(f, "anonymous memory"),
+            Self::AnonNamed(name) => write!(f, "[anon:{}]", name),
+            Self::AnonShmem(name) => write!(f, "[anon_shmem:{}]", name),
+            Self::Stack(None) => write!(f, "[stack]"),
+            Self::Stack(Some(tid)) => write!(f, "[stack:{}]", tid),
+            Self::Vvar => write!(f, "[vvar]"),
+            Self::Vsyscall => write!(f, "[vsyscall]"),
+            Self::Special(name) => write!(f, "[{}]", name),
+            Self::Memfd(name) => write!(f, "/memfd:{}", name),
+            Self::File(path) => write!(f, "{:?}", path),
+        }
     }
+}
 
-    fn path(i: &str) -> IResult<&str, &str> {
-        take_while(is_path_character)(i)
+/// The kernel escapes newlines in paths as `\012`, and nothing else.
+fn unescape(path: &str) -> Cow<'_, str> {
+    if path.contains("\\012") {
+        Cow::Owned(path.replace("\\012", "\n"))
+    } else {
+        Cow::Borrowed(path)
     }
+}
 
-    alt((
-        map(delimited(tag("["), path, tag("]")), Source::Special),
-        map(path, |s| {
-            if s.is_empty() {
-                Source::Anonymous
-            } else {
-                Source::File(s)
-            }
-        }),
-    ))(i)
+/// Figures out the source from the rest of a `maps` line (after the
+/// inode), and whether it's been deleted.
+fn classify_source(rest: &str) -> (Source<'_>, bool) {
+    // paths can contain anything but a newline, spaces included, so this
+    // is only ever a suffix.
+    let (path, deleted) = match rest.strip_suffix(" (deleted)") {
+        Some(path) => (path, true),
+        None => (rest, false),
+    };
+
+    let source = if path.is_empty() {
+        Source::Anonymous
+    } else if let Some(name) = path.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+        if let Some(name) = name.strip_prefix("anon:") {
+            Source::AnonNamed(name)
+        } else if let Some(name) = name.strip_prefix("anon_shmem:") {
+            Source::AnonShmem(name)
+        } else if name == "stack" {
+            Source::Stack(None)
+        } else if let Some(tid) = name.strip_prefix("stack:").and_then(|s| s.parse().ok()) {
+            Source::Stack(Some(tid))
+        } else if name == "vvar" {
+            Source::Vvar
+        } else if name == "vsyscall" {
+            Source::Vsyscall
+        } else {
+            Source::Special(name)
+        }
+    } else if let Some(name) = path.strip_prefix("/memfd:") {
+        Source::Memfd(unescape(name))
+    } else {
+        Source::File(unescape(path))
+    };
+    (source, deleted)
+}
+
+/// parses everything up to the end of the line as a mapping source
+fn source(i: &str) -> IResult<&str, (Source<'_>, bool)> {
+    map(take_while(|c| c != '\n'), classify_source)(i)
 }
 
 #[derive(Debug)]
@@ -181,14 +246,14 @@ pub struct Mapping<'a> {
 }
 
 fn mapping(i: &str) -> IResult<&str, Mapping> {
-    let (i, (addr_range, perms, offset, dev, len, source, deleted)) = tuple((
+    let (i, (addr_range, perms, offset, dev, len, (source, deleted))) = tuple((
         spaced(hex_addr_range),
         spaced(perms),
         spaced(hex_addr),
         spaced(dev),
         spaced(dec_number),
-        spaced(source),
-        spaced(map(opt(tag("(deleted)")), |o| o.is_some())),
+        // not `spaced`: trailing spaces are part of the path
+        source,
     ))(i)?;
     let res = Mapping {
         addr_range,
@@ -205,3 +270,498 @@ fn mapping(i: &str) -> IResult<&str, Mapping> {
 pub fn mappings(i: &str) -> IResult<&str, Vec<Mapping>> {
     all_consuming(many0(terminated(spaced(mapping), tag("\n"))))(i)
 }
+
+/// Memory usage counters from `/proc/:pid/smaps` (or `smaps_rollup`), in
+/// KiB. Counters the kernel doesn't report stay at zero.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct MemStats {
+    pub size: u64,
+    pub rss: u64,
+    pub pss: u64,
+    pub shared_clean: u64,
+    pub shared_dirty: u64,
+    pub private_clean: u64,
+    pub private_dirty: u64,
+    pub swap: u64,
+    pub anon_huge_pages: u64,
+}
+
+impl MemStats {
+    /// Sets the counter for a field, as named in `smaps`. Returns false
+    /// for fields we don't keep track of.
+    fn set(&mut self, field: &str, value: u64) -> bool {
+        let counter = match field {
+            "Size" => &mut self.size,
+            "Rss" => &mut self.rss,
+            "Pss" => &mut self.pss,
+            "Shared_Clean" => &mut self.shared_clean,
+            "Shared_Dirty" => &mut self.shared_dirty,
+            "Private_Clean" => &mut self.private_clean,
+            "Private_Dirty" => &mut self.private_dirty,
+            "Swap" => &mut self.swap,
+            "AnonHugePages" => &mut self.anon_huge_pages,
+            _ => return false,
+        };
+        *counter = value;
+        true
+    }
+}
+
+impl std::ops::AddAssign for MemStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.size += rhs.size;
+        self.rss += rhs.rss;
+        self.pss += rhs.pss;
+        self.shared_clean += rhs.shared_clean;
+        self.shared_dirty += rhs.shared_dirty;
+        self.private_clean += rhs.private_clean;
+        self.private_dirty += rhs.private_dirty;
+        self.swap += rhs.swap;
+        self.anon_huge_pages += rhs.anon_huge_pages;
+    }
+}
+
+/// A mapping, as seen in `/proc/:pid/smaps`
+#[derive(Debug)]
+pub struct SmapsEntry<'a> {
+    pub mapping: Mapping<'a>,
+    pub stats: MemStats,
+    /// Two-letter flags, like "rd", "ex" or "mr" (see `man 5 proc`)
+    pub vm_flags: Vec<&'a str>,
+}
+
+/// One line of an smaps entry, after the mapping itself
+enum SmapsField<'a> {
+    /// "Rss:  1234 kB", "THPeligible:  0", etc.
+    Counter(&'a str, u64),
+    VmFlags(Vec<&'a str>),
+}
+
+fn smaps_field(i: &str) -> IResult<&str, SmapsField<'_>> {
+    fn is_name_character(c: char) -> bool {
+        c.is_ascii_alphanumeric() || c == '_'
+    }
+
+    let vm_flags = map(
+        preceded(
+            tag("VmFlags:"),
+            many0(spaced(take_while1(|c: char| c.is_ascii_alphanumeric()))),
+        ),
+        SmapsField::VmFlags,
+    );
+    let counter = map(
+        tuple((
+            take_while1(is_name_character),
+            tag(":"),
+            spaced(dec_number),
+            opt(spaced(tag("kB"))),
+        )),
+        |(name, _, value, _)| SmapsField::Counter(name, value),
+    );
+    terminated(alt((vm_flags, counter)), tag("\n"))(i)
+}
+
+fn smaps_entry(i: &str) -> IResult<&str, SmapsEntry<'_>> {
+    let (i, mapping) = terminated(spaced(mapping), tag("\n"))(i)?;
+    let (i, fields) = many0(smaps_field)(i)?;
+
+    let mut entry = SmapsEntry {
+        mapping,
+        stats: MemStats::default(),
+        vm_flags: Vec::new(),
+    };
+    for field in fields {
+        match field {
+            SmapsField::Counter(name, value) => {
+                entry.stats.set(name, value);
+            }
+            SmapsField::VmFlags(flags) => entry.vm_flags = flags,
+        }
+    }
+    Ok((i, entry))
+}
+
+/// Parses the contents of `/proc/:pid/smaps`
+pub fn smaps(i: &str) -> IResult<&str, Vec<SmapsEntry<'_>>> {
+    all_consuming(many0(smaps_entry))(i)
+}
+
+/// Parses the contents of `/proc/:pid/smaps_rollup`: a single entry for
+/// a pseudo-mapping that spans the whole address space, with the sums of
+/// every counter.
+pub fn smaps_rollup(i: &str) -> IResult<&str, MemStats> {
+    map(all_consuming(smaps_entry), |entry| entry.stats)(i)
+}
+
+/// An entry of `/proc/:pid/auxv`
+#[derive(Debug, Clone, Copy)]
+pub struct AuxEntry {
+    /// `None` for types we don't know about
+    pub typ: Option<AuxType>,
+    pub raw_type: u64,
+    pub value: u64,
+}
+
+/// Parses the contents of `/proc/:pid/auxv`, which is in the process's
+/// native format: pairs of 4-byte words for 32-bit processes, 8-byte words
+/// for 64-bit ones. Stops at `AT_NULL`.
+pub fn auxv(i: &[u8], word_size: usize) -> IResult<&[u8], Vec<AuxEntry>> {
+    fn native_word(word_size: usize) -> impl Fn(&[u8]) -> IResult<&[u8], u64> + Copy {
+        move |i: &[u8]| {
+            if word_size == 4 {
+                map(le_u32, u64::from)(i)
+            } else {
+                le_u64(i)
+            }
+        }
+    }
+    let word = native_word(word_size);
+    let entry = map(tuple((word, word)), |(raw_type, value)| AuxEntry {
+        typ: AuxType::from_u64(raw_type),
+        raw_type,
+        value,
+    });
+    terminated(
+        many0(verify(entry, |e: &AuxEntry| {
+            e.raw_type != AuxType::Null as u64
+        })),
+        tuple((word, word)),
+    )(i)
+}
+
+/// Parses NUL-terminated strings, like `/proc/:pid/cmdline` and
+/// `/proc/:pid/environ`. The last one may be missing its terminator: a
+/// process can overwrite its own arguments.
+pub fn nul_separated(i: &[u8]) -> IResult<&[u8], Vec<&[u8]>> {
+    let (i, (mut strings, last)) = pair(
+        many0(terminated(take_while(|b| b != 0), tag(&b"\0"[..]))),
+        rest,
+    )(i)?;
+    if !last.is_empty() {
+        strings.push(last);
+    }
+    Ok((i, strings))
+}
+
+/// The parts of `/proc/:pid/status` we care about. Fields are missing
+/// when the kernel doesn't report them: kernel threads have no `VmRSS`,
+/// and there's no `Seccomp` without `CONFIG_SECCOMP`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Status {
+    pub name: Option<String>,
+    /// PID of the process tracing this one, 0 if there's none
+    pub tracer_pid: Option<u32>,
+    /// 0: disabled, 1: strict, 2: filter
+    pub seccomp: Option<u32>,
+    /// Resident set size, in KiB
+    pub vm_rss: Option<u64>,
+    pub threads: Option<u32>,
+    /// Effective capabilities, as a bit set
+    pub cap_eff: Option<u64>,
+}
+
+/// Parses the contents of `/proc/:pid/status`
+pub fn status(i: &str) -> IResult<&str, Status> {
+    let line = terminated(
+        separated_pair(
+            take_while1(|c| c != ':' && c != '\n'),
+            tag(":"),
+            spaced(take_while(|c| c != '\n')),
+        ),
+        tag("\n"),
+    );
+    let (i, lines) = all_consuming(many0(line))(i)?;
+
+    let mut status = Status::default();
+    for (key, val) in lines {
+        match key {
+            "Name" => status.name = Some(val.to_string()),
+            "TracerPid" => status.tracer_pid = val.parse().ok(),
+            "Seccomp" => status.seccomp = val.parse().ok(),
+            "VmRSS" => status.vm_rss = val.trim_end_matches("kB").trim().parse().ok(),
+            "Threads" => status.threads = val.parse().ok(),
+            "CapEff" => status.cap_eff = u64::from_str_radix(val, 16).ok(),
+            _ => {}
+        }
+    }
+    Ok((i, status))
+}
+
+/// Another process's memory, through `/proc/:pid/mem`. Reading it takes
+/// the same permissions as attaching with ptrace.
+pub struct ProcessMemory {
+    file: std::fs::File,
+}
+
+impl ProcessMemory {
+    pub fn open(pid: u32) -> std::io::Result<Self> {
+        let file = std::fs::File::open(format!("/proc/{}/mem", pid))?;
+        Ok(Self { file })
+    }
+
+    /// Reads up to `len` bytes at `addr`. Reads stop early at the first
+    /// page that isn't mapped (or readable), so this may come up short.
+    pub fn read(&self, addr: u64, len: usize) -> std::io::Result<Vec<u8>> {
+        use std::os::unix::fs::FileExt;
+
+        let mut buf = vec![0u8; len];
+        let mut done = 0;
+        while done < len {
+            match self.file.read_at(&mut buf[done..], addr + done as u64) {
+                Ok(0) => break,
+                Ok(n) => done += n,
+                // only an error if we couldn't read anything at all
+                Err(_) if done > 0 => break,
+                Err(e) => return Err(e),
+            }
+        }
+        buf.truncate(done);
+        Ok(buf)
+    }
+
+    pub fn read_u64(&self, addr: u64) -> std::io::Result<u64> {
+        let bytes = self.read(addr, 8)?;
+        let bytes: [u8; 8] = bytes
+            .as_slice()
+            .try_into()
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "short read"))?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// Reads a NUL-terminated string at `addr`, of at most `max_len` bytes
+    pub fn read_cstr(&self, addr: u64, max_len: usize) -> std::io::Result<Vec<u8>> {
+        let mut bytes = self.read(addr, max_len)?;
+        if let Some(len) = bytes.iter().position(|&b| b == 0) {
+            bytes.truncate(len);
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// As the kernel pads it, except for the anonymous mapping's trailing
+    /// space, which it really does print
+    const MAPS: &str = "\
+5581a8a00000-5581a8a02000 r--p 00000000 fd:01 1234                       /usr/bin/cat
+5581a8a02000-5581a8a06000 r-xp 00002000 fd:01 1234                       /usr/bin/cat
+5581aa000000-5581aa021000 rw-p 00000000 00:00 0                          [heap]
+7f0000000000-7f0000001000 rw-s 00000000 00:01 42                         /memfd:jit code (deleted)
+7f0000001000-7f0000002000 r--p 00001000 fd:01 99                         /tmp/with space/lib.so (deleted)
+7f0000002000-7f0000003000 r--p 00000000 fd:01 100                        /tmp/new\\012line.so
+7f0000003000-7f0000004000 rw-p 00000000 00:00 0                          [anon:my arena]
+7f0000004000-7f0000005000 rw-s 00000000 00:01 7                          [anon_shmem:shared]
+7f0000005000-7f0000006000 rw-p 00000000 00:00 0 
+7f0000006000-7f0000007000 rw-p 00000000 00:00 0                          [stack:4242]
+7ffc00000000-7ffc00021000 rw-p 00000000 00:00 0                          [stack]
+7ffc00100000-7ffc00104000 r--p 00000000 00:00 0                          [vvar]
+7ffc00104000-7ffc00106000 r-xp 00000000 00:00 0                          [vdso]
+ffffffffff600000-ffffffffff601000 --xp 00000000 00:00 0                  [vsyscall]
+";
+
+    #[test]
+    fn maps_fields() {
+        let (_, maps) = mappings(MAPS).unwrap();
+        assert_eq!(maps.len(), 14);
+
+        let text = &maps[1];
+        assert_eq!(
+            text.addr_range,
+            delf::Addr(0x5581a8a02000)..delf::Addr(0x5581a8a06000)
+        );
+        assert_eq!(format!("{:?}", text.perms), "r-xp");
+        assert_eq!(text.offset, delf::Addr(0x2000));
+        assert_eq!((text.dev.major, text.dev.minor), (0xfd, 1));
+        assert_eq!(text.len, 1234);
+
+        // shared mappings
+        assert_eq!(format!("{:?}", maps[3].perms), "rw--");
+        assert_eq!(format!("{:?}", maps[13].perms), "--xp");
+    }
+
+    #[test]
+    fn maps_sources() {
+        let (_, maps) = mappings(MAPS).unwrap();
+        let sources: Vec<_> = maps.iter().map(|m| (&m.source, m.deleted)).collect();
+
+        assert!(matches!(sources[0], (Source::File(path), false) if path == "/usr/bin/cat"));
+        assert!(matches!(sources[2], (Source::Special("heap"), false)));
+        assert!(matches!(sources[3], (Source::Memfd(name), true) if name == "jit code"));
+        // spaces are part of the path, only the suffix means deleted
+        assert!(matches!(
+            sources[4],
+            (Source::File(path), true) if path == "/tmp/with space/lib.so"
+        ));
+        assert!(matches!(sources[5], (Source::File(path), false) if path == "/tmp/new\nline.so"));
+        assert!(matches!(sources[6], (Source::AnonNamed("my arena"), false)));
+        assert!(matches!(sources[7], (Source::AnonShmem("shared"), false)));
+        assert!(matches!(sources[8], (Source::Anonymous, false)));
+        assert!(matches!(sources[9], (Source::Stack(Some(4242)), false)));
+        assert!(matches!(sources[10], (Source::Stack(None), false)));
+        assert!(matches!(sources[11], (Source::Vvar, false)));
+        assert!(matches!(sources[12], (Source::Special("vdso"), false)));
+        assert!(matches!(sources[13], (Source::Vsyscall, false)));
+    }
+
+    #[test]
+    fn unescape_only_newlines() {
+        assert_eq!(unescape("/plain/path"), "/plain/path");
+        assert!(matches!(unescape("/plain/path"), Cow::Borrowed(_)));
+        assert_eq!(unescape("/a\\012b\\012"), "/a\nb\n");
+        // the kernel doesn't escape backslashes, so this is literal
+        assert_eq!(unescape("/a\\b"), "/a\\b");
+    }
+
+    #[test]
+    fn maps_reject_garbage() {
+        assert!(mappings("not a mapping\n").is_err());
+        assert!(mappings("0000-1000 rwxq 00000000 00:00 0\n").is_err());
+    }
+
+    const SMAPS: &str = "\
+5581a8a00000-5581a8a02000 r--p 00000000 fd:01 1234                       /usr/bin/cat
+Size:                  8 kB
+KernelPageSize:        4 kB
+Rss:                   8 kB
+Pss:                   4 kB
+Shared_Clean:          8 kB
+Private_Dirty:         0 kB
+THPeligible:    0
+VmFlags: rd mr mw me sd 
+7f0000005000-7f0000006000 rw-p 00000000 00:00 0 
+Size:                  4 kB
+Rss:                   4 kB
+Pss:                   4 kB
+Private_Dirty:         4 kB
+Swap:                  0 kB
+VmFlags: rd wr mr mw me ac sd 
+";
+
+    #[test]
+    fn smaps_entries() {
+        let (_, entries) = smaps(SMAPS).unwrap();
+        assert_eq!(entries.len(), 2);
+
+        assert!(matches!(&entries[0].mapping.source, Source::File(path) if path == "/usr/bin/cat"));
+        assert_eq!(
+            entries[0].stats,
+            MemStats {
+                size: 8,
+                rss: 8,
+                pss: 4,
+                shared_clean: 8,
+                ..Default::default()
+            }
+        );
+        assert_eq!(entries[0].vm_flags, ["rd", "mr", "mw", "me", "sd"]);
+
+        assert!(matches!(entries[1].mapping.source, Source::Anonymous));
+        assert_eq!(entries[1].stats.private_dirty, 4);
+        assert_eq!(entries[1].vm_flags.len(), 7);
+
+        let mut total = MemStats::default();
+        for entry in &entries {
+            total += entry.stats;
+        }
+        assert_eq!((total.size, total.rss, total.pss), (12, 12, 8));
+    }
+
+    #[test]
+    fn smaps_rollup_totals() {
+        let input = "\
+00400000-7ffc00021000 ---p 00000000 00:00 0                              [rollup]
+Rss:                1876 kB
+Pss:                 512 kB
+Pss_Anon:            100 kB
+Swap:                 16 kB
+";
+        let (_, stats) = smaps_rollup(input).unwrap();
+        assert_eq!((stats.rss, stats.pss, stats.swap), (1876, 512, 16));
+    }
+
+    fn words(words: &[u64], word_size: usize) -> Vec<u8> {
+        words
+            .iter()
+            .flat_map(|w| w.to_le_bytes()[..word_size].to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn auxv_64_and_32_bit() {
+        // AT_PAGESZ, an unknown type, AT_ENTRY, AT_NULL, then junk
+        let raw = [6, 0x1000, 0x77, 5, 9, 0x401000, 0, 0, 0xdead, 0xbeef];
+        for word_size in [8, 4] {
+            let input = words(&raw, word_size);
+            let (rest, entries) = auxv(&input, word_size).unwrap();
+            assert_eq!(rest.len(), 2 * word_size);
+
+            let entries: Vec<_> = entries
+                .iter()
+                .map(|e| (e.typ.map(|t| t as u64), e.raw_type, e.value))
+                .collect();
+            assert_eq!(
+                entries,
+                vec![
+                    (Some(6), 6, 0x1000),
+                    (None, 0x77, 5),
+                    (Some(9), 9, 0x401000)
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn auxv_needs_terminator() {
+        let input = words(&[6, 0x1000, 9], 8);
+        assert!(auxv(&input, 8).is_err());
+    }
+
+    #[test]
+    fn nul_separated_strings() {
+        let (_, strings) = nul_separated(b"ls\0-l\0\0/tmp\0").unwrap();
+        assert_eq!(strings, vec![&b"ls"[..], b"-l", b"", b"/tmp"]);
+        // overwritten by the process, without a final NUL
+        let (_, strings) = nul_separated(b"nginx: worker").unwrap();
+        assert_eq!(strings, vec![&b"nginx: worker"[..]]);
+        let (_, strings) = nul_separated(b"").unwrap();
+        assert!(strings.is_empty());
+    }
+
+    #[test]
+    fn status_fields() {
+        let input = "\
+Name:\tcat
+Umask:\t0022
+State:\tR (running)
+TracerPid:\t0
+Uid:\t1000\t1000\t1000\t1000
+VmRSS:\t    1876 kB
+Threads:\t1
+CapEff:\t000001ffffffffff
+Seccomp:\t2
+";
+        let (_, status) = status(input).unwrap();
+        assert_eq!(
+            status,
+            Status {
+                name: Some("cat".into()),
+                tracer_pid: Some(0),
+                seccomp: Some(2),
+                vm_rss: Some(1876),
+                threads: Some(1),
+                cap_eff: Some(0x1ff_ffff_ffff),
+            }
+        );
+    }
+
+    #[test]
+    fn status_kernel_thread() {
+        // no VmRSS, no Seccomp
+        let (_, status) = status("Name:\tkthreadd\nTracerPid:\t0\nThreads:\t1\n").unwrap();
+        assert_eq!(status.name.as_deref(), Some("kthreadd"));
+        assert_eq!(status.vm_rss, None);
+        assert_eq!(status.seccomp, None);
+    }
+}
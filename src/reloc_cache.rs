@@ -0,0 +1,174 @@
+//! A prelink-style cache of symbol resolution results. Looking up symbols
+//! is most of the work `apply_relocations` does, and as long as none of
+//! the objects change, it always comes up with the same answers - so we
+//! write them down, and replay them next time.
+//!
+//! Cache entries are keyed on the build-id and mtime of every object in
+//! the closure, in load order. Nothing is ever evicted: remove the cache
+//! directory to start over.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{buildid, process::Object};
+
+/// Where a relocation's symbol was found: object index (in load order),
+/// and symbol index in that object. `None` for relocations without a
+/// symbol, or undefined weak symbols.
+pub type CachedBinding = Option<(usize, usize)>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyEntry {
+    path: PathBuf,
+    build_id: Option<String>,
+    mtime: (u64, u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Key {
+    objects: Vec<KeyEntry>,
+}
+
+impl Key {
+    /// Returns `None` if we can't stat one of the objects, in which case
+    /// we don't use the cache at all.
+    pub fn new(objects: &[Object]) -> Option<Self> {
+        let objects = objects
+            .iter()
+            .map(|obj| {
+                let mtime = fs::metadata(&obj.path)
+                    .and_then(|meta| meta.modified())
+                    .ok()?
+                    .duration_since(UNIX_EPOCH)
+                    .ok()?;
+                Some(KeyEntry {
+                    path: obj.path.clone(),
+                    build_id: buildid::build_id(&obj.file).map(|id| buildid::to_hex(&id)),
+                    mtime: (mtime.as_secs(), mtime.subsec_nanos()),
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self { objects })
+    }
+
+    /// The cache file name: a FNV-1a hash of the key. Collisions are
+    /// harmless, since the full key is stored in the file and checked.
+    fn file_name(&self) -> String {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for entry in &self.objects {
+            let bytes = entry
+                .path
+                .to_string_lossy()
+                .bytes()
+                .chain(entry.build_id.iter().flat_map(|id| id.bytes()))
+                .chain(entry.mtime.0.to_le_bytes())
+                .chain(entry.mtime.1.to_le_bytes())
+                .collect::<Vec<_>>();
+            for b in bytes {
+                hash ^= b as u64;
+                hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+            }
+        }
+        format!("{:016x}.json", hash)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    key: Key,
+    /// One per relocation, in the order `apply_relocations` goes through them
+    bindings: Vec<CachedBinding>,
+}
+
+/// Returns the cached bindings for `key`, if there are any
+pub fn load(dir: &Path, key: &Key) -> Option<Vec<CachedBinding>> {
+    let contents = fs::read(dir.join(key.file_name())).ok()?;
+    let entry: Entry = serde_json::from_slice(&contents).ok()?;
+    if &entry.key != key {
+        return None;
+    }
+    Some(entry.bindings)
+}
+
+pub fn store(dir: &Path, key: &Key, bindings: Vec<CachedBinding>) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let entry = Entry {
+        key: key.clone(),
+        bindings,
+    };
+    // write then rename, so concurrent runs never see a partial file
+    let path = dir.join(key.file_name());
+    let tmp = path.with_extension(format!("tmp.{}", std::process::id()));
+    fs::write(&tmp, serde_json::to_vec(&entry)?)?;
+    fs::rename(tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(mtime: u64) -> Key {
+        Key {
+            objects: vec![
+                KeyEntry {
+                    path: "/usr/bin/true".into(),
+                    build_id: Some("0123456789abcdef".into()),
+                    mtime: (mtime, 0),
+                },
+                KeyEntry {
+                    path: "/usr/lib/libc.so.6".into(),
+                    build_id: None,
+                    mtime: (mtime, 500),
+                },
+            ],
+        }
+    }
+
+    /// A fresh directory, that doesn't exist yet
+    fn cache_dir(test: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("elk-reloc-cache-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = cache_dir("round-trip");
+        let bindings = vec![Some((0, 3)), None, Some((1, 1234))];
+
+        assert_eq!(load(&dir, &key(1)), None);
+        store(&dir, &key(1), bindings.clone()).unwrap();
+        assert_eq!(load(&dir, &key(1)), Some(bindings));
+        // any object changing makes it a different key
+        assert_eq!(load(&dir, &key(2)), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn checks_the_full_key() {
+        let dir = cache_dir("full-key");
+        store(&dir, &key(1), vec![Some((0, 1))]).unwrap();
+        // as if `key(2)` hashed to the same file name
+        fs::rename(dir.join(key(1).file_name()), dir.join(key(2).file_name())).unwrap();
+        assert_eq!(load(&dir, &key(2)), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ignores_garbage() {
+        let dir = cache_dir("garbage");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(key(1).file_name()), b"{\"key\": ").unwrap();
+        assert_eq!(load(&dir, &key(1)), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
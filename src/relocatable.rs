@@ -0,0 +1,317 @@
+//! Relocatable objects (`ET_REL`, aka `.o` files) have no segments, just
+//! sections, so nobody has decided where they go in memory yet - that's
+//! our job. This module has the bits shared by the loader and the linker.
+
+use std::{collections::HashMap, ops::Range};
+
+use crate::{
+    arch::{Arch, RelKind},
+    process::Reloc,
+};
+
+pub const SHF_WRITE: u64 = 0x1;
+pub const SHF_ALLOC: u64 = 0x2;
+pub const SHF_EXECINSTR: u64 = 0x4;
+
+/// Symbols that haven't been allocated yet (`int foo;` in C, pre-`-fno-common`)
+pub const SHN_COMMON: u16 = 0xfff2;
+
+/// Size of a PLT stub, see `Arch::plt_stub`
+pub const PLT_STUB_SIZE: u64 = 16;
+
+const PAGE_SIZE: u64 = 0x1000;
+
+/// The segment an allocated section ends up in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SectionKind {
+    Text,
+    ReadOnly,
+    Data,
+}
+
+impl SectionKind {
+    pub const ALL: [Self; 3] = [Self::Text, Self::ReadOnly, Self::Data];
+
+    pub fn of(sh: &delf::SectionHeader) -> Option<Self> {
+        if sh.flags & SHF_ALLOC == 0 {
+            return None;
+        }
+        Some(if sh.flags & SHF_EXECINSTR != 0 {
+            Self::Text
+        } else if sh.flags & SHF_WRITE != 0 {
+            Self::Data
+        } else {
+            Self::ReadOnly
+        })
+    }
+
+    pub fn flags(self) -> enumflags2::BitFlags<delf::SegmentFlag> {
+        use delf::SegmentFlag as SF;
+        match self {
+            Self::Text => SF::Read | SF::Execute,
+            Self::ReadOnly => SF::Read.into(),
+            Self::Data => SF::Read | SF::Write,
+        }
+    }
+}
+
+/// A relocation, along with the index of the section it applies to.
+/// Its offset is relative to that section.
+#[derive(Debug, Clone)]
+pub struct SectionReloc {
+    pub section: usize,
+    pub rel: Reloc,
+}
+
+/// Reads all RELA sections that apply to allocated sections
+pub fn read_relocations<I: AsRef<[u8]>>(file: &delf::File<I>) -> Option<Vec<SectionReloc>> {
+    let input = file.input.as_ref();
+    let mut res = Vec::new();
+
+    for sh in &file.section_headers {
+        if !matches!(sh.r#type, delf::SectionType::Rela) {
+            continue;
+        }
+        let target = sh.info as usize;
+        match file.section_headers.get(target) {
+            Some(target_sh) if SectionKind::of(target_sh).is_some() => {}
+            // relocations for debug info and such, we don't need those
+            _ => continue,
+        }
+
+        let start: usize = sh.offset.into();
+        let data = input.get(start..start.checked_add(usize::from(sh.size))?)?;
+        res.extend(rela_entries(data).into_iter().map(|rel| SectionReloc {
+            section: target,
+            rel,
+        }));
+    }
+    Some(res)
+}
+
+/// Parses raw `Elf64_Rela` entries
+pub fn rela_entries(data: &[u8]) -> Vec<Reloc> {
+    let u64_at = |chunk: &[u8], i: usize| {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(&chunk[i..i + 8]);
+        u64::from_le_bytes(buf)
+    };
+
+    data.chunks_exact(24)
+        .map(|chunk| {
+            let info = u64_at(chunk, 8);
+            Reloc {
+                offset: delf::Addr(u64_at(chunk, 0)),
+                r#type: (info & 0xffff_ffff) as u32,
+                sym: (info >> 32) as u32,
+                addend: delf::Addr(u64_at(chunk, 16)),
+            }
+        })
+        .collect()
+}
+
+/// Where everything goes, relative to the start of the image
+#[derive(Debug, Default)]
+pub struct Layout {
+    /// Offset of each allocated section, by section index
+    pub sections: HashMap<usize, u64>,
+    /// Offset of each common symbol, by symbol index
+    pub commons: HashMap<usize, u64>,
+    /// Offset of the GOT slot for each symbol that needs one, by symbol index
+    pub got: HashMap<u32, u64>,
+    /// Offset of the PLT stub for each symbol that needs one, by symbol index
+    pub plt: HashMap<u32, u64>,
+    /// Page-aligned range of each segment
+    pub segments: Vec<(SectionKind, Range<u64>)>,
+}
+
+impl Layout {
+    pub fn size(&self) -> u64 {
+        self.segments
+            .iter()
+            .map(|(_, range)| range.end)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Where a symbol ended up, if it's defined in this object
+    pub fn sym_offset(&self, index: usize, sym: &delf::Sym) -> Option<u64> {
+        match sym.shndx.0 {
+            SHN_COMMON => self.commons.get(&index).copied(),
+            _ => sym
+                .shndx
+                .get()
+                .and_then(|shndx| self.sections.get(&shndx))
+                .map(|offset| offset + sym.value.0),
+        }
+    }
+}
+
+/// Decides where every allocated section, common symbol, GOT slot
+/// and PLT stub goes. `needs_plt` is asked about every symbol that's
+/// the target of a PLT-style relocation.
+pub fn layout<I: AsRef<[u8]>>(
+    arch: &dyn Arch,
+    file: &delf::File<I>,
+    syms: &[delf::Sym],
+    rels: &[SectionReloc],
+    needs_plt: impl Fn(&delf::Sym) -> bool,
+) -> Layout {
+    let mut layout = Layout::default();
+
+    let mut got_syms = Vec::new();
+    let mut plt_syms = Vec::new();
+    for SectionReloc { rel, .. } in rels {
+        let sym = match syms.get(rel.sym as usize) {
+            Some(sym) if rel.sym != 0 => sym,
+            _ => continue,
+        };
+        match arch.rel_kind(rel.r#type) {
+            Some(RelKind::GotPcRel) if !got_syms.contains(&rel.sym) => got_syms.push(rel.sym),
+            Some(RelKind::Plt32) if needs_plt(sym) && !plt_syms.contains(&rel.sym) => {
+                plt_syms.push(rel.sym);
+                // PLT stubs jump through the GOT
+                if !got_syms.contains(&rel.sym) {
+                    got_syms.push(rel.sym);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let align = |x: u64, a: u64| x.next_multiple_of(a.max(1));
+
+    let mut cursor = 0;
+    for kind in SectionKind::ALL {
+        let start = cursor;
+
+        // NOBITS sections go last, so the file-backed parts stay contiguous
+        let mut sections: Vec<_> = file
+            .section_headers
+            .iter()
+            .enumerate()
+            .filter(|(_, sh)| SectionKind::of(sh) == Some(kind))
+            .collect();
+        sections.sort_by_key(|(_, sh)| matches!(sh.r#type, delf::SectionType::NoBits));
+
+        for (index, sh) in sections {
+            cursor = align(cursor, sh.addralign.0);
+            layout.sections.insert(index, cursor);
+            cursor += sh.size.0;
+        }
+
+        match kind {
+            SectionKind::Text => {
+                for &sym in &plt_syms {
+                    cursor = align(cursor, PLT_STUB_SIZE);
+                    layout.plt.insert(sym, cursor);
+                    cursor += PLT_STUB_SIZE;
+                }
+            }
+            SectionKind::Data => {
+                for &sym in &got_syms {
+                    cursor = align(cursor, 8);
+                    layout.got.insert(sym, cursor);
+                    cursor += 8;
+                }
+                for (index, sym) in syms.iter().enumerate() {
+                    if sym.shndx.0 == SHN_COMMON {
+                        // for common symbols, `value` is the alignment
+                        cursor = align(cursor, sym.value.0);
+                        layout.commons.insert(index, cursor);
+                        cursor += sym.size;
+                    }
+                }
+            }
+            SectionKind::ReadOnly => {}
+        }
+
+        if cursor > start {
+            cursor = align(cursor, PAGE_SIZE);
+            layout.segments.push((kind, start..cursor));
+        }
+    }
+
+    layout
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch;
+
+    /// Lays out `samples/layout.o`. Its sections are .text (1), .data (3),
+    /// .bss (4) and .rodata (5), and the symbols it references are
+    /// external_var (6), external_fn (7), counter (8), common_var (9) and
+    /// message (10).
+    fn sample_layout(needs_plt: impl Fn(&delf::Sym) -> bool) -> (Layout, Vec<delf::Sym>) {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/samples/layout.o");
+        let file = delf::File::parse_or_print_error(std::fs::read(path).unwrap()).unwrap();
+        let syms = file.read_symtab_entries().unwrap();
+        let rels = read_relocations(&file).unwrap();
+        (layout(&arch::X86_64, &file, &syms, &rels, needs_plt), syms)
+    }
+
+    #[test]
+    fn segments_and_sections() {
+        let (layout, _) = sample_layout(|sym| sym.shndx.is_undef());
+        assert_eq!(
+            layout.segments,
+            vec![
+                (SectionKind::Text, 0..0x1000),
+                (SectionKind::ReadOnly, 0x1000..0x2000),
+                (SectionKind::Data, 0x2000..0x3000),
+            ]
+        );
+        assert_eq!(layout.size(), 0x3000);
+        // .comment and friends aren't allocated, .bss (32-byte aligned)
+        // comes after .data
+        assert_eq!(
+            layout.sections,
+            [(1, 0), (3, 0x2000), (4, 0x2020), (5, 0x1000)]
+                .into_iter()
+                .collect()
+        );
+    }
+
+    #[test]
+    fn got_plt_and_commons() {
+        let (layout, syms) = sample_layout(|sym| sym.shndx.is_undef());
+        // right after .text
+        assert_eq!(layout.plt, [(7, 0x50)].into_iter().collect());
+        // right after .bss, in the order relocations first mention them
+        assert_eq!(
+            layout.got,
+            [
+                (6, 0x20a0),
+                (7, 0x20a8),
+                (8, 0x20b0),
+                (9, 0x20b8),
+                (10, 0x20c0)
+            ]
+            .into_iter()
+            .collect()
+        );
+        // after the GOT, aligned to their `value`
+        assert_eq!(layout.commons, [(9, 0x20e0)].into_iter().collect());
+
+        assert_eq!(layout.sym_offset(8, &syms[8]), Some(0x2000));
+        assert_eq!(layout.sym_offset(9, &syms[9]), Some(0x20e0));
+        assert_eq!(layout.sym_offset(10, &syms[10]), Some(0x1000));
+        assert_eq!(layout.sym_offset(7, &syms[7]), None);
+    }
+
+    #[test]
+    fn no_plt() {
+        let (layout, _) = sample_layout(|_| false);
+        assert!(layout.plt.is_empty());
+        // PLT32 relocations don't need a GOT slot when they're direct
+        assert_eq!(
+            layout.got,
+            [(6, 0x20a0), (8, 0x20a8), (9, 0x20b0), (10, 0x20b8)]
+                .into_iter()
+                .collect()
+        );
+        assert_eq!(layout.segments[0], (SectionKind::Text, 0..0x1000));
+    }
+}
@@ -0,0 +1,412 @@
+//! ltrace-style call tracing: JUMP_SLOT bindings for matching symbols get
+//! pointed at a generated trampoline, which logs the call, calls the real
+//! target, then logs what it returned.
+//!
+//! The logging side runs in the middle of the *program*, on its threads,
+//! with its thread pointer: it can't allocate, and can't touch anything
+//! thread-local (so no `std::io`, no `println!`). It formats into stack
+//! buffers and writes to stderr with raw syscalls. Return addresses are
+//! kept in a fixed set of per-thread shadow stacks, keyed by the thread
+//! pointer.
+//!
+//! The return hook has no unwind info, so anything that leaves a traced
+//! call without returning from it (C++ exceptions, `longjmp`, thread
+//! exit) leaves a stale frame on that thread's shadow stack: the next
+//! traced return on that thread will go to the wrong place.
+
+use std::{
+    cell::{Cell, RefCell, UnsafeCell},
+    fmt::{self, Write},
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use custom_debug_derive::Debug as CustomDebug;
+use mmap::{MapOption, MemoryMap};
+
+const PAGE_SIZE: usize = 0x1000;
+/// Trampolines are 24 bytes, we give them a bit of room
+const TRAMPOLINE_SIZE: usize = 32;
+
+/// Threads we can trace returns for at once. A thread holds on to a
+/// stack while it's inside a traced call, past this, calls still get
+/// logged, but not their return values.
+const MAX_THREADS: usize = 64;
+/// How deep traced calls can nest, per thread
+const MAX_DEPTH: usize = 256;
+
+/// Shell-style glob matching: `*` matches any run of characters, `?`
+/// matches exactly one.
+pub fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // where to resume after the last `*`, if the rest doesn't match
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// What a trampoline knows about the binding it stands in for
+#[derive(Debug)]
+struct Binding {
+    target: u64,
+    name: String,
+    caller: String,
+}
+
+#[derive(CustomDebug)]
+pub struct Tracer {
+    pattern: String,
+    // trampolines never move, so neither can these
+    #[debug(skip)]
+    pages: RefCell<Vec<MemoryMap>>,
+    // bytes used in the last page
+    used: Cell<usize>,
+}
+
+impl Tracer {
+    pub fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.into(),
+            pages: Default::default(),
+            used: Cell::new(0),
+        }
+    }
+
+    pub fn matches(&self, name: &[u8]) -> bool {
+        glob_match(self.pattern.as_bytes(), name)
+    }
+
+    /// Generates a trampoline for a call to `name` (at `target`) from
+    /// `caller`, and returns its address.
+    pub fn trampoline(
+        &self,
+        name: &[u8],
+        caller: &Path,
+        target: delf::Addr,
+    ) -> Result<delf::Addr, mmap::MapError> {
+        // trampolines hold on to this for the rest of the program's life
+        let binding: &'static Binding = Box::leak(Box::new(Binding {
+            target: target.0,
+            name: String::from_utf8_lossy(name).into_owned(),
+            caller: caller
+                .file_name()
+                .unwrap_or(caller.as_os_str())
+                .to_string_lossy()
+                .into_owned(),
+        }));
+
+        let mut pages = self.pages.borrow_mut();
+        if pages.is_empty() || self.used.get() + TRAMPOLINE_SIZE > PAGE_SIZE {
+            pages.push(MemoryMap::new(
+                PAGE_SIZE,
+                &[MapOption::MapReadable, MapOption::MapWritable],
+            )?);
+            self.used.set(0);
+        }
+        let page = pages.last().expect("we just made sure there's a page");
+        let addr = delf::Addr(page.data() as u64 + self.used.get() as u64);
+        self.used.set(self.used.get() + TRAMPOLINE_SIZE);
+
+        extern "C" {
+            fn elk_trace_entry();
+        }
+        let mut code = Vec::with_capacity(TRAMPOLINE_SIZE);
+        // mov r11, binding
+        code.extend(&[0x49, 0xbb]);
+        code.extend(&(binding as *const Binding as u64).to_le_bytes());
+        // jmp [rip+0], followed by the address to jump to
+        code.extend(&[0xff, 0x25, 0x00, 0x00, 0x00, 0x00]);
+        code.extend(&(elk_trace_entry as *const () as u64).to_le_bytes());
+        unsafe { addr.write(&code) };
+
+        Ok(addr)
+    }
+
+    /// Makes trampolines executable (and read-only), once they're all
+    /// generated.
+    pub fn protect(&self) -> Result<(), region::Error> {
+        for page in self.pages.borrow().iter() {
+            unsafe {
+                region::protect(page.data(), PAGE_SIZE, region::Protection::READ_EXECUTE)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Frame {
+    ret: u64,
+    binding: *const Binding,
+}
+
+struct ShadowStack {
+    /// Thread pointer of the thread that owns this stack, 0 if free.
+    /// Threads own a stack for as long as they're inside a traced call.
+    owner: AtomicU64,
+    // only ever touched by the owning thread
+    depth: UnsafeCell<usize>,
+    frames: UnsafeCell<[Frame; MAX_DEPTH]>,
+}
+
+// each stack is only ever used by the thread that claimed it
+unsafe impl Sync for ShadowStack {}
+
+impl ShadowStack {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const NEW: Self = Self {
+        owner: AtomicU64::new(0),
+        depth: UnsafeCell::new(0),
+        frames: UnsafeCell::new(
+            [Frame {
+                ret: 0,
+                binding: std::ptr::null(),
+            }; MAX_DEPTH],
+        ),
+    };
+
+    /// The current thread's stack, if it has (or can claim) one
+    fn current() -> Option<&'static Self> {
+        let tp = thread_pointer();
+        if let Some(stack) = STACKS
+            .iter()
+            .find(|stack| stack.owner.load(Ordering::Acquire) == tp)
+        {
+            return Some(stack);
+        }
+        STACKS.iter().find(|stack| {
+            stack
+                .owner
+                .compare_exchange(0, tp, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+        })
+    }
+}
+
+static STACKS: [ShadowStack; MAX_THREADS] = [ShadowStack::NEW; MAX_THREADS];
+
+/// What `trace_enter` hands back to the assembly: where to jump, and
+/// whether to hook the return.
+#[repr(C)]
+struct Entered {
+    target: u64,
+    hook_return: u64,
+}
+
+/// Called by `elk_trace_entry`, with the integer argument registers
+/// saved at `regs` (r9, r8, rcx, rdx, rsi, rdi - in that order).
+unsafe extern "C" fn trace_enter(binding: *const Binding, regs: *const u64, ret: u64) -> Entered {
+    let binding = &*binding;
+    let regs = std::slice::from_raw_parts(regs, 6);
+    let args = [regs[5], regs[4], regs[3], regs[2], regs[1], regs[0]];
+
+    let stack = ShadowStack::current();
+    let depth = stack.map_or(0, |stack| *stack.depth.get());
+
+    let mut line = LineBuf::new();
+    indent(&mut line, depth);
+    let _ = write!(
+        line,
+        "[elk] {} -> {}({:#x}, {:#x}, {:#x}, {:#x}, {:#x}, {:#x})",
+        binding.caller, binding.name, args[0], args[1], args[2], args[3], args[4], args[5]
+    );
+
+    let hook_return = match stack {
+        Some(stack) if depth < MAX_DEPTH => {
+            (*stack.frames.get())[depth] = Frame { ret, binding };
+            *stack.depth.get() = depth + 1;
+            true
+        }
+        _ => {
+            let _ = write!(line, " <return not traced>");
+            false
+        }
+    };
+    line.flush();
+
+    Entered {
+        target: binding.target,
+        hook_return: hook_return as u64,
+    }
+}
+
+/// Called by `elk_trace_return`, returns the address to return to
+unsafe extern "C" fn trace_leave(value: u64) -> u64 {
+    // we only hook returns for threads that have a stack, so this
+    // always finds one.
+    let stack = match ShadowStack::current() {
+        Some(stack) => stack,
+        None => std::process::abort(),
+    };
+    let depth = *stack.depth.get() - 1;
+    *stack.depth.get() = depth;
+    let frame = (*stack.frames.get())[depth];
+    if depth == 0 {
+        // back out of every traced call, let another thread have it
+        stack.owner.store(0, Ordering::Release);
+    }
+
+    let mut line = LineBuf::new();
+    indent(&mut line, depth);
+    let _ = write!(line, "[elk] {} = {:#x}", (*frame.binding).name, value);
+    line.flush();
+
+    frame.ret
+}
+
+fn indent(line: &mut LineBuf, depth: usize) {
+    for _ in 0..depth {
+        let _ = line.write_str("  ");
+    }
+}
+
+/// The program's thread pointer, which glibc (and our own TCB) stores
+/// at `fs:0`.
+fn thread_pointer() -> u64 {
+    let tp: u64;
+    unsafe {
+        std::arch::asm!("mov {}, qword ptr fs:[0]", out(reg) tp, options(nostack, readonly));
+    }
+    tp
+}
+
+/// A line of output, formatted on the stack. Anything past its capacity
+/// is dropped.
+struct LineBuf {
+    data: [u8; 512],
+    len: usize,
+}
+
+impl LineBuf {
+    fn new() -> Self {
+        Self {
+            data: [0; 512],
+            len: 0,
+        }
+    }
+
+    /// Writes the line (and a newline) to stderr
+    fn flush(mut self) {
+        let _ = self.write_str("\n");
+        if self.len == self.data.len() {
+            self.data[self.len - 1] = b'\n';
+        }
+        unsafe {
+            std::arch::asm!(
+                "syscall",
+                inout("rax") 1u64 => _,
+                in("rdi") 2u64,
+                in("rsi") self.data.as_ptr(),
+                in("rdx") self.len,
+                lateout("rcx") _, lateout("r11") _,
+                options(nostack),
+            );
+        }
+    }
+}
+
+impl fmt::Write for LineBuf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let n = s.len().min(self.data.len() - self.len);
+        self.data[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+// Trampolines jump here with their `Binding` in r11, and the stack just
+// as the caller left it: return address on top, rsp ≡ 8 (mod 16).
+//
+// We save every argument register (integer and SSE, plus rax for
+// varargs), let `trace_enter` log the call, restore them, then swap the
+// return address for `elk_trace_return` and jump to the real target.
+// Stack arguments stay exactly where the target expects them.
+std::arch::global_asm!(
+    ".globl elk_trace_entry",
+    "elk_trace_entry:",
+    "push rax",
+    "push rdi",
+    "push rsi",
+    "push rdx",
+    "push rcx",
+    "push r8",
+    "push r9",
+    "sub rsp, 128",
+    "movdqu [rsp], xmm0",
+    "movdqu [rsp+16], xmm1",
+    "movdqu [rsp+32], xmm2",
+    "movdqu [rsp+48], xmm3",
+    "movdqu [rsp+64], xmm4",
+    "movdqu [rsp+80], xmm5",
+    "movdqu [rsp+96], xmm6",
+    "movdqu [rsp+112], xmm7",
+    "mov rdi, r11",
+    "lea rsi, [rsp+128]",
+    "mov rdx, [rsp+184]",
+    "call {enter}",
+    "mov r11, rax",
+    "movdqu xmm0, [rsp]",
+    "movdqu xmm1, [rsp+16]",
+    "movdqu xmm2, [rsp+32]",
+    "movdqu xmm3, [rsp+48]",
+    "movdqu xmm4, [rsp+64]",
+    "movdqu xmm5, [rsp+80]",
+    "movdqu xmm6, [rsp+96]",
+    "movdqu xmm7, [rsp+112]",
+    "add rsp, 128",
+    "test rdx, rdx",
+    "pop r9",
+    "pop r8",
+    "pop rcx",
+    "pop rdx",
+    "pop rsi",
+    "pop rdi",
+    "pop rax",
+    "jz 2f",
+    "lea r10, [rip + elk_trace_return]",
+    "mov [rsp], r10",
+    "2:",
+    "jmp r11",
+    "",
+    // The target returns here, with rsp ≡ 0 (mod 16). We make room for
+    // the real return address, save the return registers, and let
+    // `trace_leave` log the value and tell us where to go.
+    ".globl elk_trace_return",
+    "elk_trace_return:",
+    "push rax",
+    "push rax",
+    "push rdx",
+    "sub rsp, 40",
+    "movdqu [rsp], xmm0",
+    "movdqu [rsp+16], xmm1",
+    "mov rdi, rax",
+    "call {leave}",
+    "mov [rsp+56], rax",
+    "movdqu xmm0, [rsp]",
+    "movdqu xmm1, [rsp+16]",
+    "add rsp, 40",
+    "pop rdx",
+    "pop rax",
+    "ret",
+    enter = sym trace_enter,
+    leave = sym trace_leave,
+);
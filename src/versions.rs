@@ -0,0 +1,238 @@
+//! GNU symbol versioning: `.gnu.version` says which version each dynamic
+//! symbol has, `.gnu.version_d` lists the versions an object defines, and
+//! `.gnu.version_r` lists the versions it needs from its dependencies.
+//!
+//! We find those sections by name, and do the parsing ourselves.
+
+use std::collections::HashMap;
+
+/// Version indices 0 and 1 mean "local" and "global" (unversioned)
+const VER_NDX_GLOBAL: u16 = 1;
+/// Set on symbols that aren't the default version of their name
+const VERSYM_HIDDEN: u16 = 0x8000;
+
+#[derive(Debug, Clone, Default)]
+pub struct Versions {
+    /// Version index of every dynamic symbol, hidden bit included
+    symbols: Vec<u16>,
+    /// Versions this object defines, by version index
+    pub defined: HashMap<u16, String>,
+    /// Versions this object needs, by version index: the file name (as in
+    /// DT_NEEDED) they're expected from, and the version name.
+    pub needed: HashMap<u16, (String, String)>,
+}
+
+impl Versions {
+    /// Returns `None` if the object doesn't use symbol versioning.
+    pub fn parse<I: AsRef<[u8]>>(file: &delf::File<I>) -> Option<Self> {
+        let input = file.input.as_ref();
+        let section = |name: &[u8]| {
+            file.section_headers
+                .iter()
+                .find(|sh| file.shstrtab_entry(sh.name) == name)
+        };
+        let contents = |sh: &delf::SectionHeader| {
+            let start: usize = sh.offset.into();
+            input.get(start..start.checked_add(usize::from(sh.size))?)
+        };
+        // both version_d and version_r point at their string table
+        // through `link`
+        let strings = |sh: &delf::SectionHeader| {
+            file.section_headers
+                .get(sh.link as usize)
+                .and_then(&contents)
+        };
+
+        let mut versions = Self::default();
+
+        let versym = section(b".gnu.version")?;
+        versions.symbols = contents(versym)?
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+
+        if let Some(verdef) = section(b".gnu.version_d") {
+            let (data, strtab) = (contents(verdef)?, strings(verdef)?);
+            let mut offset = 0;
+            for _ in 0..verdef.info {
+                // Elf_Verdef: version, flags, ndx, cnt, hash, aux, next
+                let ndx = read_u16(data, offset + 4)?;
+                let aux = read_u32(data, offset + 12)? as usize;
+                let next = read_u32(data, offset + 16)? as usize;
+                // the first Elf_Verdaux is the version's own name, the
+                // others are its parents.
+                let name = read_u32(data, offset.checked_add(aux)?)? as usize;
+                versions.defined.insert(ndx, cstr(strtab, name)?);
+                if next == 0 {
+                    break;
+                }
+                offset = next_offset(data, offset, next)?;
+            }
+        }
+
+        if let Some(verneed) = section(b".gnu.version_r") {
+            let (data, strtab) = (contents(verneed)?, strings(verneed)?);
+            let mut offset = 0;
+            for _ in 0..verneed.info {
+                // Elf_Verneed: version, cnt, file, aux, next
+                let cnt = read_u16(data, offset + 2)?;
+                let file_name = cstr(strtab, read_u32(data, offset + 4)? as usize)?;
+                let mut aux = next_offset(data, offset, read_u32(data, offset + 8)? as usize)?;
+                for _ in 0..cnt {
+                    // Elf_Vernaux: hash, flags, other, name, next
+                    let other = read_u16(data, aux + 6)?;
+                    let name = cstr(strtab, read_u32(data, aux + 8)? as usize)?;
+                    versions.needed.insert(other, (file_name.clone(), name));
+                    match read_u32(data, aux + 12)? {
+                        0 => break,
+                        next => aux = next_offset(data, aux, next as usize)?,
+                    }
+                }
+                match read_u32(data, offset + 12)? {
+                    0 => break,
+                    next => offset = next_offset(data, offset, next as usize)?,
+                }
+            }
+        }
+
+        Some(versions)
+    }
+
+    /// The version of the dynamic symbol at `index`, if it has one. For
+    /// defined symbols, that's the version they define, for undefined
+    /// symbols, the version they need.
+    pub fn symbol_version(&self, index: usize) -> Option<&str> {
+        let ndx = self.symbols.get(index)? & !VERSYM_HIDDEN;
+        if ndx <= VER_NDX_GLOBAL {
+            return None;
+        }
+        self.defined
+            .get(&ndx)
+            .or_else(|| self.needed.get(&ndx).map(|(_, name)| name))
+            .map(|name| name.as_str())
+    }
+
+    /// Whether the symbol at `index` is a non-default version, ie. it's
+    /// `foo@VER` rather than `foo@@VER`.
+    pub fn is_hidden(&self, index: usize) -> bool {
+        self.symbols
+            .get(index)
+            .is_some_and(|ndx| ndx & VERSYM_HIDDEN != 0)
+    }
+}
+
+/// Follows a relative link between entries. The result is always inside
+/// `data`, so reading fields at small offsets from it can't overflow.
+fn next_offset(data: &[u8], offset: usize, delta: usize) -> Option<usize> {
+    offset.checked_add(delta).filter(|&next| next < data.len())
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let b = data.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let b = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn cstr(strtab: &[u8], offset: usize) -> Option<String> {
+    let s = strtab.get(offset..)?;
+    let end = s.iter().position(|&b| b == 0)?;
+    Some(String::from_utf8_lossy(&s[..end]).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `samples/libversioned.so.1`: its dynamic symbols are puts (1), bar
+    /// (2), foo@VERS_1 (3) and foo@@VERS_2 (6).
+    fn sample() -> Vec<u8> {
+        std::fs::read(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/samples/libversioned.so.1"
+        ))
+        .unwrap()
+    }
+
+    fn parse(input: &[u8]) -> Option<Versions> {
+        Versions::parse(&delf::File::parse_or_print_error(input).unwrap())
+    }
+
+    /// Where the section named `name` starts in `input`
+    fn section_offset(input: &[u8], name: &[u8]) -> usize {
+        let file = delf::File::parse_or_print_error(input).unwrap();
+        let sh = file
+            .section_headers
+            .iter()
+            .find(|sh| file.shstrtab_entry(sh.name) == name)
+            .unwrap();
+        sh.offset.into()
+    }
+
+    #[test]
+    fn definitions_and_needs() {
+        let versions = parse(&sample()).unwrap();
+        let defined: HashMap<u16, String> = [
+            (1, "libversioned.so.1".to_string()),
+            (2, "VERS_1".to_string()),
+            (3, "VERS_2".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(versions.defined, defined);
+        let needed: HashMap<u16, (String, String)> =
+            [(4, ("libc.so.6".to_string(), "GLIBC_2.2.5".to_string()))]
+                .into_iter()
+                .collect();
+        assert_eq!(versions.needed, needed);
+    }
+
+    #[test]
+    fn symbol_versions() {
+        let versions = parse(&sample()).unwrap();
+        // local and global (unversioned) symbols
+        assert_eq!(versions.symbol_version(0), None);
+        assert_eq!(versions.symbol_version(2), None);
+        // needed, defined, then out of range
+        assert_eq!(versions.symbol_version(1), Some("GLIBC_2.2.5"));
+        assert_eq!(versions.symbol_version(3), Some("VERS_1"));
+        assert_eq!(versions.symbol_version(6), Some("VERS_2"));
+        assert_eq!(versions.symbol_version(100), None);
+
+        assert!(versions.is_hidden(3));
+        assert!(!versions.is_hidden(6));
+        assert!(!versions.is_hidden(1));
+    }
+
+    #[test]
+    fn unversioned() {
+        let input =
+            std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/samples/hello-nolibc")).unwrap();
+        assert!(parse(&input).is_none());
+    }
+
+    #[test]
+    fn links_out_of_bounds() {
+        let far = 0xffff_fff0_u32.to_le_bytes();
+
+        // vd_next of the first Elf_Verdef
+        let mut input = sample();
+        let verdef = section_offset(&input, b".gnu.version_d");
+        input[verdef + 16..verdef + 20].copy_from_slice(&far);
+        assert!(parse(&input).is_none());
+
+        // vd_aux of the first Elf_Verdef
+        let mut input = sample();
+        input[verdef + 12..verdef + 16].copy_from_slice(&far);
+        assert!(parse(&input).is_none());
+
+        // vn_aux of the first Elf_Verneed
+        let mut input = sample();
+        let verneed = section_offset(&input, b".gnu.version_r");
+        input[verneed + 8..verneed + 12].copy_from_slice(&far);
+        assert!(parse(&input).is_none());
+    }
+}